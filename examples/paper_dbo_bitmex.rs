@@ -0,0 +1,30 @@
+extern crate market_maker;
+
+use rust_decimal_macros::dec;
+
+use market_maker::bot::{Bot, Config};
+use market_maker::implements::exchanges::bitmex::{BitMEXEnvironment, BitMEXMarket};
+use market_maker::implements::exchanges::paper::PaperBroker;
+use market_maker::logger;
+use market_maker::strategies::dbo::DepthBasedOffering;
+
+fn main() -> anyhow::Result<()> {
+    logger::setup_with("info");
+
+    // exchange, no apikey needed
+    let market = BitMEXMarket::connect(&BitMEXEnvironment::Testnet, "XBTUSD")?;
+    let broker = PaperBroker::connect(&market);
+    let status = broker.status();
+
+    // strategy
+    let policy = DepthBasedOffering::new(dec!(200), dec!(1000));
+
+    // bot
+    let config = Config {
+        num_iteration: usize::MAX,
+        test: false,
+        max_position: Some(dec!(1000)),
+    };
+    let mut bot = Bot::new(config, market, status, broker, policy);
+    bot.run()
+}
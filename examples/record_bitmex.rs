@@ -0,0 +1,20 @@
+extern crate market_maker;
+
+use std::thread;
+
+use market_maker::implements::exchanges::bitmex::{BitMEXEnvironment, BitMEXRecorder};
+use market_maker::implements::records::{RecordWriter, Rotation};
+use market_maker::logger;
+
+fn main() {
+    logger::setup_with("info");
+
+    // one file per hour
+    let writer = RecordWriter::create("./records", "xbtusd", Rotation::Interval(3_600_000))
+        .expect("failed to create record writer");
+    let _recorder = BitMEXRecorder::start(&BitMEXEnvironment::Testnet, "XBTUSD", writer);
+
+    loop {
+        thread::park();
+    }
+}
@@ -4,18 +4,28 @@ use rust_decimal_macros::dec;
 
 use market_maker::apikey::ApiKey;
 use market_maker::bot::{Bot, Config};
-use market_maker::implements::exchanges::bitmex::{BitMEXBroker, BitMEXMarket, BitMEXStatus};
+use market_maker::implements::exchanges::bitmex::{
+    BitMEXBroker, BitMEXEnvironment, BitMEXMarket, BitMEXStatus, DeadMansSwitchConfig,
+};
 use market_maker::logger;
 use market_maker::strategies::dbo::DepthBasedOffering;
 
-fn main() {
+const SYMBOL: &str = "XBTUSD";
+
+fn main() -> anyhow::Result<()> {
     logger::setup_with("info");
 
     // exchange
-    let apikey = ApiKey::read_json("./keys/bitmex.json").expect("apikey not found");
-    let market = BitMEXMarket::connect();
-    let status = BitMEXStatus::connect(&apikey);
-    let broker = BitMEXBroker::connect(&apikey);
+    let apikey = ApiKey::read_json("./keys/bitmex.json")?;
+    let environment = BitMEXEnvironment::Testnet;
+    let market = BitMEXMarket::connect(&environment, SYMBOL)?;
+    let status = BitMEXStatus::connect(&environment, &apikey, SYMBOL)?;
+    let broker = BitMEXBroker::connect_with_dead_mans_switch(
+        &environment,
+        &apikey,
+        SYMBOL,
+        DeadMansSwitchConfig::default(),
+    )?;
 
     // strategy
     let policy = DepthBasedOffering::new(dec!(200), dec!(1000));
@@ -24,7 +34,8 @@ fn main() {
     let config = Config {
         num_iteration: usize::MAX,
         test: true,
+        max_position: Some(dec!(1000)),
     };
     let mut bot = Bot::new(config, market, status, broker, policy);
-    bot.run().unwrap();
+    bot.run()
 }
@@ -0,0 +1,55 @@
+extern crate market_maker;
+
+use rust_decimal_macros::dec;
+
+use market_maker::backtest::{
+    write_sweep_csv, write_sweep_json, BacktestConfig, DboParams, SweepRunner,
+};
+use market_maker::implements::records::RecordReader;
+use market_maker::logger;
+use market_maker::types::MarketInfo;
+
+fn main() {
+    logger::setup_with("info");
+
+    let records: Vec<_> = RecordReader::open_dir("./records", "xbtusd")
+        .and_then(|reader| reader.collect())
+        .expect("failed to read records");
+
+    // fees are rated on the notional in satoshis, so PnL is reported in XBt
+    let config = BacktestConfig {
+        entry_latency: 50,
+        cancel_latency: 50,
+        maker_fee: dec!(-0.00025),
+        taker_fee: dec!(0.00075),
+    };
+    let info = MarketInfo {
+        max_order_size: dec!(10000000),
+        min_order_size: dec!(100),
+        lot_size: dec!(100),
+        max_order_price: dec!(1000000),
+        min_order_price: dec!(1),
+        tick_size: dec!(0.5),
+        multiplier: dec!(-100000000),
+        settlement_currency: "XBt".to_string(),
+    };
+
+    let grid = DboParams::grid(
+        &[dec!(1000), dec!(5000), dec!(10000)],
+        &[dec!(10000), dec!(50000), dec!(100000), dec!(500000)],
+    );
+    let rows = SweepRunner::new(config, info, records).run(grid);
+
+    write_sweep_csv("./sweep.csv", &rows).expect("failed to write csv");
+    write_sweep_json("./sweep.json", &rows).expect("failed to write json");
+    for row in rows.iter().take(5) {
+        println!(
+            "#{} max_exposure={} target_depth={} net_pnl={} XBt max_drawdown={} XBt",
+            row.rank,
+            row.params.max_exposure,
+            row.params.target_depth,
+            row.net_pnl,
+            row.max_drawdown
+        );
+    }
+}
@@ -3,15 +3,27 @@ use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::path::Path;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ApiKey {
     key: String,
     secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
 }
 
 impl ApiKey {
     pub fn new(key: String, secret: String) -> Self {
-        Self { key, secret }
+        Self {
+            key,
+            secret,
+            environment: None,
+        }
+    }
+
+    /// Tags the key with the deployment it was issued for, e.g. `mainnet`.
+    pub fn with_environment(mut self, environment: &str) -> Self {
+        self.environment = Some(environment.to_string());
+        self
     }
 
     pub fn key(&self) -> &str {
@@ -22,6 +34,10 @@ impl ApiKey {
         &self.secret
     }
 
+    pub fn environment(&self) -> Option<&str> {
+        self.environment.as_deref()
+    }
+
     pub fn read_json<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
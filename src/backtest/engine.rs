@@ -0,0 +1,670 @@
+use log::*;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::fill_model::{take_liquidity, QueueFillModel};
+use crate::implements::records::{Record, RecordEvent};
+use crate::implements::simulation::{FixedLatency, NoImpact};
+use crate::implements::writers::{OrderbookWriteOp, OrderbookWriter};
+use crate::interfaces::{ImpactModel, LatencyModel, Policy};
+use crate::observation::Observation;
+use crate::types::{
+    Amount, Execution, Fill, Inventory, Liquidity, MarketInfo, NewOrder, OfferId, OpenOrders,
+    Order, OrderId, OrderState, OrderType, Orderbook, Price, Side, UpdateOrder,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BacktestConfig {
+    pub entry_latency: u64,  // ms
+    pub cancel_latency: u64, // ms
+    pub maker_fee: Decimal,  // rate of the notional, negative on rebates
+    pub taker_fee: Decimal,  // rate of the notional
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BacktestResult {
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub submitted_volume: Amount,
+    pub fills: Vec<Fill>,
+    pub marks: Vec<Mark>,
+    pub quotes: Vec<Quote>,
+    pub inventory: Vec<InventoryPoint>,
+    pub position: Amount,
+    pub open_orders: OpenOrders,
+    pub info: MarketInfo,
+}
+
+/// Position published to the policy, from the start and after every fill.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryPoint {
+    pub timestamp: u64,
+    pub position: Amount,
+}
+
+/// Mid price of the recorded orderbook.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mark {
+    pub timestamp: u64,
+    pub price: Price,
+}
+
+/// Our best resting prices right after an evaluation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Quote {
+    pub timestamp: u64,
+    pub ask: Option<Price>,
+    pub bid: Option<Price>,
+}
+
+impl Quote {
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.ask? - self.bid?)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct InFlight {
+    arrives_at: u64,
+    order: Order,
+}
+
+/// Runs a `Policy` over recorded market data, simulating fills of our orders.
+///
+/// The policy is evaluated on every orderbook update, as in `Bot::run`. Submitted orders stay
+/// in `pending_orders` until they reach the exchange after the configured latency, where new
+/// orders either take liquidity from the book or rest in a `QueueFillModel`.
+///
+/// Latency defaults to `FixedLatency` from the config, and the recorded book does not react to
+/// our marketable orders unless an `ImpactModel` says so.
+pub struct Backtest<P> {
+    config: BacktestConfig,
+    info: MarketInfo,
+    policy: P,
+    nonce: u64,
+    started_at: Option<u64>,
+    timestamp: u64,
+    orderbook: Option<Orderbook>,
+    executions: Vec<Execution>,
+    observation: Option<Observation>,
+    in_flight: Vec<InFlight>,
+    latency: Box<dyn LatencyModel>,
+    impact: Box<dyn ImpactModel>,
+    model: QueueFillModel,
+    submitted_volume: Amount,
+    position: Amount,
+    fills: Vec<Fill>,
+    marks: Vec<Mark>,
+    quotes: Vec<Quote>,
+    inventory: Vec<InventoryPoint>,
+}
+
+impl<P> Backtest<P>
+where
+    P: Policy,
+{
+    pub fn new(config: BacktestConfig, info: MarketInfo, policy: P) -> Self {
+        let latency = FixedLatency::new(config.entry_latency, config.cancel_latency);
+        Self {
+            config,
+            info,
+            policy,
+            nonce: 0,
+            started_at: None,
+            timestamp: 0,
+            orderbook: None,
+            executions: Vec::new(),
+            observation: None,
+            in_flight: Vec::new(),
+            latency: Box::new(latency),
+            impact: Box::new(NoImpact),
+            model: QueueFillModel::new(),
+            submitted_volume: Amount::zero(),
+            position: Amount::zero(),
+            fills: Vec::new(),
+            marks: Vec::new(),
+            quotes: Vec::new(),
+            inventory: Vec::new(),
+        }
+    }
+
+    pub fn with_latency_model(mut self, latency: impl LatencyModel + 'static) -> Self {
+        self.latency = Box::new(latency);
+        self
+    }
+
+    pub fn with_impact_model(mut self, impact: impl ImpactModel + 'static) -> Self {
+        self.impact = Box::new(impact);
+        self
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    pub fn position(&self) -> Amount {
+        self.position
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub fn open_orders(&self) -> OpenOrders {
+        self.model.open_orders(self.timestamp)
+    }
+
+    pub fn pending_orders(&self) -> Vec<Order> {
+        self.in_flight.iter().map(|f| f.order.clone()).collect()
+    }
+
+    pub fn run<'a, I>(mut self, records: I) -> BacktestResult
+    where
+        I: IntoIterator<Item = &'a Record>,
+    {
+        for record in records {
+            self.step(record);
+        }
+
+        BacktestResult {
+            started_at: self.started_at.unwrap_or(self.timestamp),
+            finished_at: self.timestamp,
+            open_orders: self.open_orders(),
+            submitted_volume: self.submitted_volume,
+            fills: self.fills,
+            marks: self.marks,
+            quotes: self.quotes,
+            inventory: self.inventory,
+            position: self.position,
+            info: self.info,
+        }
+    }
+
+    pub fn step(&mut self, record: &Record) {
+        if self.started_at.is_none() {
+            self.started_at = Some(record.timestamp());
+            self.record_inventory(record.timestamp());
+        }
+        self.deliver(record.timestamp());
+        self.timestamp = self.timestamp.max(record.timestamp());
+
+        match record.event() {
+            RecordEvent::Orderbook(ops) => {
+                for op in ops.iter().cloned() {
+                    match op {
+                        OrderbookWriteOp::Snapshot(snapshot) => self.orderbook = Some(snapshot),
+                        op => {
+                            if let Some(orderbook) = self.orderbook.as_mut() {
+                                if let Err(e) = OrderbookWriter::new(orderbook).apply(op) {
+                                    error!("{:?}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if let Some(orderbook) = &self.orderbook {
+                    if let Some(price) = orderbook.mid_price() {
+                        self.marks.push(Mark {
+                            timestamp: self.timestamp,
+                            price,
+                        });
+                    }
+
+                    let fills = self.model.on_orderbook(orderbook);
+                    self.settle_maker_fills(fills);
+                    self.evaluate();
+                }
+            }
+            RecordEvent::Execution(executions) => {
+                for execution in executions {
+                    let fills = self.model.on_execution(execution);
+                    self.settle_maker_fills(fills);
+
+                    match self.observation.as_mut() {
+                        Some(observation) => observation.insert_execution(execution.clone()),
+                        None => self.executions.push(execution.clone()),
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(&mut self) {
+        let orderbook = self.observed_orderbook();
+        let inventory = self.current_inventory();
+        let open_orders = self.open_orders();
+        let pending_orders = self.pending_orders();
+
+        let observation = match self.observation.as_mut() {
+            Some(observation) => {
+                observation.update_orderbook(orderbook);
+                observation.update_inventory(inventory);
+                observation.update_open_orders(open_orders);
+                observation.update_pending_orders(pending_orders);
+                observation
+            }
+            None => self.observation.insert(Observation::new(
+                self.info.clone(),
+                std::mem::take(&mut self.executions),
+                orderbook,
+                inventory,
+                open_orders,
+                pending_orders,
+            )),
+        };
+
+        let orders = self.policy.evaluate(&*observation);
+        for order in orders {
+            let latency = self.latency.latency(self.timestamp, &order);
+            self.in_flight.push(InFlight {
+                arrives_at: self.timestamp + latency,
+                order,
+            });
+        }
+
+        // arrivals without latency
+        self.deliver(self.timestamp);
+
+        let open_orders = self.open_orders();
+        self.quotes.push(Quote {
+            timestamp: self.timestamp,
+            ask: open_orders.asks().map(|o| o.price()).min(),
+            bid: open_orders.bids().map(|o| o.price()).max(),
+        });
+    }
+
+    /// Delivers in-flight orders which have reached the exchange by `timestamp`.
+    fn deliver(&mut self, timestamp: u64) {
+        self.in_flight.sort_by_key(|f| f.arrives_at);
+
+        while self
+            .in_flight
+            .first()
+            .map_or(false, |f| f.arrives_at <= timestamp)
+        {
+            let InFlight { arrives_at, order } = self.in_flight.remove(0);
+            self.timestamp = self.timestamp.max(arrives_at);
+
+            match order {
+                Order::New(new_order) => self.execute_new_order(new_order),
+                Order::Update(update_order) => self.execute_update_order(update_order),
+                Order::Cancel(cancel_order) => {
+                    if self.model.remove(cancel_order.id()).is_none() {
+                        debug!("cancel: order not found: {}", cancel_order.id());
+                    }
+                }
+            }
+        }
+    }
+
+    fn execute_new_order(&mut self, order: NewOrder) {
+        if self.orderbook.is_none() || order.amount() <= Amount::zero() {
+            return;
+        }
+
+        let id = OrderId::new(self.nonce);
+        self.nonce += 1;
+        self.submitted_volume += order.amount();
+        self.place_order(id, order);
+    }
+
+    // amends keep their queue position only when reduced at the same price
+    fn execute_update_order(&mut self, order: UpdateOrder) {
+        let current = match self.model.get(order.id()) {
+            Some(queued) => queued.order().clone(),
+            None => {
+                debug!("amend: order not found: {}", order.id());
+                return;
+            }
+        };
+
+        if let Err(reason) = order.check(&current) {
+            debug!("amend: {}: {:?}", reason, order);
+            return;
+        }
+
+        let new_order = order.new_order();
+
+        if order.keeps_priority(&current) {
+            self.model.reduce(order.id(), new_order.amount());
+        } else {
+            self.model.remove(order.id());
+            self.place_order(current.id().clone(), new_order.clone());
+        }
+    }
+
+    fn place_order(&mut self, id: OrderId, order: NewOrder) {
+        let orderbook = match &self.orderbook {
+            Some(orderbook) => orderbook,
+            None => return,
+        };
+
+        // take liquidity, the book only reacts through the impact model
+        let side = order.order_side();
+        let taken = take_liquidity(orderbook, &order);
+        let mut remaining = order.amount();
+        let mut fills = Vec::new();
+        for (offer, amount) in &taken {
+            remaining -= amount;
+            fills.push(
+                Fill::new(
+                    self.timestamp,
+                    id.clone(),
+                    side,
+                    offer.price(),
+                    *amount,
+                    self.config.taker_fee * self.info.notional(offer.price(), *amount),
+                    Liquidity::Taker,
+                )
+                .with_client_id(order.client_id().cloned()),
+            );
+        }
+
+        if order.order_type() == OrderType::Limit && !remaining.is_zero() {
+            let state = OrderState::new(id, side, order.price(), remaining)
+                .with_client_id(order.client_id().cloned());
+            self.model.insert(orderbook, state);
+        }
+
+        if !taken.is_empty() {
+            let ops = self.impact.impact(orderbook, side, &taken);
+            if let Some(orderbook) = self.orderbook.as_mut() {
+                let mut writer = OrderbookWriter::new(orderbook);
+                for op in ops {
+                    if let Err(e) = writer.apply(op) {
+                        error!("{:?}", e);
+                    }
+                }
+            }
+        }
+
+        for fill in fills {
+            self.settle(fill);
+        }
+    }
+
+    fn settle_maker_fills(&mut self, fills: Vec<(OrderState, Amount)>) {
+        for (order, amount) in fills {
+            self.settle(
+                Fill::new(
+                    self.timestamp,
+                    order.id().clone(),
+                    order.side(),
+                    order.price(),
+                    amount,
+                    self.config.maker_fee * self.info.notional(order.price(), amount),
+                    Liquidity::Maker,
+                )
+                .with_client_id(order.client_id().cloned()),
+            );
+        }
+    }
+
+    fn settle(&mut self, fill: Fill) {
+        match fill.side() {
+            Side::Ask => self.position -= fill.amount(),
+            Side::Bid => self.position += fill.amount(),
+        }
+        debug!("fill: {:?}", fill);
+        if let Some(observation) = self.observation.as_mut() {
+            observation.insert_fill(fill.clone());
+        }
+        self.record_inventory(fill.timestamp());
+        self.fills.push(fill);
+    }
+
+    fn current_inventory(&self) -> Inventory {
+        Inventory::Position(self.position)
+    }
+
+    fn record_inventory(&mut self, timestamp: u64) {
+        let position = self.current_inventory().position();
+        self.inventory.push(InventoryPoint {
+            timestamp,
+            position,
+        });
+    }
+
+    // our resting orders are part of the book on a real venue
+    fn observed_orderbook(&self) -> Orderbook {
+        let mut orderbook = self.orderbook.clone().unwrap_or_default();
+        let mut writer = OrderbookWriter::new(&mut orderbook);
+        for queued in self.model.orders() {
+            let order = queued.order();
+            let op = OrderbookWriteOp::create(
+                self.timestamp,
+                order.side(),
+                OfferId::own(order.id()),
+                order.price(),
+                order.amount(),
+            );
+            if let Err(e) = writer.apply(op) {
+                error!("{:?}", e);
+            }
+        }
+        orderbook
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::implements::simulation::{ConsumeLiquidity, ReplayedLatency};
+    use crate::interfaces::Observation as ObservationInterface;
+    use crate::strategies::dbo::DepthBasedOffering;
+    use crate::types::{Offer, TradeId};
+
+    fn dummy_records() -> Vec<Record> {
+        vec![
+            Record::new(0, vec![OrderbookWriteOp::init(Orderbook::for_test())]),
+            // orders are still in flight
+            Record::new(
+                50,
+                vec![OrderbookWriteOp::update(
+                    50,
+                    Side::Ask,
+                    OfferId::new(170000),
+                    None,
+                    dec!(900),
+                )],
+            ),
+            // a trade at our bid price before our order arrives
+            Record::new(
+                80,
+                vec![Execution::new(
+                    80,
+                    TradeId::new(1),
+                    Side::Bid,
+                    dec!(14000.5),
+                    dec!(100),
+                )],
+            ),
+            // a new bid joins our level, behind us
+            Record::new(
+                120,
+                vec![OrderbookWriteOp::create(
+                    120,
+                    Side::Bid,
+                    OfferId::new(140005),
+                    dec!(14000.5),
+                    dec!(300),
+                )],
+            ),
+            Record::new(
+                150,
+                vec![Execution::new(
+                    150,
+                    TradeId::new(2),
+                    Side::Bid,
+                    dec!(14000.5),
+                    dec!(400),
+                )],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_backtest_latency_and_queue() {
+        let config = BacktestConfig {
+            entry_latency: 100,
+            cancel_latency: 100,
+            maker_fee: dec!(-0.0001),
+            taker_fee: dec!(0.0005),
+        };
+        let policy = DepthBasedOffering::new(dec!(500), dec!(1000));
+        let mut backtest = Backtest::new(config, MarketInfo::for_test(), policy);
+
+        let records = dummy_records();
+
+        backtest.step(&records[0]);
+        assert_eq!(
+            backtest.pending_orders(),
+            vec![
+                Order::create(OrderType::Limit, Side::Ask, dec!(15999.5), dec!(500)),
+                Order::create(OrderType::Limit, Side::Bid, dec!(14000.5), dec!(500)),
+            ]
+        );
+        assert_eq!(backtest.open_orders().orders().count(), 0);
+
+        backtest.step(&records[1]);
+        backtest.step(&records[2]);
+        assert_eq!(backtest.pending_orders().len(), 2);
+        assert!(backtest.fills().is_empty());
+
+        // orders arrived at 100, nobody was queued at 14000.5 yet
+        backtest.step(&records[3]);
+        assert!(backtest.pending_orders().is_empty());
+        assert_eq!(backtest.open_orders().orders().count(), 2);
+        // and the policy sees them in the book apart from the recorded offers
+        let own = OfferId::own(&OrderId::new(1));
+        assert!(backtest.observed_orderbook().bids().any(|o| o.id() == &own));
+
+        backtest.step(&records[4]);
+        assert_eq!(
+            backtest.fills(),
+            &[Fill::new(
+                150,
+                OrderId::new(1),
+                Side::Bid,
+                dec!(14000.5),
+                dec!(400),
+                dec!(-0.0001) * MarketInfo::for_test().notional(dec!(14000.5), dec!(400)),
+                Liquidity::Maker,
+            )]
+        );
+        assert_eq!(backtest.position(), dec!(400));
+    }
+
+    struct TakeOnce(Order);
+
+    impl Policy for TakeOnce {
+        fn evaluate(&self, observation: impl ObservationInterface) -> Vec<Order> {
+            if observation.inventory().position().is_zero()
+                && observation.pending_orders().is_empty()
+            {
+                vec![self.0.clone()]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn test_backtest_taker_fill() {
+        let config = BacktestConfig {
+            entry_latency: 0,
+            cancel_latency: 0,
+            maker_fee: dec!(-0.0001),
+            taker_fee: dec!(0.0005),
+        };
+        let policy = TakeOnce(Order::create(
+            OrderType::Market,
+            Side::Bid,
+            dec!(0),
+            dec!(1500),
+        ));
+        let result =
+            Backtest::new(config, MarketInfo::for_test(), policy).run(&dummy_records()[..1]);
+
+        assert_eq!(
+            result.fills,
+            vec![
+                Fill::new(
+                    0,
+                    OrderId::new(0),
+                    Side::Bid,
+                    dec!(16000),
+                    dec!(1000),
+                    // 1000 contracts of 6250 satoshis
+                    dec!(3125),
+                    Liquidity::Taker
+                ),
+                Fill::new(
+                    0,
+                    OrderId::new(0),
+                    Side::Bid,
+                    dec!(17000),
+                    dec!(500),
+                    dec!(0.0005) * MarketInfo::for_test().notional(dec!(17000), dec!(500)),
+                    Liquidity::Taker
+                ),
+            ]
+        );
+        assert_eq!(result.position, dec!(1500));
+        assert_eq!(
+            result.inventory,
+            vec![
+                InventoryPoint {
+                    timestamp: 0,
+                    position: dec!(0)
+                },
+                InventoryPoint {
+                    timestamp: 0,
+                    position: dec!(1000)
+                },
+                InventoryPoint {
+                    timestamp: 0,
+                    position: dec!(1500)
+                },
+            ]
+        );
+        assert_eq!(result.open_orders.orders().count(), 0);
+    }
+
+    #[test]
+    fn test_backtest_latency_and_impact_models() {
+        let config = BacktestConfig {
+            entry_latency: 0,
+            cancel_latency: 0,
+            maker_fee: dec!(-0.0001),
+            taker_fee: dec!(0.0005),
+        };
+        let policy = TakeOnce(Order::create(
+            OrderType::Market,
+            Side::Bid,
+            dec!(0),
+            dec!(400),
+        ));
+        let mut backtest = Backtest::new(config, MarketInfo::for_test(), policy)
+            .with_latency_model(ReplayedLatency::new(vec![30]))
+            .with_impact_model(ConsumeLiquidity);
+
+        let records = dummy_records();
+        backtest.step(&records[0]);
+        assert!(backtest.fills().is_empty());
+        assert_eq!(backtest.pending_orders().len(), 1);
+
+        // arrives at 30, before the update at 50
+        backtest.step(&records[1]);
+        assert_eq!(backtest.fills().len(), 1);
+        assert_eq!(backtest.fills()[0].timestamp(), 30);
+        assert_eq!(
+            backtest.orderbook.as_ref().unwrap().best_ask(),
+            Some(&Offer::new(OfferId::new(160000), dec!(16000.0), dec!(600)))
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use rust_decimal::prelude::*;
+
+use crate::types::{
+    Amount, Execution, NewOrder, Offer, OpenOrders, OrderId, OrderState, OrderType, Orderbook,
+    Price, Side,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueuedOrder {
+    order: OrderState,
+    queue_ahead: Amount,
+}
+
+impl QueuedOrder {
+    pub fn order(&self) -> &OrderState {
+        &self.order
+    }
+
+    pub fn queue_ahead(&self) -> Amount {
+        self.queue_ahead
+    }
+}
+
+/// Estimates fills of our resting orders from market data which does not contain them.
+///
+/// An order joins the back of the queue at its price level, so the displayed amount at that
+/// level is ahead of it. Trades at the level consume the queue first and fill us only with
+/// what is left over, while the queue can never be longer than the displayed level. Trades
+/// through the level, or an opposite side crossing our price, fill the order entirely.
+#[derive(Clone, Debug, Default)]
+pub struct QueueFillModel {
+    orders: Vec<QueuedOrder>,
+}
+
+impl QueueFillModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &QueuedOrder> {
+        self.orders.iter()
+    }
+
+    pub fn get(&self, id: &OrderId) -> Option<&QueuedOrder> {
+        self.orders.iter().find(|o| o.order.id() == id)
+    }
+
+    pub fn open_orders(&self, timestamp: u64) -> OpenOrders {
+        OpenOrders::new(timestamp, self.orders.iter().map(|o| o.order.clone()))
+    }
+
+    pub fn insert(&mut self, orderbook: &Orderbook, order: OrderState) {
+        let displayed = level_amount(orderbook, order.side(), order.price());
+        let ours: Amount = self
+            .orders
+            .iter()
+            .filter(|o| o.order.side() == order.side() && o.order.price() == order.price())
+            .map(|o| o.order.amount())
+            .sum();
+
+        self.orders.push(QueuedOrder {
+            order,
+            queue_ahead: displayed + ours,
+        });
+    }
+
+    pub fn remove(&mut self, id: &OrderId) -> Option<OrderState> {
+        let index = self.orders.iter().position(|o| o.order.id() == id)?;
+        Some(self.orders.remove(index).order)
+    }
+
+    /// Reduces a resting order to `amount` without losing its place in the queue.
+    pub fn reduce(&mut self, id: &OrderId, amount: Amount) -> bool {
+        let index = match self.orders.iter().position(|o| o.order.id() == id) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        if amount.is_zero() {
+            self.orders.remove(index);
+        } else {
+            let queued = &mut self.orders[index];
+            queued.order.amount = amount.min(queued.order.amount());
+        }
+        true
+    }
+
+    /// Returns the (order, filled amount) pairs caused by an orderbook update.
+    pub fn on_orderbook(&mut self, orderbook: &Orderbook) -> Vec<(OrderState, Amount)> {
+        let mut fills = Vec::new();
+
+        for queued in &mut self.orders {
+            let order = &queued.order;
+
+            let crossed = match order.side() {
+                Side::Ask => orderbook
+                    .best_bid_price()
+                    .map_or(false, |price| price >= order.price()),
+                Side::Bid => orderbook
+                    .best_ask_price()
+                    .map_or(false, |price| price <= order.price()),
+            };
+
+            if crossed {
+                fills.push((order.clone(), order.amount()));
+                queued.queue_ahead = Amount::zero();
+            } else {
+                let displayed = level_amount(orderbook, order.side(), order.price());
+                queued.queue_ahead = queued.queue_ahead.min(displayed);
+            }
+        }
+
+        self.settle(fills)
+    }
+
+    /// Returns the (order, filled amount) pairs caused by a public trade.
+    pub fn on_execution(&mut self, execution: &Execution) -> Vec<(OrderState, Amount)> {
+        let mut fills = Vec::new();
+
+        for queued in &mut self.orders {
+            let order = &queued.order;
+            if order.side() != execution.maker_side() {
+                continue;
+            }
+
+            let through = match order.side() {
+                Side::Ask => execution.price() > order.price(),
+                Side::Bid => execution.price() < order.price(),
+            };
+
+            if through {
+                fills.push((order.clone(), order.amount()));
+                queued.queue_ahead = Amount::zero();
+            } else if execution.price() == order.price() {
+                let left = execution.amount() - queued.queue_ahead;
+                queued.queue_ahead = (queued.queue_ahead - execution.amount()).max(Amount::zero());
+
+                if left > Amount::zero() {
+                    fills.push((order.clone(), left.min(order.amount())));
+                }
+            }
+        }
+
+        self.settle(fills)
+    }
+
+    fn settle(&mut self, fills: Vec<(OrderState, Amount)>) -> Vec<(OrderState, Amount)> {
+        for (order, amount) in &fills {
+            if let Some(index) = self.orders.iter().position(|o| o.order.id() == order.id()) {
+                let queued = &mut self.orders[index];
+                queued.order.amount -= *amount;
+                if queued.order.amount().is_zero() {
+                    self.orders.remove(index);
+                }
+            }
+        }
+        fills
+    }
+}
+
+fn level_amount(orderbook: &Orderbook, side: Side, price: Price) -> Amount {
+    let offers = match side {
+        Side::Ask => &orderbook.asks,
+        Side::Bid => &orderbook.bids,
+    };
+    offers
+        .iter()
+        .filter(|offer| offer.price() == price)
+        .map(|offer| offer.amount())
+        .sum()
+}
+
+/// Returns the (offer, amount) fills of a marketable order walking the book, which is left
+/// untouched. Whatever is not returned is left for a limit order to rest.
+pub fn take_liquidity(orderbook: &Orderbook, order: &NewOrder) -> Vec<(Offer, Amount)> {
+    let side = order.order_side();
+    let offers = match side {
+        Side::Ask => &orderbook.bids,
+        Side::Bid => &orderbook.asks,
+    };
+
+    let mut remaining = order.amount();
+    let mut fills = Vec::new();
+    for offer in offers {
+        if remaining.is_zero() {
+            break;
+        }
+
+        let crossed = match (order.order_type(), side) {
+            (OrderType::Market, _) => true,
+            (OrderType::Limit, Side::Ask) => offer.price() >= order.price(),
+            (OrderType::Limit, Side::Bid) => offer.price() <= order.price(),
+        };
+        if !crossed {
+            break;
+        }
+
+        let amount = remaining.min(offer.amount());
+        remaining -= amount;
+        fills.push((offer.clone(), amount));
+    }
+    fills
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::types::{Offer, OfferId, TradeId};
+
+    fn trade(maker_side: Side, price: Price, amount: Amount) -> Execution {
+        Execution::new(0, TradeId::new(0), maker_side, price, amount)
+    }
+
+    #[test]
+    fn test_queue_fill_model_queue_position() {
+        let orderbook = Orderbook::for_test();
+        let mut model = QueueFillModel::new();
+
+        let order = OrderState::new(OrderId::new(1), Side::Bid, dec!(14000), dec!(500));
+        model.insert(&orderbook, order.clone());
+        assert_eq!(model.get(order.id()).unwrap().queue_ahead(), dec!(1000));
+
+        // trades at the other side do not matter
+        assert_eq!(
+            model.on_execution(&trade(Side::Ask, dec!(14000), dec!(900))),
+            vec![]
+        );
+
+        // consumes the queue ahead of us
+        assert_eq!(
+            model.on_execution(&trade(Side::Bid, dec!(14000), dec!(600))),
+            vec![]
+        );
+        assert_eq!(model.get(order.id()).unwrap().queue_ahead(), dec!(400));
+
+        // partially fills us
+        assert_eq!(
+            model.on_execution(&trade(Side::Bid, dec!(14000), dec!(600))),
+            vec![(order.clone(), dec!(200))],
+        );
+        assert_eq!(model.get(order.id()).unwrap().order().amount(), dec!(300));
+        assert_eq!(model.get(order.id()).unwrap().queue_ahead(), dec!(0));
+
+        // fills the rest
+        assert_eq!(
+            model.on_execution(&trade(Side::Bid, dec!(14000), dec!(1000))),
+            vec![(
+                OrderState::new(OrderId::new(1), Side::Bid, dec!(14000), dec!(300)),
+                dec!(300)
+            )],
+        );
+        assert_eq!(model.orders().count(), 0);
+    }
+
+    #[test]
+    fn test_queue_fill_model_level_shrink() {
+        let mut orderbook = Orderbook::for_test();
+        let mut model = QueueFillModel::new();
+
+        let first = OrderState::new(OrderId::new(1), Side::Ask, dec!(16000), dec!(500));
+        let second = OrderState::new(OrderId::new(2), Side::Ask, dec!(16000), dec!(500));
+        model.insert(&orderbook, first.clone());
+        model.insert(&orderbook, second.clone());
+        assert_eq!(model.get(second.id()).unwrap().queue_ahead(), dec!(1500));
+
+        // cancels at the level shorten the queue
+        orderbook.asks[0].amount = dec!(300);
+        assert_eq!(model.on_orderbook(&orderbook), vec![]);
+        assert_eq!(model.get(first.id()).unwrap().queue_ahead(), dec!(300));
+        assert_eq!(model.get(second.id()).unwrap().queue_ahead(), dec!(300));
+
+        // trade through the level
+        assert_eq!(
+            model.on_execution(&trade(Side::Ask, dec!(16500), dec!(1))),
+            vec![(first, dec!(500)), (second, dec!(500))],
+        );
+        assert_eq!(model.orders().count(), 0);
+    }
+
+    #[test]
+    fn test_queue_fill_model_crossed() {
+        let mut orderbook = Orderbook::for_test();
+        let mut model = QueueFillModel::new();
+
+        let order = OrderState::new(OrderId::new(1), Side::Bid, dec!(15000), dec!(500));
+        model.insert(&orderbook, order.clone());
+        assert_eq!(model.get(order.id()).unwrap().queue_ahead(), dec!(0));
+
+        orderbook
+            .asks
+            .insert(0, Offer::new(OfferId::new(150000), dec!(15000), dec!(100)));
+        assert_eq!(model.on_orderbook(&orderbook), vec![(order, dec!(500))]);
+        assert_eq!(model.orders().count(), 0);
+    }
+}
@@ -0,0 +1,9 @@
+mod engine;
+mod fill_model;
+mod report;
+mod sweep;
+
+pub use engine::*;
+pub use fill_model::*;
+pub use report::*;
+pub use sweep::*;
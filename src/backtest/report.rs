@@ -0,0 +1,405 @@
+use chrono::{TimeZone, Utc};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::engine::{BacktestResult, InventoryPoint, Mark};
+use crate::types::{Amount, Fill, MarketInfo, Price, Side};
+
+/// Summary of a backtest, with PnL and fees in the settlement currency of the market.
+///
+/// Inverse contracts, which have a negative `MarketInfo::multiplier`, are valued at
+/// `multiplier / price`, so closing `qty` contracts bought at `entry` realizes
+/// `qty * |multiplier| * (1 / entry - 1 / exit)`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub started_at: u64,  // ms
+    pub finished_at: u64, // ms
+    pub settlement_currency: String,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub fees: Decimal,
+    pub net_pnl: Decimal, // realized + unrealized - fees
+    pub traded_volume: Amount,
+    pub traded_notional: Decimal,
+    pub net_return: Option<Decimal>, // net_pnl / traded_notional
+    pub maker_fills: usize,
+    pub taker_fills: usize,
+    pub submitted_volume: Amount,
+    pub fill_ratio: Option<Decimal>, // traded_volume / submitted_volume
+    pub final_position: Amount,
+    pub max_exposure: Amount,
+    pub time_at_max_exposure: u64, // ms
+    pub time_at_max_exposure_ratio: Option<Decimal>,
+    pub average_spread: Option<Price>,
+    pub max_drawdown: Decimal,
+    pub inventory: Vec<InventoryPoint>,
+}
+
+impl BacktestReport {
+    pub fn new(result: &BacktestResult, max_exposure: Amount) -> Self {
+        let mut book = PnlBook::new(&result.info);
+
+        let mut peak: Option<Decimal> = None;
+        let mut max_drawdown = Decimal::zero();
+        let mut mark: Option<Price> = None;
+        let mut record_equity = |equity: Decimal| {
+            let peak = peak.get_or_insert(equity);
+            *peak = (*peak).max(equity);
+            max_drawdown = max_drawdown.max(*peak - equity);
+        };
+
+        // replay fills and marks in time order, fills first on ties
+        let mut marks = result.marks.iter().peekable();
+        for fill in &result.fills {
+            while let Some(Mark { price, .. }) = marks.next_if(|m| m.timestamp < fill.timestamp()) {
+                mark = Some(*price);
+                record_equity(book.equity(*price));
+            }
+
+            book.apply(fill);
+            if let Some(price) = mark {
+                record_equity(book.equity(price));
+            }
+        }
+        for Mark { price, .. } in marks {
+            mark = Some(*price);
+            record_equity(book.equity(*price));
+        }
+
+        let unrealized_pnl = mark.map_or(Decimal::zero(), |price| book.unrealized(price));
+        let net_pnl = book.realized + unrealized_pnl - book.fees;
+
+        let duration = result.finished_at.saturating_sub(result.started_at);
+        let time_at_max_exposure =
+            time_at_exposure(&result.inventory, result.finished_at, max_exposure);
+
+        let spreads: Vec<Price> = result.quotes.iter().filter_map(|q| q.spread()).collect();
+        let average_spread = if spreads.is_empty() {
+            None
+        } else {
+            Some(spreads.iter().sum::<Price>() / Decimal::from(spreads.len()))
+        };
+
+        Self {
+            started_at: result.started_at,
+            finished_at: result.finished_at,
+            settlement_currency: result.info.settlement_currency().to_string(),
+            realized_pnl: book.realized,
+            unrealized_pnl,
+            fees: book.fees,
+            net_pnl,
+            traded_volume: book.volume,
+            traded_notional: book.notional,
+            net_return: (!book.notional.is_zero()).then(|| net_pnl / book.notional),
+            maker_fills: result
+                .fills
+                .iter()
+                .filter(|f| f.liquidity().is_maker())
+                .count(),
+            taker_fills: result
+                .fills
+                .iter()
+                .filter(|f| f.liquidity().is_taker())
+                .count(),
+            submitted_volume: result.submitted_volume,
+            fill_ratio: (!result.submitted_volume.is_zero())
+                .then(|| book.volume / result.submitted_volume),
+            final_position: result.position,
+            max_exposure,
+            time_at_max_exposure,
+            time_at_max_exposure_ratio: (duration > 0)
+                .then(|| Decimal::from(time_at_max_exposure) / Decimal::from(duration)),
+            average_spread,
+            max_drawdown,
+            inventory: result.inventory.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+struct PnlBook<'a> {
+    info: &'a MarketInfo,
+    position: Amount,
+    entry_value: Decimal, // average contract value of the open position
+    realized: Decimal,
+    fees: Decimal,
+    volume: Amount,
+    notional: Decimal,
+}
+
+impl<'a> PnlBook<'a> {
+    fn new(info: &'a MarketInfo) -> Self {
+        Self {
+            info,
+            position: Amount::zero(),
+            entry_value: Decimal::zero(),
+            realized: Decimal::zero(),
+            fees: Decimal::zero(),
+            volume: Amount::zero(),
+            notional: Decimal::zero(),
+        }
+    }
+
+    fn apply(&mut self, fill: &Fill) {
+        let signed = match fill.side() {
+            Side::Ask => -fill.amount(),
+            Side::Bid => fill.amount(),
+        };
+
+        self.fees += fill.fee();
+        self.volume += fill.amount();
+        self.notional += self.info.notional(fill.price(), fill.amount());
+
+        let value = self.info.contract_value(fill.price());
+        if self.position.is_zero() || self.position.is_sign_positive() == signed.is_sign_positive()
+        {
+            let size = self.position.abs() + fill.amount();
+            self.entry_value =
+                (self.entry_value * self.position.abs() + value * fill.amount()) / size;
+            self.position += signed;
+            return;
+        }
+
+        let closed = fill.amount().min(self.position.abs());
+        let direction = self.position.signum();
+        self.realized += (value - self.entry_value) * closed * direction;
+        self.position += signed;
+
+        if self.position.is_zero() {
+            self.entry_value = Decimal::zero();
+        } else if self.position.signum() != direction {
+            self.entry_value = value;
+        }
+    }
+
+    fn unrealized(&self, mark: Price) -> Decimal {
+        (self.info.contract_value(mark) - self.entry_value) * self.position
+    }
+
+    fn equity(&self, mark: Price) -> Decimal {
+        self.realized + self.unrealized(mark) - self.fees
+    }
+}
+
+fn time_at_exposure(inventory: &[InventoryPoint], finished_at: u64, exposure: Amount) -> u64 {
+    let mut total = 0;
+    for (i, point) in inventory.iter().enumerate() {
+        let until = inventory
+            .get(i + 1)
+            .map_or(finished_at, |next| next.timestamp);
+        if point.position.abs() >= exposure {
+            total += until.saturating_sub(point.timestamp);
+        }
+    }
+    total
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const P: usize = 9;
+        const I: usize = 9;
+        const W: usize = P + I + 1;
+        const K: usize = 26;
+
+        let date = |timestamp: u64| {
+            Utc.timestamp_millis_opt(timestamp.try_into().unwrap())
+                .unwrap()
+        };
+        let optional =
+            |value: Option<Decimal>| value.map_or_else(|| "-".to_string(), |v| format!("{v:.P$}"));
+
+        writeln!(
+            f,
+            "BacktestReport @ {} - {} (PnL in {})",
+            date(self.started_at),
+            date(self.finished_at),
+            self.settlement_currency
+        )?;
+
+        let rows: Vec<(&str, String)> = vec![
+            ("Realized PnL", format!("{:.P$}", self.realized_pnl)),
+            ("Unrealized PnL", format!("{:.P$}", self.unrealized_pnl)),
+            ("Fees", format!("{:.P$}", self.fees)),
+            ("Net PnL", format!("{:.P$}", self.net_pnl)),
+            ("Net Return", optional(self.net_return)),
+            ("Max Drawdown", format!("{:.P$}", self.max_drawdown)),
+            ("Traded Volume", format!("{:.P$}", self.traded_volume)),
+            ("Traded Notional", format!("{:.P$}", self.traded_notional)),
+            ("Maker Fills", self.maker_fills.to_string()),
+            ("Taker Fills", self.taker_fills.to_string()),
+            ("Submitted Volume", format!("{:.P$}", self.submitted_volume)),
+            ("Fill Ratio", optional(self.fill_ratio)),
+            ("Final Position", format!("{:.P$}", self.final_position)),
+            ("Max Exposure", format!("{:.P$}", self.max_exposure)),
+            (
+                "Time at Max Exposure (ms)",
+                self.time_at_max_exposure.to_string(),
+            ),
+            (
+                "Time at Max Exposure (%)",
+                optional(
+                    self.time_at_max_exposure_ratio
+                        .map(|r| r * Decimal::ONE_HUNDRED),
+                ),
+            ),
+            ("Average Spread", optional(self.average_spread)),
+        ];
+
+        for (key, value) in rows {
+            writeln!(f, "  {key:<K$} {value:>W$}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::backtest::Quote;
+    use crate::types::{Liquidity, OpenOrders, OrderId};
+
+    fn fill(info: &MarketInfo, timestamp: u64, side: Side, price: Price, amount: Amount) -> Fill {
+        Fill::new(
+            timestamp,
+            OrderId::new(timestamp),
+            side,
+            price,
+            amount,
+            dec!(-0.0001) * info.notional(price, amount),
+            Liquidity::Maker,
+        )
+    }
+
+    fn mark(timestamp: u64, price: Price) -> Mark {
+        Mark { timestamp, price }
+    }
+
+    fn point(timestamp: u64, position: Amount) -> InventoryPoint {
+        InventoryPoint {
+            timestamp,
+            position,
+        }
+    }
+
+    #[test]
+    fn test_backtest_report() {
+        let info = MarketInfo {
+            multiplier: dec!(1),
+            settlement_currency: "USD".to_string(),
+            ..MarketInfo::for_test()
+        };
+        let result = BacktestResult {
+            started_at: 0,
+            finished_at: 1000,
+            submitted_volume: dec!(70),
+            fills: vec![
+                fill(&info, 100, Side::Bid, dec!(100), dec!(10)),
+                fill(&info, 200, Side::Bid, dec!(110), dec!(10)),
+                fill(&info, 500, Side::Ask, dec!(120), dec!(15)),
+            ],
+            marks: vec![
+                mark(0, dec!(100)),
+                mark(300, dec!(90)),
+                mark(600, dec!(115)),
+            ],
+            quotes: vec![
+                Quote {
+                    timestamp: 0,
+                    ask: Some(dec!(101)),
+                    bid: Some(dec!(99)),
+                },
+                Quote {
+                    timestamp: 300,
+                    ask: Some(dec!(94)),
+                    bid: None,
+                },
+                Quote {
+                    timestamp: 600,
+                    ask: Some(dec!(118)),
+                    bid: Some(dec!(112)),
+                },
+            ],
+            inventory: vec![
+                point(0, dec!(0)),
+                point(100, dec!(10)),
+                point(200, dec!(20)),
+                point(500, dec!(5)),
+            ],
+            position: dec!(5),
+            open_orders: OpenOrders::new(1000, vec![]),
+            info,
+        };
+
+        let report = BacktestReport::new(&result, dec!(20));
+        println!("{}", report);
+
+        // entry at 105, 15 closed at 120
+        assert_eq!(report.settlement_currency, "USD");
+        assert_eq!(report.realized_pnl, dec!(225));
+        assert_eq!(report.unrealized_pnl, dec!(50));
+        assert_eq!(report.fees, dec!(-0.39));
+        assert_eq!(report.net_pnl, dec!(275.39));
+        assert_eq!(report.traded_volume, dec!(35));
+        assert_eq!(report.traded_notional, dec!(3900));
+        assert_eq!(report.maker_fills, 3);
+        assert_eq!(report.taker_fills, 0);
+        assert_eq!(report.fill_ratio, Some(dec!(0.5)));
+        assert_eq!(report.final_position, dec!(5));
+        assert_eq!(report.time_at_max_exposure, 300);
+        assert_eq!(report.time_at_max_exposure_ratio, Some(dec!(0.3)));
+        assert_eq!(report.average_spread, Some(dec!(4)));
+        // from 0.1 at 100 down to -299.79 at 300
+        assert_eq!(report.max_drawdown, dec!(299.89));
+        assert_eq!(report.inventory, result.inventory);
+
+        let json = report.to_json().unwrap();
+        let decoded: BacktestReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, report);
+    }
+
+    #[test]
+    fn test_backtest_report_inverse() {
+        let info = MarketInfo::for_test();
+        let result = BacktestResult {
+            started_at: 0,
+            finished_at: 1000,
+            submitted_volume: dec!(300),
+            fills: vec![
+                fill(&info, 100, Side::Bid, dec!(10000), dec!(100)),
+                fill(&info, 200, Side::Bid, dec!(8000), dec!(100)),
+                fill(&info, 300, Side::Ask, dec!(12500), dec!(100)),
+            ],
+            marks: vec![mark(0, dec!(10000)), mark(400, dec!(10000))],
+            quotes: vec![],
+            inventory: vec![
+                point(0, dec!(0)),
+                point(100, dec!(100)),
+                point(200, dec!(200)),
+                point(300, dec!(100)),
+            ],
+            position: dec!(100),
+            open_orders: OpenOrders::new(1000, vec![]),
+            info,
+        };
+
+        let report = BacktestReport::new(&result, dec!(200));
+
+        // contracts are worth 1e8 / price satoshis, the entry averages to 11250 of them
+        assert_eq!(report.settlement_currency, "XBt");
+        assert_eq!(report.realized_pnl, dec!(325000));
+        assert_eq!(report.unrealized_pnl, dec!(125000));
+        assert_eq!(report.traded_notional, dec!(3050000));
+        assert_eq!(report.fees, dec!(-305));
+        assert_eq!(report.net_pnl, dec!(450305));
+        assert_eq!(report.final_position, dec!(100));
+        assert_eq!(report.time_at_max_exposure, 100);
+    }
+}
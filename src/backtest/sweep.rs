@@ -0,0 +1,324 @@
+use log::*;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::engine::{Backtest, BacktestConfig};
+use super::report::BacktestReport;
+use crate::implements::records::Record;
+use crate::interfaces::Policy;
+use crate::strategies::dbo::DepthBasedOffering;
+use crate::types::{Amount, MarketInfo};
+
+/// One point of a parameter grid, which knows how to build its policy.
+pub trait SweepParams: Clone + Send + Sync + Serialize {
+    type Policy: Policy;
+
+    fn build(&self) -> Self::Policy;
+    fn max_exposure(&self) -> Amount;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DboParams {
+    pub max_exposure: Amount,
+    pub target_depth: Amount,
+}
+
+impl DboParams {
+    pub fn grid(max_exposures: &[Amount], target_depths: &[Amount]) -> Vec<Self> {
+        max_exposures
+            .iter()
+            .flat_map(|&max_exposure| {
+                target_depths.iter().map(move |&target_depth| Self {
+                    max_exposure,
+                    target_depth,
+                })
+            })
+            .collect()
+    }
+}
+
+impl SweepParams for DboParams {
+    type Policy = DepthBasedOffering;
+
+    fn build(&self) -> DepthBasedOffering {
+        DepthBasedOffering::new(self.max_exposure, self.target_depth)
+    }
+
+    fn max_exposure(&self) -> Amount {
+        self.max_exposure
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SweepRow<T> {
+    pub rank: usize, // 1 is the best net PnL
+    #[serde(flatten)]
+    pub params: T,
+    pub net_pnl: Decimal,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub fees: Decimal,
+    pub max_drawdown: Decimal,
+    pub fill_ratio: Option<Decimal>,
+    pub traded_volume: Amount,
+    pub maker_fills: usize,
+    pub taker_fills: usize,
+    pub final_position: Amount,
+}
+
+const METRIC_COLUMNS: [&str; 10] = [
+    "net_pnl",
+    "realized_pnl",
+    "unrealized_pnl",
+    "fees",
+    "max_drawdown",
+    "fill_ratio",
+    "traded_volume",
+    "maker_fills",
+    "taker_fills",
+    "final_position",
+];
+
+impl<T> SweepRow<T> {
+    fn new(params: T, report: &BacktestReport) -> Self {
+        Self {
+            rank: 0,
+            params,
+            net_pnl: report.net_pnl,
+            realized_pnl: report.realized_pnl,
+            unrealized_pnl: report.unrealized_pnl,
+            fees: report.fees,
+            max_drawdown: report.max_drawdown,
+            fill_ratio: report.fill_ratio,
+            traded_volume: report.traded_volume,
+            maker_fills: report.maker_fills,
+            taker_fills: report.taker_fills,
+            final_position: report.final_position,
+        }
+    }
+}
+
+/// Runs one independent backtest per parameter set over a shared dataset.
+pub struct SweepRunner {
+    config: BacktestConfig,
+    info: MarketInfo,
+    records: Arc<[Record]>,
+    num_threads: usize,
+}
+
+impl SweepRunner {
+    pub fn new(
+        config: BacktestConfig,
+        info: MarketInfo,
+        records: impl Into<Arc<[Record]>>,
+    ) -> Self {
+        Self {
+            config,
+            info,
+            records: records.into(),
+            num_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    pub fn with_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads.max(1);
+        self
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Returns the rows ranked by net PnL, then by the smaller drawdown.
+    pub fn run<T: SweepParams>(&self, grid: Vec<T>) -> Vec<SweepRow<T>> {
+        let next = AtomicUsize::new(0);
+        let rows = Mutex::new(Vec::with_capacity(grid.len()));
+
+        thread::scope(|s| {
+            for _ in 0..self.num_threads.min(grid.len()) {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(params) = grid.get(i) else {
+                        break;
+                    };
+
+                    let backtest =
+                        Backtest::new(self.config.clone(), self.info.clone(), params.build());
+                    let result = backtest.run(self.records.iter());
+                    let report = BacktestReport::new(&result, params.max_exposure());
+                    debug!("sweep {}/{}: net_pnl={}", i + 1, grid.len(), report.net_pnl);
+
+                    rows.lock()
+                        .unwrap()
+                        .push(SweepRow::new(params.clone(), &report));
+                });
+            }
+        });
+
+        let mut rows = rows.into_inner().unwrap();
+        rows.sort_by(|a, b| {
+            b.net_pnl
+                .cmp(&a.net_pnl)
+                .then(a.max_drawdown.cmp(&b.max_drawdown))
+        });
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.rank = i + 1;
+        }
+        rows
+    }
+}
+
+pub fn write_sweep_json<T: Serialize>(
+    path: impl AsRef<Path>,
+    rows: &[SweepRow<T>],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, rows)?;
+    writer.flush()
+}
+
+pub fn write_sweep_csv<T: Serialize>(
+    path: impl AsRef<Path>,
+    rows: &[SweepRow<T>],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "{}", sweep_csv(rows)?)?;
+    writer.flush()
+}
+
+fn sweep_csv<T: Serialize>(rows: &[SweepRow<T>]) -> io::Result<String> {
+    // params are flattened into the row, so they must serialize as a map
+    let param_columns: Vec<String> = match rows.first() {
+        Some(row) => match serde_json::to_value(&row.params)? {
+            Value::Object(map) => map.keys().cloned().collect(),
+            _ => vec![],
+        },
+        None => vec![],
+    };
+    let columns: Vec<&str> = std::iter::once("rank")
+        .chain(param_columns.iter().map(String::as_str))
+        .chain(METRIC_COLUMNS)
+        .collect();
+
+    let mut csv = columns.join(",");
+    csv.push('\n');
+    for row in rows {
+        let value = serde_json::to_value(row)?;
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|c| value.get(c).map_or_else(String::new, csv_field))
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+fn csv_field(value: &Value) -> String {
+    let field = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::implements::writers::OrderbookWriteOp;
+    use crate::types::{Execution, Offer, OfferId, Orderbook, Side, TradeId};
+
+    fn dummy_records() -> Vec<Record> {
+        vec![
+            Record::new(
+                0,
+                vec![OrderbookWriteOp::init(Orderbook::new(
+                    0,
+                    vec![Offer::new(OfferId::new(160000), dec!(16000.0), dec!(1000))],
+                    vec![
+                        Offer::new(OfferId::new(140000), dec!(14000.0), dec!(1000)),
+                        Offer::new(OfferId::new(130000), dec!(13000.0), dec!(1000)),
+                    ],
+                ))],
+            ),
+            Record::new(
+                150,
+                vec![Execution::new(
+                    150,
+                    TradeId::new(1),
+                    Side::Bid,
+                    dec!(14000.5),
+                    dec!(1000),
+                )],
+            ),
+            Record::new(
+                200,
+                vec![OrderbookWriteOp::update(
+                    200,
+                    Side::Bid,
+                    OfferId::new(140000),
+                    None,
+                    dec!(100),
+                )],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_sweep() {
+        let config = BacktestConfig {
+            entry_latency: 100,
+            cancel_latency: 100,
+            maker_fee: dec!(-0.0001),
+            taker_fee: dec!(0.0005),
+        };
+        let runner =
+            SweepRunner::new(config, MarketInfo::for_test(), dummy_records()).with_threads(2);
+        let grid = DboParams::grid(&[dec!(500), dec!(1000)], &[dec!(1000), dec!(5000)]);
+        assert_eq!(grid.len(), 4);
+
+        let rows = runner.run(grid);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows.iter().map(|r| r.rank).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(rows.windows(2).all(|w| w[0].net_pnl >= w[1].net_pnl));
+        // only the shallow bids are hit by the trade at 150, the larger one ranks first
+        assert_eq!(
+            rows[0].params,
+            DboParams {
+                max_exposure: dec!(1000),
+                target_depth: dec!(1000)
+            }
+        );
+        assert_eq!(rows[0].fill_ratio, Some(dec!(0.5)));
+        assert_eq!(rows[1].params.target_depth, dec!(1000));
+        assert!(rows[2..].iter().all(|r| r.maker_fills == 0));
+
+        let csv = sweep_csv(&rows).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("rank,max_exposure,target_depth,net_pnl,"));
+        assert!(lines[1].starts_with("1,"));
+
+        let json = serde_json::to_value(&rows).unwrap();
+        assert_eq!(json[0]["rank"], 1);
+        assert!(json[0].get("target_depth").is_some());
+    }
+}
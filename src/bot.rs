@@ -1,23 +1,34 @@
-use anyhow::Result;
-use crossbeam_channel::select;
+use anyhow::{bail, Result};
+use crossbeam_channel::{bounded, select, tick};
 use log::*;
+use std::thread;
+use std::time::Duration;
 
+use crate::clock::{Clock, SystemClock};
 use crate::components::order_service::OrderService;
 use crate::interfaces::{Broker, Market, Observation as ObservationInterface, Policy, Status};
 use crate::observation::Observation;
+use crate::types::Amount;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Config {
     pub num_iteration: usize,
-    pub test: bool, // no submission
+    pub test: bool,                   // no submission
+    pub max_position: Option<Amount>, // stops the bot once the absolute position exceeds it
 }
 
-pub struct Bot<M, S, B, P> {
+/// How often the broker hears from us while no data arrives, well within the timeout of a
+/// dead man's switch.
+pub const HEARTBEAT_INTERVAL_MS: u64 = 1_000;
+
+pub struct Bot<M, S, B, P, C = SystemClock> {
     config: Config,
     market: M,
     status: S,
     policy: P,
-    order_service: OrderService<B>,
+    order_service: OrderService<B, C>,
+    clock: C,
+    heartbeat_interval: Duration,
 }
 
 impl<M, S, B, P> Bot<M, S, B, P>
@@ -28,17 +39,70 @@ where
     P: Policy,
 {
     pub fn new(config: Config, market: M, status: S, broker: B, policy: P) -> Self {
-        let order_service = OrderService::start(broker);
+        Self::with_clock(config, market, status, broker, policy, SystemClock)
+    }
+}
+
+impl<M, S, B, P, C> Bot<M, S, B, P, C>
+where
+    M: Market,
+    S: Status,
+    B: Broker + Send + Sync + 'static,
+    P: Policy,
+    C: Clock + Clone + 'static,
+{
+    pub fn with_clock(
+        config: Config,
+        market: M,
+        status: S,
+        broker: B,
+        policy: P,
+        clock: C,
+    ) -> Self {
+        let order_service = OrderService::start_with_clock(broker, clock.clone());
         Self {
             config,
             market,
             status,
             policy,
             order_service,
+            clock,
+            heartbeat_interval: Duration::from_millis(HEARTBEAT_INTERVAL_MS),
         }
     }
 
+    pub fn with_heartbeat_interval(self, interval_ms: u64) -> Self {
+        Self {
+            heartbeat_interval: Duration::from_millis(interval_ms),
+            ..self
+        }
+    }
+
+    /// Runs the iterations and cancels all our orders on the way out, whether the run
+    /// completed, failed or tripped a risk limit.
     pub fn run(&mut self) -> Result<()> {
+        let result = self.run_iterations();
+        if let Err(e) = &result {
+            error!("stopping: {:?}", e);
+        }
+
+        if !self.config.test {
+            let canceled = self.order_service.cancel_all(None);
+            info!("canceled on shutdown: {:?}", canceled);
+        }
+        if result.is_ok() {
+            self.disarm();
+        }
+        result
+    }
+
+    /// Disarms the broker's guard against the bot dying, e.g. a dead man's switch. `run`
+    /// does so on a clean exit, otherwise the guard is left to fire.
+    pub fn disarm(&self) {
+        self.order_service.disarm();
+    }
+
+    fn run_iterations(&mut self) -> Result<()> {
         info!("Start running!");
         info!("\n{:#?}", self.config);
 
@@ -47,40 +111,110 @@ where
 
         let execution = self.market.execution();
         let orderbook = self.market.orderbook();
+        let ticker = self.market.ticker();
         let inventory = self.status.inventory();
         let open_orders = self.status.open_orders();
+        let fills = self.status.fills();
+        let account = self.status.account();
+        let market_connection = self.market.connection();
+        let status_connection = self.status.connection();
+
+        let heartbeat = tick(self.heartbeat_interval);
 
         info!("Warmingup observation..");
-        let mut observation = Observation::warmup(
-            info,
-            execution.as_receiver(),
-            orderbook.as_receiver(),
-            inventory.as_receiver(),
-            open_orders.as_receiver(),
-        )?;
-
-        for i in 0..self.config.num_iteration {
+        let order_service = &self.order_service;
+        let mut observation = thread::scope(|scope| {
+            // keeps beating however long the first data takes
+            let (warm, warming) = bounded::<()>(0);
+            let heartbeat = &heartbeat;
+            scope.spawn(move || loop {
+                select! {
+                    recv(heartbeat) -> _ => order_service.heartbeat(),
+                    recv(warming) -> _ => break,
+                }
+            });
+            let observation = Observation::warmup(
+                info,
+                execution.as_receiver(),
+                fills.as_receiver(),
+                orderbook.as_receiver(),
+                inventory.as_receiver(),
+                open_orders.as_receiver(),
+            );
+            drop(warm);
+            observation
+        })?;
+        for state in market_connection.as_receiver().try_iter() {
+            observation.update_market_connection(state);
+        }
+        for state in status_connection.as_receiver().try_iter() {
+            observation.update_status_connection(state);
+        }
+
+        let mut i = 0;
+        while i < self.config.num_iteration {
             let mut target = false;
             select! {
+                // beats do not count as iterations, so a quiet market does not end the run
+                recv(heartbeat) -> msg => {
+                    msg?;
+                    self.order_service.heartbeat();
+                    continue;
+                },
                 recv(execution.as_receiver()) -> msg => {
                     info!("iteration[{i}] receive execution!");
                     observation.insert_execution(msg?);
                 },
+                recv(fills.as_receiver()) -> msg => {
+                    let fill = msg?;
+                    info!("iteration[{i}] receive fill: {:?}", fill);
+                    observation.insert_fill(fill);
+                },
                 recv(orderbook.as_receiver()) -> msg => {
                     info!("iteration[{i}] receive orderbook!");
                     observation.update_orderbook(msg?);
                     target = true;
                 },
+                recv(ticker.as_receiver()) -> msg => {
+                    info!("iteration[{i}] receive ticker!");
+                    observation.update_ticker(msg?);
+                },
                 recv(inventory.as_receiver()) -> msg => {
                     info!("iteration[{i}] receive inventory!");
                     observation.update_inventory(msg?);
                 },
+                recv(account.as_receiver()) -> msg => {
+                    info!("iteration[{i}] receive account!");
+                    observation.update_account(msg?);
+                },
                 recv(open_orders.as_receiver()) -> msg => {
                     info!("iteration[{i}] receive orders!");
-                    observation.update_open_orders(msg?);
+                    let orders = msg?;
+                    self.order_service.update_open_orders(&orders);
+                    observation.update_open_orders(orders);
+                },
+                // let the policy react to the data going stale
+                recv(market_connection.as_receiver()) -> msg => {
+                    let state = msg?;
+                    warn!("iteration[{i}] market connection: {:?}", state);
+                    observation.update_market_connection(state);
+                    target = true;
+                },
+                recv(status_connection.as_receiver()) -> msg => {
+                    let state = msg?;
+                    warn!("iteration[{i}] status connection: {:?}", state);
+                    observation.update_status_connection(state);
+                    target = true;
                 },
             }
 
+            if let Some(max_position) = self.config.max_position {
+                let position = observation.inventory().position();
+                if position.abs() > max_position {
+                    bail!("risk limit tripped: position {position} exceeds {max_position}");
+                }
+            }
+
             if target {
                 let pending_orders = self
                     .order_service
@@ -90,23 +224,196 @@ where
                     .collect();
                 observation.update_pending_orders(pending_orders);
 
+                observation.update_data_age(
+                    self.clock.now(),
+                    self.market.watermark(),
+                    self.status.watermark(),
+                );
+
                 info!("orderbook:\n{}", observation.orderbook());
                 info!("open_orders:\n{}", observation.open_orders());
+                info!("ticker:\n{:?}", observation.ticker());
                 info!("inventory:\n{:?}", observation.inventory());
+                info!("account:\n{:?}", observation.account());
                 info!("pending_orders:\n{:?}", observation.pending_orders());
+                info!("connection: {:?}", observation.connection());
+                info!(
+                    "age (ms): orderbook {:?}, ticker {:?}, open_orders {:?}",
+                    observation.orderbook_age(),
+                    observation.ticker_age(),
+                    observation.open_orders_age()
+                );
 
                 info!("iteration[{i}] evaluating..");
                 let orders = self.policy.evaluate(&observation);
                 info!("output:\n{:#?}", orders);
 
                 if !orders.is_empty() && !self.config.test {
-                    for order in orders {
-                        self.order_service.submit(order);
+                    if self.order_service.is_backing_off() {
+                        warn!("backing off, holding {} orders", orders.len());
+                    } else {
+                        self.order_service.submit_batch(orders);
                     }
                 }
             }
+            i += 1;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::implements::exchanges::simulated::SimulatedExchange;
+    use crate::implements::writers::OrderbookWriteOp;
+    use crate::pubsub::{PubSub, Subscription};
+    use crate::strategies::dbo::DepthBasedOffering;
+    use crate::types::{
+        ConnectionState, Execution, Inventory, MarketInfo, OfferId, Order, OrderId, OrderResponse,
+        OrderType, Orderbook, Side, Ticker,
+    };
+
+    // counts the heartbeats of the bot
+    #[derive(Clone)]
+    struct Heartbeats(SimulatedExchange, Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Broker for Heartbeats {
+        async fn submit(&self, order: Order) -> OrderResponse {
+            self.0.submit(order).await
+        }
+
+        async fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId> {
+            self.0.cancel_all(side).await
+        }
+
+        fn heartbeat(&self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // never sends an orderbook
+    struct Silent(SimulatedExchange, PubSub<Orderbook>);
+
+    impl Market for Silent {
+        fn info(&self) -> MarketInfo {
+            self.0.info()
+        }
+
+        fn orderbook(&self) -> Subscription<Orderbook> {
+            self.1.subscribe()
+        }
+
+        fn execution(&self) -> Subscription<Execution> {
+            Market::execution(&self.0)
+        }
+
+        fn ticker(&self) -> Subscription<Ticker> {
+            Market::ticker(&self.0)
+        }
+
+        fn connection(&self) -> Subscription<ConnectionState> {
+            Market::connection(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_bot_heartbeat_without_data() {
+        let config = Config {
+            num_iteration: 20,
+            test: true,
+            max_position: None,
+        };
+        let policy = DepthBasedOffering::new(dec!(500), dec!(1000));
+
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+        let count = Arc::new(AtomicUsize::new(0));
+        let orderbook = PubSub::new();
+        let bot = {
+            let market = Silent(exchange.clone(), orderbook.clone());
+            let broker = Heartbeats(exchange.clone(), count.clone());
+            thread::spawn(move || {
+                Bot::new(config, market, exchange, broker, policy)
+                    .with_heartbeat_interval(10)
+                    .run()
+            })
+        };
+
+        // while waiting for the first orderbook
+        thread::sleep(Duration::from_millis(200));
+        let warming = count.load(Ordering::SeqCst);
+        assert!(warming > 1);
+
+        // and once running, without using up the iterations
+        orderbook.publish(Orderbook::for_test());
+        thread::sleep(Duration::from_millis(300));
+        assert!(count.load(Ordering::SeqCst) > warming + 5);
+        assert!(!bot.is_finished());
+
+        orderbook.unsubscribe_all();
+        assert!(bot.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_bot_risk_limit() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+
+        // long beyond the limit with an order resting
+        block_on(exchange.submit(Order::create(
+            OrderType::Market,
+            Side::Bid,
+            dec!(0),
+            dec!(600),
+        )));
+        block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(14500),
+            dec!(100),
+        )));
+
+        let feeder = {
+            let exchange = exchange.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                exchange
+                    .apply(OrderbookWriteOp::update(
+                        1,
+                        Side::Ask,
+                        OfferId::new(160000),
+                        None,
+                        dec!(2000),
+                    ))
+                    .unwrap();
+            })
+        };
+
+        let config = Config {
+            num_iteration: 3,
+            test: false,
+            max_position: Some(dec!(500)),
+        };
+        let policy = DepthBasedOffering::new(dec!(500), dec!(1000));
+        let mut bot = Bot::new(
+            config,
+            exchange.clone(),
+            exchange.clone(),
+            exchange.clone(),
+            policy,
+        );
+        assert!(bot.run().is_err());
+        feeder.join().unwrap();
+
+        assert_eq!(exchange.current_open_orders().orders().count(), 0);
+        assert_eq!(exchange.current_inventory(), Inventory::Position(dec!(600)));
+    }
+}
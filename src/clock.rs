@@ -0,0 +1,63 @@
+use chrono::Utc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Source of the current time in unix milliseconds.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        Utc::now().timestamp_millis().try_into().unwrap()
+    }
+}
+
+/// A clock which only moves when told to. Clones share the same time.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedClock {
+    now: Arc<AtomicU64>,
+}
+
+impl SimulatedClock {
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: Arc::new(AtomicU64::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulated_clock() {
+        let clock = SimulatedClock::new(1000);
+        let shared = clock.clone();
+        assert_eq!(shared.now(), 1000);
+
+        clock.advance(500);
+        assert_eq!(shared.now(), 1500);
+
+        shared.set(42);
+        assert_eq!(clock.now(), 42);
+    }
+}
@@ -0,0 +1,538 @@
+use rust_decimal::prelude::*;
+use thiserror::Error;
+
+use crate::implements::writers::OpenOrdersWriteOp;
+use crate::types::{
+    Amount, Execution, Liquidity, NewOrder, Offer, OfferId, OpenOrders, OrderId, OrderState,
+    OrderType, Orderbook, Price, Side, TradeId, UpdateOrder,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MatchingError {
+    #[error("already exists: {0}")]
+    AlreadyExists(OrderId),
+    #[error("order not found: {0}")]
+    OrderNotFound(OrderId),
+    #[error("invalid amount")]
+    InvalidAmount,
+    #[error("invalid amendment: {0}")]
+    InvalidAmendment(OrderId),
+}
+
+pub type MatchingResult<T> = Result<T, MatchingError>;
+
+/// State transition of a single order, in the order they happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OrderEvent {
+    Accepted {
+        timestamp: u64,
+        order: OrderState,
+    },
+    Filled {
+        timestamp: u64,
+        id: OrderId,
+        price: Price,
+        amount: Amount,
+        liquidity: Liquidity,
+    },
+    Amended {
+        timestamp: u64,
+        id: OrderId,
+        price: Price,
+        amount: Amount, // leaves
+    },
+    Canceled {
+        timestamp: u64,
+        id: OrderId,
+    },
+}
+
+impl OrderEvent {
+    pub fn id(&self) -> &OrderId {
+        match self {
+            Self::Accepted { order, .. } => order.id(),
+            Self::Filled { id, .. } | Self::Amended { id, .. } | Self::Canceled { id, .. } => id,
+        }
+    }
+
+    pub fn to_write_op(&self) -> OpenOrdersWriteOp {
+        match self.clone() {
+            Self::Accepted { timestamp, order } => OpenOrdersWriteOp::create(
+                timestamp,
+                order.id().clone(),
+                order.side(),
+                order.price(),
+                order.amount(),
+            ),
+            Self::Filled {
+                timestamp,
+                id,
+                amount,
+                ..
+            } => OpenOrdersWriteOp::execution(timestamp, id, amount),
+            Self::Amended {
+                timestamp,
+                id,
+                price,
+                amount,
+            } => OpenOrdersWriteOp::update(timestamp, id, None, price, amount),
+            Self::Canceled { timestamp, id } => OpenOrdersWriteOp::delete(timestamp, id),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchOutcome {
+    pub executions: Vec<Execution>,
+    pub events: Vec<OrderEvent>,
+}
+
+impl MatchOutcome {
+    /// Write ops for the open orders of everyone involved.
+    pub fn write_ops(&self) -> Vec<OpenOrdersWriteOp> {
+        self.events.iter().map(|e| e.to_write_op()).collect()
+    }
+
+    /// Write ops for the open orders of the given owner only.
+    pub fn write_ops_for<F>(&self, is_owned: F) -> Vec<OpenOrdersWriteOp>
+    where
+        F: Fn(&OrderId) -> bool,
+    {
+        self.events
+            .iter()
+            .filter(|e| is_owned(e.id()))
+            .map(|e| e.to_write_op())
+            .collect()
+    }
+}
+
+/// An in-process limit order book matching with strict price-time priority.
+#[derive(Clone, Debug, Default)]
+pub struct MatchingEngine {
+    nonce: u64,
+    asks: Vec<OrderState>, // best first, then by arrival
+    bids: Vec<OrderState>,
+}
+
+impl MatchingEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &OrderId) -> Option<&OrderState> {
+        self.asks.iter().chain(&self.bids).find(|o| o.id() == id)
+    }
+
+    pub fn open_orders(&self, timestamp: u64) -> OpenOrders {
+        let orders = self.asks.iter().chain(&self.bids).cloned();
+        OpenOrders::new(timestamp, orders)
+    }
+
+    /// Order by order view of the book, where each offer id is the order id.
+    pub fn orderbook(&self, timestamp: u64) -> Orderbook {
+        let offers = |orders: &[OrderState]| -> Vec<Offer> {
+            orders
+                .iter()
+                .map(|o| Offer::new(OfferId::new(o.id()), o.price(), o.amount()))
+                .collect()
+        };
+        Orderbook::new(timestamp, offers(&self.asks), offers(&self.bids))
+    }
+
+    /// Submits an order with a generated id.
+    pub fn submit(&mut self, timestamp: u64, order: NewOrder) -> MatchingResult<MatchOutcome> {
+        let id = OrderId::new(format!("m{}", self.next_nonce()));
+        self.submit_with_id(timestamp, id, order)
+    }
+
+    pub fn submit_with_id(
+        &mut self,
+        timestamp: u64,
+        id: OrderId,
+        order: NewOrder,
+    ) -> MatchingResult<MatchOutcome> {
+        if self.get(&id).is_some() {
+            return Err(MatchingError::AlreadyExists(id));
+        }
+        if order.amount() <= Amount::zero() {
+            return Err(MatchingError::InvalidAmount);
+        }
+
+        let limit = match order.order_type() {
+            OrderType::Limit => Some(order.price()),
+            OrderType::Market => None,
+        };
+        let state = OrderState::new(id, order.order_side(), order.price(), order.amount());
+
+        let mut outcome = MatchOutcome::default();
+        outcome.events.push(OrderEvent::Accepted {
+            timestamp,
+            order: state.clone(),
+        });
+        self.execute(timestamp, state, limit, &mut outcome);
+        Ok(outcome)
+    }
+
+    pub fn cancel(&mut self, timestamp: u64, id: &OrderId) -> MatchingResult<MatchOutcome> {
+        self.remove(id)
+            .ok_or_else(|| MatchingError::OrderNotFound(id.clone()))?;
+
+        let events = vec![OrderEvent::Canceled {
+            timestamp,
+            id: id.clone(),
+        }];
+        Ok(MatchOutcome {
+            executions: vec![],
+            events,
+        })
+    }
+
+    /// Changes price and leaves amount. Priority is kept only when the amount is reduced
+    /// at the same price, otherwise the order is re-queued and may match right away.
+    pub fn amend(&mut self, timestamp: u64, order: UpdateOrder) -> MatchingResult<MatchOutcome> {
+        let id = order.id().clone();
+        let new_order = order.new_order();
+        let current = self
+            .get(&id)
+            .ok_or_else(|| MatchingError::OrderNotFound(id.clone()))?;
+
+        if new_order.order_type() != OrderType::Limit || new_order.order_side() != current.side() {
+            return Err(MatchingError::InvalidAmendment(id));
+        }
+        if new_order.amount() <= Amount::zero() {
+            return Err(MatchingError::InvalidAmount);
+        }
+
+        let (price, amount) = (new_order.price(), new_order.amount());
+        let mut outcome = MatchOutcome::default();
+        outcome.events.push(OrderEvent::Amended {
+            timestamp,
+            id: id.clone(),
+            price,
+            amount,
+        });
+
+        if order.keeps_priority(current) {
+            let resting = self.find_mut(&id).unwrap();
+            resting.amount = amount;
+        } else {
+            let mut state = self.remove(&id).unwrap();
+            state.price = price;
+            state.amount = amount;
+            self.execute(timestamp, state, Some(price), &mut outcome);
+        }
+
+        Ok(outcome)
+    }
+
+    fn execute(
+        &mut self,
+        timestamp: u64,
+        mut taker: OrderState,
+        limit: Option<Price>,
+        outcome: &mut MatchOutcome,
+    ) {
+        let maker_side = taker.side().opposite();
+
+        loop {
+            if taker.amount().is_zero() {
+                return;
+            }
+
+            let makers = match maker_side {
+                Side::Ask => &mut self.asks,
+                Side::Bid => &mut self.bids,
+            };
+            let Some(maker) = makers.first_mut() else {
+                break;
+            };
+            let crosses = limit.map_or(true, |limit| match maker_side {
+                Side::Ask => maker.price() <= limit,
+                Side::Bid => maker.price() >= limit,
+            });
+            if !crosses {
+                break;
+            }
+
+            let price = maker.price();
+            let amount = taker.amount().min(maker.amount());
+            maker.amount -= amount;
+            taker.amount -= amount;
+
+            let maker_id = maker.id().clone();
+            if maker.amount().is_zero() {
+                makers.remove(0);
+            }
+
+            let trade_id = TradeId::new(format!("t{}", self.next_nonce()));
+            outcome.executions.push(Execution::new(
+                timestamp, trade_id, maker_side, price, amount,
+            ));
+            outcome.events.push(OrderEvent::Filled {
+                timestamp,
+                id: maker_id,
+                price,
+                amount,
+                liquidity: Liquidity::Maker,
+            });
+            outcome.events.push(OrderEvent::Filled {
+                timestamp,
+                id: taker.id().clone(),
+                price,
+                amount,
+                liquidity: Liquidity::Taker,
+            });
+        }
+
+        if limit.is_some() {
+            self.insert(taker);
+        } else {
+            // market orders never rest
+            outcome.events.push(OrderEvent::Canceled {
+                timestamp,
+                id: taker.id().clone(),
+            });
+        }
+    }
+
+    fn insert(&mut self, state: OrderState) {
+        let (orders, is_behind): (_, fn(Price, Price) -> bool) = match state.side() {
+            Side::Ask => (&mut self.asks, |resting, price| resting <= price),
+            Side::Bid => (&mut self.bids, |resting, price| resting >= price),
+        };
+        let index = orders.partition_point(|o| is_behind(o.price(), state.price()));
+        orders.insert(index, state);
+    }
+
+    fn remove(&mut self, id: &OrderId) -> Option<OrderState> {
+        for orders in [&mut self.asks, &mut self.bids] {
+            if let Some(index) = orders.iter().position(|o| o.id() == id) {
+                return Some(orders.remove(index));
+            }
+        }
+        None
+    }
+
+    fn find_mut(&mut self, id: &OrderId) -> Option<&mut OrderState> {
+        self.asks
+            .iter_mut()
+            .chain(self.bids.iter_mut())
+            .find(|o| o.id() == id)
+    }
+
+    fn next_nonce(&mut self) -> u64 {
+        let nonce = self.nonce;
+        self.nonce += 1;
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::implements::writers::OpenOrdersWriter;
+
+    fn limit(side: Side, price: Price, amount: Amount) -> NewOrder {
+        NewOrder::new(OrderType::Limit, side, price, amount)
+    }
+
+    fn id(id: &str) -> OrderId {
+        OrderId::new(id)
+    }
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        for (i, side, price, amount) in [
+            ("a1", Side::Ask, dec!(101), dec!(10)),
+            ("a2", Side::Ask, dec!(102), dec!(10)),
+            ("a3", Side::Ask, dec!(101), dec!(5)),
+            ("b1", Side::Bid, dec!(99), dec!(10)),
+            ("b2", Side::Bid, dec!(100), dec!(10)),
+        ] {
+            engine
+                .submit_with_id(0, id(i), limit(side, price, amount))
+                .unwrap();
+        }
+        engine
+    }
+
+    #[test]
+    fn test_matching_engine_price_time_priority() {
+        let mut engine = engine();
+        let ids = |book: &Orderbook| {
+            (
+                book.asks().map(|o| o.id().to_string()).collect::<Vec<_>>(),
+                book.bids().map(|o| o.id().to_string()).collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            ids(&engine.orderbook(0)),
+            (
+                vec!["a1".into(), "a3".into(), "a2".into()],
+                vec!["b2".into(), "b1".into()]
+            )
+        );
+
+        // sweeps a1, a3 and part of a2, the rest rests at 102
+        let outcome = engine
+            .submit_with_id(10, id("t1"), limit(Side::Bid, dec!(102), dec!(20)))
+            .unwrap();
+        let trades: Vec<_> = outcome
+            .executions
+            .iter()
+            .map(|e| (e.maker_side(), e.price(), e.amount()))
+            .collect();
+        assert_eq!(
+            trades,
+            vec![
+                (Side::Ask, dec!(101), dec!(10)),
+                (Side::Ask, dec!(101), dec!(5)),
+                (Side::Ask, dec!(102), dec!(5)),
+            ]
+        );
+        assert_eq!(engine.get(&id("a2")).unwrap().amount(), dec!(5));
+        assert!(engine.get(&id("t1")).is_none());
+
+        // market sell walks the bids and drops what is left
+        let outcome = engine
+            .submit_with_id(
+                20,
+                id("t2"),
+                NewOrder::new(OrderType::Market, Side::Ask, dec!(0), dec!(25)),
+            )
+            .unwrap();
+        assert_eq!(outcome.executions.len(), 2);
+        assert!(outcome
+            .executions
+            .iter()
+            .all(|e| e.maker_side() == Side::Bid));
+        assert_eq!(
+            outcome.events.last(),
+            Some(&OrderEvent::Canceled {
+                timestamp: 20,
+                id: id("t2")
+            })
+        );
+        assert_eq!(engine.orderbook(20).bids().count(), 0);
+    }
+
+    #[test]
+    fn test_matching_engine_amend_and_cancel() {
+        let mut engine = engine();
+        engine
+            .submit_with_id(0, id("a4"), limit(Side::Ask, dec!(101), dec!(5)))
+            .unwrap();
+
+        // reducing keeps priority, increasing loses it
+        engine
+            .amend(
+                1,
+                UpdateOrder::new(id("a1"), limit(Side::Ask, dec!(101), dec!(4))),
+            )
+            .unwrap();
+        engine
+            .amend(
+                2,
+                UpdateOrder::new(id("a3"), limit(Side::Ask, dec!(101), dec!(6))),
+            )
+            .unwrap();
+        let asks: Vec<_> = engine
+            .orderbook(2)
+            .asks()
+            .map(|o| (o.id().to_string(), o.amount()))
+            .collect();
+        assert_eq!(
+            asks,
+            vec![
+                ("a1".into(), dec!(4)),
+                ("a4".into(), dec!(5)),
+                ("a3".into(), dec!(6)),
+                ("a2".into(), dec!(10)),
+            ]
+        );
+
+        // repricing through the book trades as a taker
+        let outcome = engine
+            .amend(
+                3,
+                UpdateOrder::new(id("b2"), limit(Side::Bid, dec!(101), dec!(10))),
+            )
+            .unwrap();
+        assert_eq!(
+            outcome
+                .executions
+                .iter()
+                .map(|e| e.amount())
+                .sum::<Amount>(),
+            dec!(10)
+        );
+        assert!(engine.get(&id("b2")).is_none());
+        assert!(engine.get(&id("a4")).is_none());
+        assert_eq!(engine.get(&id("a3")).unwrap().amount(), dec!(5));
+
+        assert_eq!(
+            engine.amend(
+                4,
+                UpdateOrder::new(id("b1"), limit(Side::Ask, dec!(99), dec!(1)))
+            ),
+            Err(MatchingError::InvalidAmendment(id("b1")))
+        );
+
+        engine.cancel(5, &id("b1")).unwrap();
+        assert_eq!(
+            engine.cancel(5, &id("b1")),
+            Err(MatchingError::OrderNotFound(id("b1")))
+        );
+        assert_eq!(engine.orderbook(5).bids().count(), 0);
+    }
+
+    #[test]
+    fn test_matching_engine_write_ops() {
+        let mut engine = MatchingEngine::new();
+        let mut open_orders = OpenOrders::new(0, vec![]);
+        let mut apply = |outcome: MatchOutcome| {
+            let mut writer = OpenOrdersWriter::new(&mut open_orders);
+            for op in outcome.write_ops_for(|id| id.to_string().starts_with("ours")) {
+                writer.apply(op).unwrap();
+            }
+        };
+
+        apply(
+            engine
+                .submit_with_id(1, id("ours-1"), limit(Side::Bid, dec!(100), dec!(10)))
+                .unwrap(),
+        );
+        apply(
+            engine
+                .submit_with_id(2, id("theirs-1"), limit(Side::Ask, dec!(100), dec!(4)))
+                .unwrap(),
+        );
+        apply(
+            engine
+                .amend(
+                    3,
+                    UpdateOrder::new(id("ours-1"), limit(Side::Bid, dec!(99), dec!(5))),
+                )
+                .unwrap(),
+        );
+        apply(
+            engine
+                .submit_with_id(4, id("ours-2"), limit(Side::Ask, dec!(105), dec!(1)))
+                .unwrap(),
+        );
+
+        assert_eq!(
+            open_orders,
+            OpenOrders::new(
+                4,
+                vec![
+                    OrderState::new(id("ours-1"), Side::Bid, dec!(99), dec!(5)),
+                    OrderState::new(id("ours-2"), Side::Ask, dec!(105), dec!(1)),
+                ]
+            )
+        );
+    }
+}
@@ -1 +1,2 @@
+pub mod matching_engine;
 pub mod order_service;
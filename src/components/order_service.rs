@@ -1,15 +1,19 @@
-use chrono::Utc;
 use log::*;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use tokio::runtime::Runtime;
 use tokio::time::Duration;
 
+use crate::clock::{Clock, SystemClock};
 use crate::interfaces::Broker;
-use crate::types::Order;
+use crate::types::{ClientOrderId, OpenOrders, Order, OrderId, OrderResponse, Side};
 
 const EXPIRES_MS: u64 = 20_000;
 const GC_TICK_MS: u64 = 1_000;
+const DRAIN_TICK_MS: u64 = 10;
+const DRAIN_TIMEOUT_MS: u64 = 1_000;
+const BACKOFF_MIN_MS: u64 = 500;
+const BACKOFF_MAX_MS: u64 = 30_000;
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PendingId(u64);
@@ -31,6 +35,7 @@ pub struct PendingOrder {
     timestamp: u64,
     id: PendingId,
     order: Order,
+    response: Option<OrderResponse>,
 }
 
 impl PendingOrder {
@@ -39,6 +44,7 @@ impl PendingOrder {
             timestamp,
             id,
             order,
+            response: None,
         }
     }
 
@@ -58,12 +64,79 @@ impl PendingOrder {
         let Self { order, .. } = self;
         order
     }
+
+    /// The broker's answer, while waiting for the open orders to reflect it.
+    pub fn response(&self) -> Option<&OrderResponse> {
+        self.response.as_ref()
+    }
+
+    /// Whether `open_orders` already shows the effect of this order. New orders are matched
+    /// by their client id even before the broker answers.
+    pub fn is_reflected_in(&self, open_orders: &OpenOrders) -> bool {
+        let accepted = match &self.response {
+            Some(OrderResponse::Accept(id, _)) => Some(id),
+            _ => None,
+        };
+
+        match &self.order {
+            Order::New(order) => open_orders.orders().any(|o| {
+                Some(o.id()) == accepted
+                    || (order.client_id().is_some() && o.client_id() == order.client_id())
+            }),
+            Order::Update(order) => {
+                accepted.is_some()
+                    && open_orders
+                        .orders()
+                        .find(|o| o.id() == order.id())
+                        .map_or(true, |o| {
+                            o.price() == order.new_order().price()
+                                && o.amount() <= order.new_order().amount()
+                        })
+            }
+            Order::Cancel(order) => {
+                accepted.is_some() && !open_orders.orders().any(|o| o.id() == order.id())
+            }
+        }
+    }
+}
+
+/// Doubling pause after the broker throttles us, lifted by the next accept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Throttle {
+    delay_ms: u64,
+    until: u64,
+}
+
+impl Throttle {
+    // a batch throttled as a whole counts once
+    fn update(&mut self, responses: &[(PendingId, OrderResponse)], now: u64) {
+        let throttled = responses
+            .iter()
+            .any(|(_, r)| matches!(r, OrderResponse::Reject(reason) if reason.is_throttled()));
+        if throttled {
+            self.delay_ms = (self.delay_ms * 2).clamp(BACKOFF_MIN_MS, BACKOFF_MAX_MS);
+            self.until = now + self.delay_ms;
+            warn!("throttled, backing off for {}ms", self.delay_ms);
+        } else if responses
+            .iter()
+            .any(|(_, r)| matches!(r, OrderResponse::Accept(..)))
+        {
+            *self = Self::default();
+        }
+    }
 }
 
-pub struct OrderService<B> {
+/// Sends orders without blocking and tracks them as pending until the open orders reflect
+/// them, or they are rejected or expire. Orders whose outcome is unknown stay pending until
+/// the open orders show them or they expire.
+pub struct OrderService<B, C = SystemClock> {
     nonce: u64,
+    session: u64,
     broker: Arc<B>,
+    clock: C,
     pendings: Arc<RwLock<Vec<PendingOrder>>>,
+    open_orders: Arc<RwLock<Option<OpenOrders>>>,
+    throttle: Arc<Mutex<Throttle>>,
     rt: Runtime,
 }
 
@@ -72,6 +145,16 @@ where
     B: Broker + Send + Sync + 'static,
 {
     pub fn start(broker: B) -> Self {
+        Self::start_with_clock(broker, SystemClock)
+    }
+}
+
+impl<B, C> OrderService<B, C>
+where
+    B: Broker + Send + Sync + 'static,
+    C: Clock + Clone + 'static,
+{
+    pub fn start_with_clock(broker: B, clock: C) -> Self {
         let broker = Arc::new(broker);
         let pendings = Arc::new(RwLock::new(Vec::<PendingOrder>::new()));
 
@@ -79,61 +162,451 @@ where
         let rt = Runtime::new().unwrap();
         rt.spawn({
             let pendings = pendings.clone();
+            let clock = clock.clone();
             async move {
                 let mut interval = tokio::time::interval(Duration::from_millis(GC_TICK_MS));
                 loop {
                     interval.tick().await;
-                    {
-                        let mut guard = pendings.write().unwrap();
-                        let now: u64 = Utc::now().timestamp_millis().try_into().unwrap();
-
-                        let prev = guard.len();
-                        guard.retain(|po| po.timestamp() + EXPIRES_MS > now);
-
-                        debug!("gc: {} -> {}", prev, guard.len());
-                    }
+                    collect_garbage(&pendings, clock.now());
                 }
             }
         });
 
         Self {
             nonce: 0,
+            session: clock.now(),
             broker,
+            clock,
             pendings,
+            open_orders: Arc::new(RwLock::new(None)),
+            throttle: Arc::new(Mutex::new(Throttle::default())),
             rt,
         }
     }
 
     pub fn submit(&mut self, order: Order) {
-        let timestamp: u64 = Utc::now().timestamp_millis().try_into().unwrap();
-        let id = PendingId(self.nonce);
-        let pending_order = PendingOrder::new(timestamp, id, order.clone());
-        self.nonce += 1;
+        let (id, order) = self.register(order);
 
         self.rt.spawn({
             let broker = self.broker.clone();
             let pendings = self.pendings.clone();
+            let open_orders = self.open_orders.clone();
+            let throttle = self.throttle.clone();
+            let clock = self.clock.clone();
             async move {
-                let id = pending_order.id();
-                {
-                    let mut guard = pendings.write().unwrap();
-                    guard.push(pending_order);
-                }
-
                 debug!("{id:?} send: {order:?}");
                 let response = broker.submit(order).await;
                 debug!("{id:?} recv: {response:?}");
 
-                {
-                    let mut guard = pendings.write().unwrap();
-                    guard.retain(|po| po.id() != id);
-                }
+                let responses = vec![(id, response)];
+                throttle.lock().unwrap().update(&responses, clock.now());
+                answer(&pendings, &open_orders, responses);
+            }
+        });
+    }
+
+    /// Submits `orders` in a single batch, each tracked as pending like a single order.
+    pub fn submit_batch(&mut self, orders: Vec<Order>) {
+        let (ids, orders): (Vec<_>, Vec<_>) =
+            orders.into_iter().map(|order| self.register(order)).unzip();
+
+        self.rt.spawn({
+            let broker = self.broker.clone();
+            let pendings = self.pendings.clone();
+            let open_orders = self.open_orders.clone();
+            let throttle = self.throttle.clone();
+            let clock = self.clock.clone();
+            async move {
+                debug!("{ids:?} send: {orders:?}");
+                let responses = broker.submit_batch(orders).await;
+                debug!("{ids:?} recv: {responses:?}");
+
+                let responses: Vec<_> = ids.into_iter().zip(responses).collect();
+                throttle.lock().unwrap().update(&responses, clock.now());
+                answer(&pendings, &open_orders, responses);
             }
         });
     }
 
+    /// Whether the broker recently throttled us, so new orders should be held back.
+    pub fn is_backing_off(&self) -> bool {
+        self.clock.now() < self.throttle.lock().unwrap().until
+    }
+
+    /// Settles the pending orders which `open_orders` reflects.
+    pub fn update_open_orders(&self, open_orders: &OpenOrders) {
+        let mut guard = self.pendings.write().unwrap();
+        guard.retain(|po| !po.is_reflected_in(open_orders));
+        *self.open_orders.write().unwrap() = Some(open_orders.clone());
+    }
+
+    // registered before sending so the order is visible right away
+    fn register(&mut self, order: Order) -> (PendingId, Order) {
+        let id = PendingId(self.nonce);
+        self.nonce += 1;
+
+        // unique across restarts, as long as they are a millisecond apart
+        let order = match order {
+            Order::New(new_order) if new_order.client_id().is_none() => {
+                let client_id = ClientOrderId::new(format!("mm-{}-{}", self.session, id.0));
+                new_order.with_client_id(client_id).into()
+            }
+            order => order,
+        };
+
+        let mut guard = self.pendings.write().unwrap();
+        guard.push(PendingOrder::new(self.clock.now(), id, order.clone()));
+        (id, order)
+    }
+
+    /// Cancels all open orders, or only those on `side`, right away. Orders in flight may
+    /// still come to rest, so once they are answered, or `DRAIN_TIMEOUT_MS` later on the clock,
+    /// the open orders are cancelled once more.
+    pub fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId> {
+        self.rt.block_on(async {
+            let mut canceled = self.broker.cancel_all(side).await;
+            if !self.is_in_flight() {
+                return canceled;
+            }
+
+            // bounded by ticks as well, in case the clock stands still
+            let deadline = self.clock.now() + DRAIN_TIMEOUT_MS;
+            for _ in 0..DRAIN_TIMEOUT_MS / DRAIN_TICK_MS {
+                if !self.is_in_flight() || self.clock.now() >= deadline {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(DRAIN_TICK_MS)).await;
+            }
+            canceled.extend(self.broker.cancel_all(side).await);
+            canceled
+        })
+    }
+
+    fn is_in_flight(&self) -> bool {
+        self.pendings
+            .read()
+            .unwrap()
+            .iter()
+            .any(|po| po.response.is_none())
+    }
+
+    pub fn heartbeat(&self) {
+        self.broker.heartbeat();
+    }
+
+    pub fn disarm(&self) {
+        self.rt.block_on(self.broker.disarm());
+    }
+
+    /// Drops pending orders older than `EXPIRES_MS` at the clock's current time.
+    pub fn gc(&self) {
+        collect_garbage(&self.pendings, self.clock.now());
+    }
+
     pub fn get_pending_orders(&self) -> Vec<PendingOrder> {
         let guard = self.pendings.read().unwrap();
         (*guard).clone()
     }
 }
+
+// rejected orders are done, accepted ones and those which may have gone through wait for the
+// open orders unless already reflected
+fn answer(
+    pendings: &RwLock<Vec<PendingOrder>>,
+    open_orders: &RwLock<Option<OpenOrders>>,
+    responses: Vec<(PendingId, OrderResponse)>,
+) {
+    let mut guard = pendings.write().unwrap();
+    for (id, response) in responses {
+        if let Some(index) = guard.iter().position(|po| po.id() == id) {
+            match &response {
+                OrderResponse::Accept(..) => guard[index].response = Some(response),
+                OrderResponse::Reject(reason) if reason.is_outcome_unknown() => {
+                    warn!("{id:?} {reason}");
+                    guard[index].response = Some(response);
+                }
+                OrderResponse::Reject(reason) => {
+                    info!("{id:?} rejected: {reason}");
+                    guard.remove(index);
+                }
+            }
+        }
+    }
+
+    if let Some(open_orders) = &*open_orders.read().unwrap() {
+        guard.retain(|po| !po.is_reflected_in(open_orders));
+    }
+}
+
+fn collect_garbage(pendings: &RwLock<Vec<PendingOrder>>, now: u64) {
+    let mut guard = pendings.write().unwrap();
+
+    let prev = guard.len();
+    guard.retain(|po| po.timestamp() + EXPIRES_MS > now);
+
+    debug!("gc: {} -> {}", prev, guard.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_trait::async_trait;
+    use rust_decimal_macros::dec;
+
+    use crate::clock::SimulatedClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::types::{NewOrder, OrderState, OrderType, RejectReason};
+
+    // never responds, so submitted orders stay pending until expired
+    struct SilentBroker;
+
+    #[async_trait]
+    impl Broker for SilentBroker {
+        async fn submit(&self, _order: Order) -> OrderResponse {
+            futures::future::pending().await
+        }
+
+        async fn cancel_all(&self, _side: Option<Side>) -> Vec<OrderId> {
+            futures::future::pending().await
+        }
+    }
+
+    // accepts everything as order "x"
+    struct AcceptingBroker;
+
+    #[async_trait]
+    impl Broker for AcceptingBroker {
+        async fn submit(&self, order: Order) -> OrderResponse {
+            let client_id = match order {
+                Order::New(new_order) => new_order.client_id().cloned(),
+                _ => None,
+            };
+            OrderResponse::Accept(OrderId::new("x"), client_id)
+        }
+
+        async fn cancel_all(&self, _side: Option<Side>) -> Vec<OrderId> {
+            Vec::new()
+        }
+    }
+
+    // never answers orders, counting the cancels
+    struct CancelCountingBroker(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Broker for CancelCountingBroker {
+        async fn submit(&self, _order: Order) -> OrderResponse {
+            futures::future::pending().await
+        }
+
+        async fn cancel_all(&self, _side: Option<Side>) -> Vec<OrderId> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Vec::new()
+        }
+    }
+
+    // rejects everything for the same reason
+    struct RejectingBroker(RejectReason);
+
+    #[async_trait]
+    impl Broker for RejectingBroker {
+        async fn submit(&self, _order: Order) -> OrderResponse {
+            OrderResponse::Reject(self.0.clone())
+        }
+
+        async fn cancel_all(&self, _side: Option<Side>) -> Vec<OrderId> {
+            Vec::new()
+        }
+    }
+
+    fn answered<B, C>(service: &OrderService<B, C>) -> bool
+    where
+        B: Broker + Send + Sync + 'static,
+        C: Clock + Clone + 'static,
+    {
+        for _ in 0..100 {
+            let pendings = service.get_pending_orders();
+            if pendings.iter().all(|po| po.response().is_some()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn test_order_service_expires_pending_orders() {
+        let clock = SimulatedClock::new(1_000);
+        let mut service = OrderService::start_with_clock(SilentBroker, clock.clone());
+
+        let order = NewOrder::new(OrderType::Limit, Side::Bid, dec!(100), dec!(1));
+        service.submit(order.clone().into());
+        clock.advance(5_000);
+        service.submit(order.clone().into());
+
+        // new orders are given client ids
+        let order = |client_id: &str| -> Order {
+            let client_id = ClientOrderId::new(client_id);
+            order.clone().with_client_id(client_id).into()
+        };
+        assert_eq!(
+            service.get_pending_orders(),
+            vec![
+                PendingOrder::new(1_000, PendingId(0), order("mm-1000-0")),
+                PendingOrder::new(6_000, PendingId(1), order("mm-1000-1")),
+            ]
+        );
+
+        clock.set(1_000 + EXPIRES_MS - 1);
+        service.gc();
+        assert_eq!(service.get_pending_orders().len(), 2);
+
+        clock.set(1_000 + EXPIRES_MS);
+        service.gc();
+        assert_eq!(
+            service.get_pending_orders(),
+            vec![PendingOrder::new(6_000, PendingId(1), order("mm-1000-1"))]
+        );
+
+        clock.advance(5_000);
+        service.gc();
+        assert!(service.get_pending_orders().is_empty());
+    }
+
+    #[test]
+    fn test_order_service_tracks_amends() {
+        let clock = SimulatedClock::new(1_000);
+        let mut service = OrderService::start_with_clock(SilentBroker, clock);
+
+        let new_order = NewOrder::new(OrderType::Limit, Side::Bid, dec!(101), dec!(1));
+        let amend = Order::update(OrderId::new("a"), new_order);
+        service.submit(amend.clone());
+
+        assert_eq!(
+            service.get_pending_orders(),
+            vec![PendingOrder::new(1_000, PendingId(0), amend)]
+        );
+    }
+
+    #[test]
+    fn test_order_service_settles_on_open_orders() {
+        let clock = SimulatedClock::new(1_000);
+        let mut service = OrderService::start_with_clock(AcceptingBroker, clock.clone());
+        let state = OrderState::new(OrderId::new("x"), Side::Bid, dec!(100), dec!(1));
+
+        // accepted, but not open yet
+        service.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(100),
+            dec!(1),
+        ));
+        assert!(answered(&service));
+        assert_eq!(service.get_pending_orders().len(), 1);
+
+        service.update_open_orders(&OpenOrders::new(1_000, vec![]));
+        assert_eq!(service.get_pending_orders().len(), 1);
+
+        let client_id = ClientOrderId::new("mm-1000-0");
+        let open_orders = OpenOrders::new(1_000, vec![state.clone().with_client_id(client_id)]);
+        service.update_open_orders(&open_orders);
+        assert!(service.get_pending_orders().is_empty());
+
+        // canceled once gone from the open orders
+        service.submit(Order::cancel(OrderId::new("x")));
+        assert!(answered(&service));
+        assert_eq!(service.get_pending_orders().len(), 1);
+
+        service.update_open_orders(&OpenOrders::new(1_000, vec![]));
+        assert!(service.get_pending_orders().is_empty());
+    }
+
+    #[test]
+    fn test_order_service_settles_before_answer() {
+        let clock = SimulatedClock::new(1_000);
+        let mut service = OrderService::start_with_clock(SilentBroker, clock);
+
+        service.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(100),
+            dec!(1),
+        ));
+        let state = OrderState::new(OrderId::new("x"), Side::Bid, dec!(100), dec!(1))
+            .with_client_id(ClientOrderId::new("mm-1000-0"));
+        service.update_open_orders(&OpenOrders::new(1_000, vec![state]));
+        assert!(service.get_pending_orders().is_empty());
+    }
+
+    #[test]
+    fn test_order_service_keeps_orders_of_unknown_outcome() {
+        let order = Order::create(OrderType::Limit, Side::Bid, dec!(100), dec!(1));
+
+        let clock = SimulatedClock::new(1_000);
+        let broker = RejectingBroker(RejectReason::Network("timed out".to_string()));
+        let mut service = OrderService::start_with_clock(broker, clock.clone());
+        service.submit(order.clone());
+        assert!(answered(&service));
+        assert_eq!(service.get_pending_orders().len(), 1);
+
+        let mut service =
+            OrderService::start_with_clock(RejectingBroker(RejectReason::InvalidPrice), clock);
+        service.submit(order);
+        assert!(answered(&service));
+        assert!(service.get_pending_orders().is_empty());
+    }
+
+    #[test]
+    fn test_order_service_backs_off_when_throttled() {
+        let order = Order::create(OrderType::Limit, Side::Bid, dec!(100), dec!(1));
+
+        let clock = SimulatedClock::new(1_000);
+        let broker = RejectingBroker(RejectReason::RateLimited);
+        let mut service = OrderService::start_with_clock(broker, clock.clone());
+        assert!(!service.is_backing_off());
+
+        service.submit(order.clone());
+        assert!(answered(&service));
+        assert!(service.is_backing_off());
+        clock.advance(BACKOFF_MIN_MS);
+        assert!(!service.is_backing_off());
+
+        // doubled while still throttled
+        service.submit_batch(vec![order.clone(), order]);
+        assert!(answered(&service));
+        clock.advance(BACKOFF_MIN_MS);
+        assert!(service.is_backing_off());
+        clock.advance(BACKOFF_MIN_MS);
+        assert!(!service.is_backing_off());
+    }
+
+    #[test]
+    fn test_order_service_cancel_all_with_orders_in_flight() {
+        let clock = SimulatedClock::new(1_000);
+        let cancels = Arc::new(AtomicUsize::new(0));
+        let mut service =
+            OrderService::start_with_clock(CancelCountingBroker(cancels.clone()), clock.clone());
+
+        // nothing in flight, a single cancel
+        service.cancel_all(None);
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+
+        // the order never gets answered, the second cancel waits for the clock only
+        service.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(100),
+            dec!(1),
+        ));
+        let advance = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            clock.advance(DRAIN_TIMEOUT_MS);
+        });
+        service.cancel_all(Some(Side::Bid));
+        advance.join().unwrap();
+        assert_eq!(cancels.load(Ordering::SeqCst), 3);
+    }
+}
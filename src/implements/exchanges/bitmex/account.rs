@@ -0,0 +1,121 @@
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+use super::parser::AccountUpdate;
+use crate::types::{AccountState, Amount};
+
+/// Folds the `position`, `margin` and `wallet` streams into the state of the account in the
+/// currency the instrument settles in, so a flat account has a state as well.
+#[derive(Debug)]
+pub(super) struct AccountTracker {
+    currency: String,
+    states: HashMap<String, AccountState>,
+}
+
+impl AccountTracker {
+    pub fn new(currency: impl Into<String>) -> Self {
+        Self {
+            currency: currency.into(),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Applies `update`, returning the new state if it concerns the settlement currency.
+    pub fn apply(&mut self, update: AccountUpdate) -> Option<AccountState> {
+        let state = self
+            .states
+            .entry(update.currency.clone())
+            .or_insert_with(|| AccountState::new(0, &update.currency, Amount::zero()));
+
+        if let Some(timestamp) = update.timestamp {
+            state.timestamp = timestamp;
+        }
+        if let Some(position) = update.position {
+            state.position = position;
+            // left as they were, unless the row sets them again
+            if position.is_zero() {
+                state.entry_price = None;
+                state.liquidation_price = None;
+            }
+        }
+        state.wallet_balance = update.wallet_balance.or(state.wallet_balance);
+        state.margin_balance = update.margin_balance.or(state.margin_balance);
+        state.available_margin = update.available_margin.or(state.available_margin);
+        state.leverage = update.leverage.or(state.leverage);
+        state.entry_price = update.entry_price.or(state.entry_price);
+        state.liquidation_price = update.liquidation_price.or(state.liquidation_price);
+        state.unrealised_pnl = update.unrealised_pnl.or(state.unrealised_pnl);
+
+        (update.currency == self.currency).then(|| state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    fn update(currency: &str) -> AccountUpdate {
+        AccountUpdate {
+            currency: currency.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_account_tracker() {
+        let mut tracker = AccountTracker::new("XBt");
+
+        // a flat account, with balances in other currencies left out
+        let wallet = AccountUpdate {
+            timestamp: Some(1),
+            wallet_balance: Some(dec!(100000000)),
+            ..update("XBt")
+        };
+        let mut expected = AccountState::new(1, "XBt", dec!(0));
+        expected.wallet_balance = Some(dec!(100000000));
+        assert_eq!(tracker.apply(wallet), Some(expected.clone()));
+        let margin = AccountUpdate {
+            available_margin: Some(dec!(5000)),
+            ..update("USDt")
+        };
+        assert_eq!(tracker.apply(margin), None);
+
+        let position = AccountUpdate {
+            symbol: Some("XBTUSD".to_string()),
+            timestamp: Some(2),
+            position: Some(dec!(100)),
+            entry_price: Some(dec!(16000.5)),
+            liquidation_price: Some(dec!(14600)),
+            ..update("XBt")
+        };
+        expected.timestamp = 2;
+        expected.position = dec!(100);
+        expected.entry_price = Some(dec!(16000.5));
+        expected.liquidation_price = Some(dec!(14600));
+        assert_eq!(tracker.apply(position), Some(expected.clone()));
+
+        let margin = AccountUpdate {
+            timestamp: Some(3),
+            available_margin: Some(dec!(90000000)),
+            ..update("XBt")
+        };
+        expected.timestamp = 3;
+        expected.available_margin = Some(dec!(90000000));
+        assert_eq!(tracker.apply(margin), Some(expected.clone()));
+
+        // flat again
+        let position = AccountUpdate {
+            symbol: Some("XBTUSD".to_string()),
+            timestamp: Some(4),
+            position: Some(dec!(0)),
+            ..update("XBt")
+        };
+        expected.timestamp = 4;
+        expected.position = dec!(0);
+        expected.entry_price = None;
+        expected.liquidation_price = None;
+        assert_eq!(tracker.apply(position), Some(expected));
+    }
+}
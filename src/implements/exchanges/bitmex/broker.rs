@@ -1,24 +1,75 @@
+use anyhow::Result;
 use log::*;
 
 use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
 
-use bitmex::rest::{BitMEXRest, DeleteOrderRequest, OrdType, PostOrderRequest, Side as RawSide};
+use reqwest::Method;
+use rust_decimal::Decimal;
 
+use bitmex::rest::{DeleteOrderRequest, OrdType, PostOrderRequest, Side as RawSide};
+
+use super::dead_mans_switch::{DeadMansSwitch, DeadMansSwitchConfig};
+use super::environment::BitMEXEnvironment;
+use super::parser;
+use super::transport::{RestClient, RestError};
 use crate::apikey::ApiKey;
+use crate::clock::{Clock, SystemClock};
 use crate::interfaces::Broker;
-use crate::types::{CancelOrder, NewOrder, Order, OrderId, OrderResponse, OrderType, Side};
+use crate::types::{
+    CancelOrder, NewOrder, Order, OrderId, OrderResponse, OrderType, RejectReason, Side,
+    UpdateOrder,
+};
 
 pub struct BitMEXBroker {
-    bm: BitMEXRest,
+    bm: RestClient,
+    symbol: String,
+    switch: Option<DeadMansSwitch>,
 }
 
 impl BitMEXBroker {
-    pub fn connect(apikey: &ApiKey) -> Self {
-        std::env::set_var("BITMEX_TESTNET", "1");
+    pub fn connect(environment: &BitMEXEnvironment, apikey: &ApiKey, symbol: &str) -> Result<Self> {
+        Self::connect_with_clock(environment, apikey, symbol, SystemClock)
+    }
 
-        let bm = BitMEXRest::with_credential(apikey.key(), apikey.secret());
+    pub fn connect_with_clock<C>(
+        environment: &BitMEXEnvironment,
+        apikey: &ApiKey,
+        symbol: &str,
+        clock: C,
+    ) -> Result<Self>
+    where
+        C: Clock + 'static,
+    {
+        environment.validate(apikey)?;
+        info!("BitMEX broker on {}: {}", environment, symbol);
 
-        Self { bm }
+        let bm = RestClient::new(environment, apikey).with_clock(clock);
+
+        Ok(Self {
+            bm,
+            symbol: symbol.to_string(),
+            switch: None,
+        })
+    }
+
+    /// Keeps the exchange's cancel-all-after timer armed while `heartbeat` is called, so all
+    /// our orders are canceled if the bot dies or hangs.
+    pub fn connect_with_dead_mans_switch(
+        environment: &BitMEXEnvironment,
+        apikey: &ApiKey,
+        symbol: &str,
+        config: DeadMansSwitchConfig,
+    ) -> Result<Self> {
+        let broker = Self::connect(environment, apikey, symbol)?;
+        info!("BitMEX dead man's switch: {:?}", config);
+
+        let switch = DeadMansSwitch::start(broker.bm.clone(), config);
+        Ok(Self {
+            switch: Some(switch),
+            ..broker
+        })
     }
 }
 
@@ -27,38 +78,267 @@ impl Broker for BitMEXBroker {
     async fn submit(&self, order: Order) -> OrderResponse {
         match order {
             Order::New(new_order) => {
-                let req = build_new_order_request(new_order);
-                match self.bm.request(req).await {
-                    Ok(response) => {
-                        let id = OrderId::new(response.order_id);
-                        OrderResponse::Accept(id)
-                    }
-                    Err(e) => {
-                        error!("{:?}", e);
-                        OrderResponse::Reject
-                    }
+                let req = match build_new_order_request(new_order, &self.symbol) {
+                    Ok(req) => req,
+                    Err(reason) => return OrderResponse::Reject(reason),
+                };
+                let response = self
+                    .bm
+                    .request::<_, parser::Order>(Method::POST, "/order", &req);
+                match response.await {
+                    Ok(response) => parser::parse_order_response(response),
+                    Err(e) => reject(e),
+                }
+            }
+            Order::Update(update_order) => {
+                let req = match build_amend_order_request(update_order) {
+                    Ok(req) => req,
+                    Err(reason) => return OrderResponse::Reject(reason),
+                };
+                let response = self
+                    .bm
+                    .request::<_, parser::Order>(Method::PUT, "/order", &req);
+                match response.await {
+                    Ok(response) => parser::parse_order_response(response),
+                    Err(e) => reject(e),
                 }
             }
             Order::Cancel(cancel_order) => {
                 let req = build_cancel_order_request(cancel_order);
-                match self.bm.request(req).await {
-                    Ok(response) => {
-                        let id = OrderId::new(response.first().unwrap().order_id);
-                        OrderResponse::Accept(id)
+                let response =
+                    self.bm
+                        .request::<_, Vec<parser::Order>>(Method::DELETE, "/order", &req);
+                match response.await {
+                    Ok(response) => match response.into_iter().next() {
+                        Some(response) => parser::parse_order_response(response),
+                        // nothing was canceled
+                        None => OrderResponse::Reject(RejectReason::OrderNotFound),
+                    },
+                    Err(e) => reject(e),
+                }
+            }
+        }
+    }
+
+    // one bulk request per kind of order, its replies matched to the orders by id
+    async fn submit_batch(&self, orders: Vec<Order>) -> Vec<OrderResponse> {
+        // left out of the reply, so it may or may not have been placed
+        let missing =
+            OrderResponse::Reject(RejectReason::Network("not in the response".to_string()));
+        let mut responses = vec![missing; orders.len()];
+
+        let (mut news, mut amends, mut cancels) = (Vec::new(), Vec::new(), Vec::new());
+        let mut untagged = Vec::new();
+        for (i, order) in orders.into_iter().enumerate() {
+            match order {
+                Order::New(new_order) => match new_order.client_id() {
+                    Some(id) => {
+                        let id = id.to_string();
+                        match build_new_order_request(new_order, &self.symbol) {
+                            Ok(req) => news.push(((i, id), req)),
+                            Err(reason) => responses[i] = OrderResponse::Reject(reason),
+                        }
                     }
-                    Err(e) => {
-                        error!("{:?}", e);
-                        OrderResponse::Reject
+                    // nothing to tell it apart by in a bulk reply
+                    None => untagged.push((i, Order::New(new_order))),
+                },
+                Order::Update(update_order) => {
+                    let id = update_order.id().to_string();
+                    match build_amend_order_request(update_order) {
+                        Ok(req) => amends.push(((i, id), req)),
+                        Err(reason) => responses[i] = OrderResponse::Reject(reason),
+                    }
+                }
+                Order::Cancel(cancel_order) => {
+                    let id = cancel_order.id().to_string();
+                    cancels.push(((i, id.clone()), id))
+                }
+            }
+        }
+
+        let (new_ids, news): (Vec<_>, Vec<_>) = news.into_iter().unzip();
+        let (amend_ids, amends): (Vec<_>, Vec<_>) = amends.into_iter().unzip();
+        let (cancel_ids, cancels): (Vec<_>, Vec<_>) = cancels.into_iter().unzip();
+
+        let create = (!news.is_empty()).then_some(BulkOrderRequest { orders: news });
+        let amend = (!amends.is_empty()).then_some(BulkOrderRequest { orders: amends });
+        let cancel = (!cancels.is_empty()).then(|| build_cancel_orders_request(cancels));
+
+        let (created, amended, canceled, submitted) = futures::join!(
+            self.request_orders(Method::POST, "/order/bulk", create.as_ref()),
+            self.request_orders(Method::PUT, "/order/bulk", amend.as_ref()),
+            self.request_orders(Method::DELETE, "/order", cancel.as_ref()),
+            futures::future::join_all(
+                untagged
+                    .into_iter()
+                    .map(|(i, order)| async move { (i, self.submit(order).await) })
+            ),
+        );
+
+        for (ids, answered, key) in [
+            (new_ids, created, ReplyKey::ClientOrderId),
+            (amend_ids, amended, ReplyKey::OrderId),
+            (cancel_ids, canceled, ReplyKey::OrderId),
+        ] {
+            match answered {
+                Ok(answered) => match_replies(&mut responses, ids, answered, key),
+                Err(reason) => {
+                    for (i, _) in ids {
+                        responses[i] = OrderResponse::Reject(reason.clone());
                     }
                 }
             }
         }
+        for (i, response) in submitted {
+            responses[i] = response;
+        }
+        responses
+    }
+
+    fn heartbeat(&self) {
+        if let Some(switch) = &self.switch {
+            switch.heartbeat();
+        }
+    }
+
+    async fn disarm(&self) {
+        if let Some(switch) = &self.switch {
+            if let Err(e) = switch.disarm().await {
+                error!("{:?}", e);
+            }
+        }
+    }
+
+    async fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId> {
+        let req = build_cancel_all_orders_request(&self.symbol, side);
+        let response = self
+            .bm
+            .request::<_, Vec<parser::Order>>(Method::DELETE, "/order/all", &req);
+        match response.await {
+            Ok(response) => response
+                .into_iter()
+                .map(|order| OrderId::new(order.order_id))
+                .collect(),
+            Err(e) => {
+                error!("{:?}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl BitMEXBroker {
+    /// The orders in the response, or the reason the whole request failed. Empty requests
+    /// are skipped.
+    async fn request_orders<T>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        req: Option<&T>,
+    ) -> Result<Vec<parser::Order>, RejectReason>
+    where
+        T: Serialize + Sync,
+    {
+        let req = match req {
+            Some(req) => req,
+            None => return Ok(Vec::new()),
+        };
+        let response = self
+            .bm
+            .request::<_, Vec<parser::Order>>(method, endpoint, req);
+        response.await.map_err(|e| {
+            error!("{}", e);
+            reject_reason(&e)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ReplyKey {
+    ClientOrderId,
+    OrderId,
+}
+
+/// Answers the orders of a bulk request, as `(index, id)`, with the rows of the reply carrying
+/// the same id, since a reply may be short or in another order. Orders left out of the
+/// reply keep their response.
+fn match_replies(
+    responses: &mut [OrderResponse],
+    mut ids: Vec<(usize, String)>,
+    answered: Vec<parser::Order>,
+    key: ReplyKey,
+) {
+    for order in answered {
+        let id = match key {
+            ReplyKey::ClientOrderId => order.cl_ord_id.as_deref(),
+            ReplyKey::OrderId => Some(order.order_id.as_str()),
+        };
+        match id.and_then(|id| ids.iter().position(|(_, requested)| requested == id)) {
+            Some(position) => {
+                let (i, _) = ids.remove(position);
+                responses[i] = parser::parse_order_response(order);
+            }
+            None => warn!("unexpected order in the response: {}", order.order_id),
+        }
+    }
+}
+
+fn reject(e: RestError) -> OrderResponse {
+    error!("{}", e);
+    OrderResponse::Reject(reject_reason(&e))
+}
+
+/// Classifies a failed request by its status and the message BitMEX sent along.
+pub(super) fn reject_reason(e: &RestError) -> RejectReason {
+    let (status, message) = match e {
+        RestError::Status { status, message } => (*status, message),
+        RestError::Transport(e) => return RejectReason::Network(e.to_string()),
+        RestError::Json(e) => return RejectReason::Other(e.to_string()),
+    };
+
+    let lowercase = message.to_lowercase();
+    match status {
+        429 => RejectReason::RateLimited,
+        503 => RejectReason::Overloaded,
+        502 | 504 => RejectReason::Network(message.clone()),
+        404 => RejectReason::OrderNotFound,
+        _ if lowercase.contains("insufficient available balance") => {
+            RejectReason::InsufficientMargin
+        }
+        _ if lowercase.contains("invalid orderid") || lowercase.contains("not found") => {
+            RejectReason::OrderNotFound
+        }
+        _ if lowercase.contains("ticksize") || lowercase.contains("invalid price") => {
+            RejectReason::InvalidPrice
+        }
+        _ if lowercase.contains("orderqty") || lowercase.contains("lotsize") => {
+            RejectReason::InvalidSize
+        }
+        _ => RejectReason::Other(message.clone()),
+    }
+}
+
+/// Price and whole number of contracts of an order, or why BitMEX could not take them.
+fn price_and_qty<Q: TryFrom<Decimal>>(order: &NewOrder) -> Result<(f64, Q), RejectReason> {
+    let price = order
+        .price()
+        .try_into()
+        .map_err(|_| RejectReason::InvalidPrice)?;
+    if !order.amount().fract().is_zero() {
+        return Err(RejectReason::InvalidSize);
     }
+    let qty = order
+        .amount()
+        .try_into()
+        .map_err(|_| RejectReason::InvalidSize)?;
+    Ok((price, qty))
 }
 
-pub fn build_new_order_request(order: NewOrder) -> PostOrderRequest {
-    let price = order.price().try_into().unwrap();
-    let order_qty = order.amount().try_into().unwrap();
+pub fn build_new_order_request(
+    order: NewOrder,
+    symbol: &str,
+) -> Result<PostOrderRequest, RejectReason> {
+    let (price, order_qty) = price_and_qty(&order)?;
 
     let side = match order.order_side() {
         Side::Ask => RawSide::Sell,
@@ -70,15 +350,15 @@ pub fn build_new_order_request(order: NewOrder) -> PostOrderRequest {
         OrderType::Market => OrdType::Market,
     };
 
-    PostOrderRequest {
-        symbol: "XBTUSD".to_string(),
+    Ok(PostOrderRequest {
+        symbol: symbol.to_string(),
         side: Some(side),
         simple_order_qty: None,
         order_qty: Some(order_qty),
         price: Some(price),
         display_qty: None,
         stop_px: None,
-        cl_ord_id: None,
+        cl_ord_id: order.client_id().map(|id| id.to_string()),
         cl_ord_link_id: None,
         peg_offset_value: None,
         peg_price_type: None,
@@ -87,7 +367,26 @@ pub fn build_new_order_request(order: NewOrder) -> PostOrderRequest {
         exec_inst: None,
         contingency_type: None,
         text: None,
-    }
+    })
+}
+
+/// Body of `PUT /order`, which the bitmex crate does not provide.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmendOrderRequest {
+    #[serde(rename = "orderID")]
+    pub order_id: String,
+    pub price: f64,
+    pub leaves_qty: i64,
+}
+
+pub fn build_amend_order_request(order: UpdateOrder) -> Result<AmendOrderRequest, RejectReason> {
+    let (price, leaves_qty) = price_and_qty(order.new_order())?;
+    Ok(AmendOrderRequest {
+        order_id: order.id().to_string(),
+        price,
+        leaves_qty,
+    })
 }
 
 pub fn build_cancel_order_request(order: CancelOrder) -> DeleteOrderRequest {
@@ -97,3 +396,176 @@ pub fn build_cancel_order_request(order: CancelOrder) -> DeleteOrderRequest {
         ..Default::default()
     }
 }
+
+pub fn build_cancel_orders_request(ids: Vec<String>) -> DeleteOrderRequest {
+    DeleteOrderRequest {
+        order_id: Some(ids.into()),
+        ..Default::default()
+    }
+}
+
+/// Body of `POST /order/bulk` and `PUT /order/bulk`.
+#[derive(Clone, Debug, Serialize)]
+pub struct BulkOrderRequest<T> {
+    pub orders: Vec<T>,
+}
+
+/// Body of `DELETE /order/all`.
+#[derive(Clone, Debug, Serialize)]
+pub struct CancelAllOrdersRequest {
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Value>,
+}
+
+pub fn build_cancel_all_orders_request(symbol: &str, side: Option<Side>) -> CancelAllOrdersRequest {
+    let filter = side.map(|side| match side {
+        Side::Ask => json!({ "side": RawSide::Sell }),
+        Side::Bid => json!({ "side": RawSide::Buy }),
+    });
+    CancelAllOrdersRequest {
+        symbol: symbol.to_string(),
+        filter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::ClientOrderId;
+
+    #[test]
+    fn test_cancel_all_orders_request() {
+        let req = build_cancel_all_orders_request("XBTUSD", None);
+        assert_eq!(
+            serde_json::to_value(req).unwrap(),
+            json!({ "symbol": "XBTUSD" })
+        );
+
+        let req = build_cancel_all_orders_request("XBTUSD", Some(Side::Ask));
+        assert_eq!(
+            serde_json::to_value(req).unwrap(),
+            json!({ "symbol": "XBTUSD", "filter": { "side": "Sell" } })
+        );
+
+        let req = build_cancel_orders_request(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            serde_json::to_value(req).unwrap()["orderID"],
+            json!(["a", "b"])
+        );
+    }
+
+    #[test]
+    fn test_order_request() {
+        use rust_decimal_macros::dec;
+
+        let order = |amount| NewOrder::new(OrderType::Limit, Side::Bid, dec!(17000.5), amount);
+        let req = build_new_order_request(order(dec!(100)), "XBTUSD").unwrap();
+        assert_eq!((req.price, req.order_qty), (Some(17000.5), Some(100)));
+
+        let rejected = |amount| build_new_order_request(order(amount), "XBTUSD").unwrap_err();
+        assert_eq!(rejected(dec!(0.5)), RejectReason::InvalidSize);
+        assert_eq!(rejected(Decimal::MAX), RejectReason::InvalidSize);
+
+        let update = UpdateOrder::new(OrderId::new("a"), order(dec!(100.5)));
+        assert_eq!(
+            build_amend_order_request(update).unwrap_err(),
+            RejectReason::InvalidSize
+        );
+    }
+
+    #[test]
+    fn test_match_replies() {
+        let order = |id: &str, cl_ord_id: &str| -> parser::Order {
+            serde_json::from_value(json!({
+                "timestamp": "2023-01-01T00:00:00.000Z",
+                "orderID": id,
+                "clOrdID": cl_ord_id,
+                "ordStatus": "New"
+            }))
+            .unwrap()
+        };
+        let accept = |id: &str, cl_ord_id: &str| {
+            OrderResponse::Accept(OrderId::new(id), Some(ClientOrderId::new(cl_ord_id)))
+        };
+        let missing = OrderResponse::Reject(RejectReason::Network("missing".to_string()));
+
+        // reordered and short, with an order we did not ask for
+        let mut responses = vec![missing.clone(); 4];
+        let ids = vec![
+            (0, "mm-1-0".to_string()),
+            (2, "mm-1-1".to_string()),
+            (3, "mm-1-2".to_string()),
+        ];
+        let answered = vec![
+            order("b", "mm-1-1"),
+            order("x", "other"),
+            order("a", "mm-1-0"),
+        ];
+        match_replies(&mut responses, ids, answered, ReplyKey::ClientOrderId);
+        assert_eq!(
+            responses,
+            vec![
+                accept("a", "mm-1-0"),
+                missing.clone(),
+                accept("b", "mm-1-1"),
+                missing.clone()
+            ]
+        );
+
+        let mut responses = vec![missing.clone(); 2];
+        let ids = vec![(0, "a".to_string()), (1, "b".to_string())];
+        match_replies(
+            &mut responses,
+            ids,
+            vec![order("b", "mm-1-1")],
+            ReplyKey::OrderId,
+        );
+        assert_eq!(responses, vec![missing, accept("b", "mm-1-1")]);
+    }
+
+    #[test]
+    fn test_reject_reason() {
+        let status = |status: u16, message: &str| RestError::Status {
+            status,
+            message: message.to_string(),
+        };
+
+        assert_eq!(
+            reject_reason(&status(429, "Rate limit exceeded, retry in 1 seconds.")),
+            RejectReason::RateLimited
+        );
+        assert_eq!(
+            reject_reason(&status(
+                503,
+                "The system is currently overloaded. Please try again later."
+            )),
+            RejectReason::Overloaded
+        );
+        assert!(reject_reason(&status(504, "Gateway Timeout")).is_outcome_unknown());
+        assert_eq!(
+            reject_reason(&status(
+                400,
+                "Account has insufficient Available Balance, 1000 XBt required"
+            )),
+            RejectReason::InsufficientMargin
+        );
+        assert_eq!(
+            reject_reason(&status(400, "Invalid price tickSize")),
+            RejectReason::InvalidPrice
+        );
+        assert_eq!(
+            reject_reason(&status(400, "Invalid orderQty")),
+            RejectReason::InvalidSize
+        );
+        assert_eq!(
+            reject_reason(&status(400, "Invalid orderID")),
+            RejectReason::OrderNotFound
+        );
+        assert_eq!(
+            reject_reason(&status(401, "Signature not valid.")),
+            RejectReason::Other("Signature not valid.".to_string())
+        );
+    }
+}
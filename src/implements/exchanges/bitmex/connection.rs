@@ -0,0 +1,623 @@
+use crossbeam_channel::Sender;
+use futures::FutureExt;
+use log::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use tokio::sync::Notify;
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+
+use bitmex::websocket::BitMEXWsMessage;
+
+use super::environment::BitMEXEnvironment;
+use super::parser::{self, ParsedMessage};
+use super::transport::WsClient;
+use crate::apikey::ApiKey;
+use crate::clock::Clock;
+use crate::implements::writers::{
+    OpenOrdersWriteOp, OpenOrdersWriter, OpenOrdersWriterResult, OrderbookWriteOp, OrderbookWriter,
+    OrderbookWriterResult,
+};
+use crate::pubsub::{PubSub, Subscription};
+use crate::types::{ConnectionState, MarketInfo, OpenOrders, Orderbook};
+
+const MIN_BACKOFF_MS: u64 = 500;
+const MAX_BACKOFF_MS: u64 = 30_000;
+
+/// Liveness check of a websocket. A `ping` is sent after `ping_interval_ms` of silence,
+/// and the connection is dropped after `timeout_ms` without any message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub ping_interval_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval_ms: 5_000,
+            timeout_ms: 15_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum StreamEvent {
+    Connection(ConnectionState),
+    // along with the receive timestamp (ms)
+    Message(u64, ParsedMessage),
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Clone, Debug)]
+pub(super) struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_millis(MIN_BACKOFF_MS),
+            Duration::from_millis(MAX_BACKOFF_MS),
+        )
+    }
+}
+
+/// A websocket subscription which survives disconnections.
+pub(super) struct Feed<C> {
+    pub environment: BitMEXEnvironment,
+    pub apikey: Option<ApiKey>,
+    pub topics: Vec<String>,
+    pub symbol: String,
+    /// Prices are snapped to its tick size when given.
+    pub info: Option<Arc<RwLock<MarketInfo>>>,
+    pub clock: C,
+    pub backoff: Backoff,
+    pub heartbeat: HeartbeatConfig,
+    /// Receive time (ms) of the latest orderbook or open orders rows, zero until the first
+    /// ones. Pongs and other tables only keep the connection alive.
+    pub watermark: Arc<AtomicU64>,
+    /// Number of messages dropped as malformed.
+    pub malformed: Arc<AtomicU64>,
+}
+
+impl<C: Clock> Feed<C> {
+    /// Streams parsed messages, reconnecting with backoff whenever the connection is lost,
+    /// falls silent or `resync` is notified. Every connection starts `Resyncing` since deltas
+    /// only make sense on top of the `partial` which follows the subscription, and a delta
+    /// which cannot be parsed is resynced the same way. Returns once `sender` is closed.
+    pub async fn run(mut self, sender: Sender<StreamEvent>, resync: Arc<Notify>) {
+        loop {
+            match self.connect().await {
+                Ok(mut client) => {
+                    // a resync asked for while reconnecting is already done by this connection
+                    let _ = resync.notified().now_or_never();
+
+                    if sender
+                        .send(StreamEvent::Connection(ConnectionState::Resyncing))
+                        .is_err()
+                    {
+                        return;
+                    }
+
+                    let mut last_received = self.clock.now();
+                    let mut heartbeat = interval(Duration::from_millis(
+                        self.heartbeat
+                            .ping_interval_ms
+                            .min(self.heartbeat.timeout_ms),
+                    ));
+                    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+                    let mut closed = ConnectionState::Disconnected;
+                    loop {
+                        let result = tokio::select! {
+                            result = client.next() => result,
+                            _ = heartbeat.tick() => {
+                                let silence = self.clock.now().saturating_sub(last_received);
+                                if silence >= self.heartbeat.timeout_ms {
+                                    warn!("{:?} silent for {}ms", self.topics, silence);
+                                    break;
+                                }
+                                if silence >= self.heartbeat.ping_interval_ms {
+                                    if let Err(e) = client.ping().await {
+                                        error!("{:?}", e);
+                                        break;
+                                    }
+                                }
+                                continue;
+                            }
+                            _ = resync.notified() => {
+                                warn!("resubscribing to {:?}", self.topics);
+                                break;
+                            }
+                        };
+
+                        match result {
+                            Some(Ok(message)) => {
+                                let received = self.clock.now();
+                                last_received = received;
+                                self.backoff.reset();
+                                let tick_size = self
+                                    .info
+                                    .as_ref()
+                                    .map(|info| info.read().unwrap().tick_size());
+                                match parser::parse_message(&message, &self.symbol, tick_size) {
+                                    Ok(Some(parsed)) => {
+                                        if has_data_rows(&parsed) {
+                                            self.watermark.store(received, Ordering::Relaxed);
+                                        }
+                                        let event = StreamEvent::Message(received, parsed);
+                                        if sender.send(event).is_err() {
+                                            return;
+                                        }
+                                    }
+                                    Ok(None) => {}
+                                    Err(e) => {
+                                        let count =
+                                            self.malformed.fetch_add(1, Ordering::Relaxed) + 1;
+                                        error!("malformed message ({} so far): {}", count, e);
+                                        debug!("{:?}", message);
+                                        if is_stateful(&message) {
+                                            warn!("resubscribing to {:?}", self.topics);
+                                            closed = ConnectionState::Resyncing;
+                                            break;
+                                        }
+                                    }
+                                }
+                            }
+                            Some(Err(e)) => error!("{:?}", e),
+                            None => break,
+                        }
+                    }
+
+                    if sender.send(StreamEvent::Connection(closed)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => error!("{:?}", e),
+            }
+
+            let delay = self.backoff.next_delay();
+            warn!("reconnecting to {:?} in {:?}", self.topics, delay);
+            sleep(delay).await;
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<WsClient> {
+        let mut client = WsClient::connect(&self.environment).await?;
+        if let Some(apikey) = &self.apikey {
+            let expires = self.clock.now() / 1000 + 1_000_000_000;
+            client.authenticate(apikey, expires).await?;
+        }
+        client.subscribe(&self.topics).await?;
+        Ok(client)
+    }
+}
+
+// tables kept up to date by deltas, which go out of sync when one is dropped
+fn is_stateful(message: &BitMEXWsMessage) -> bool {
+    match message {
+        BitMEXWsMessage::Table(table) => matches!(table.table.as_str(), "orderBookL2" | "order"),
+        _ => false,
+    }
+}
+
+// the rows of a stream whose freshness we track, by receive time since the rows of a
+// partial carry the time of their own last change
+fn has_data_rows(parsed: &ParsedMessage) -> bool {
+    match parsed {
+        ParsedMessage::Orderbook(ops) => !ops.is_empty(),
+        ParsedMessage::OpenOrders(ops) => !ops.is_empty(),
+        _ => false,
+    }
+}
+
+/// The connection state of a stream, published on changes and on subscription.
+#[derive(Clone)]
+pub(super) struct ConnectionTracker {
+    state: Arc<Mutex<ConnectionState>>,
+    pubsub: PubSub<ConnectionState>,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ConnectionState::Disconnected)),
+            pubsub: PubSub::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> Subscription<ConnectionState> {
+        let guard = self.state.lock().unwrap();
+        let subscription = self.pubsub.subscribe();
+        self.pubsub.publish(*guard);
+        subscription
+    }
+
+    pub fn set(&self, state: ConnectionState) {
+        let mut guard = self.state.lock().unwrap();
+        if *guard != state {
+            info!("connection: {:?} -> {:?}", *guard, state);
+            *guard = state;
+            self.pubsub.publish(state);
+        }
+    }
+}
+
+/// Applies orderbook ops to the synced book. Deltas are dropped until a snapshot arrives.
+pub(super) fn sync_orderbook(
+    orderbook: &mut Option<Orderbook>,
+    ops: Vec<OrderbookWriteOp>,
+) -> OrderbookWriterResult<()> {
+    for op in ops {
+        match op {
+            OrderbookWriteOp::Snapshot(snapshot) => *orderbook = Some(snapshot),
+            op => {
+                if let Some(orderbook) = orderbook.as_mut() {
+                    OrderbookWriter::new(orderbook).apply(op)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies open orders ops to the synced orders. Deltas are dropped until a snapshot arrives.
+pub(super) fn sync_open_orders(
+    open_orders: &mut Option<OpenOrders>,
+    ops: Vec<OpenOrdersWriteOp>,
+) -> OpenOrdersWriterResult<()> {
+    for op in ops {
+        match op {
+            OpenOrdersWriteOp::Snapshot(snapshot) => *open_orders = Some(snapshot),
+            op => {
+                if let Some(open_orders) = open_orders.as_mut() {
+                    OpenOrdersWriter::new(open_orders).apply(op)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crossbeam_channel::{unbounded, Receiver};
+    use futures::sink::SinkExt;
+    use futures::stream::StreamExt;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::clock::SystemClock;
+    use crate::types::{Offer, OfferId, Side};
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_sync_orderbook() {
+        let mut orderbook = None;
+
+        // deltas before the snapshot are dropped
+        let insert = OrderbookWriteOp::create(1, Side::Ask, OfferId::new(1), dec!(101), dec!(10));
+        sync_orderbook(&mut orderbook, vec![insert.clone()]).unwrap();
+        assert_eq!(orderbook, None);
+
+        let snapshot = Orderbook::new(
+            2,
+            vec![],
+            vec![Offer::new(OfferId::new(2), dec!(99), dec!(5))],
+        );
+        let insert = OrderbookWriteOp::create(3, Side::Ask, OfferId::new(1), dec!(101), dec!(10));
+        sync_orderbook(
+            &mut orderbook,
+            vec![OrderbookWriteOp::Snapshot(snapshot), insert],
+        )
+        .unwrap();
+        assert_eq!(orderbook.as_ref().unwrap().asks().count(), 1);
+
+        // a delta for an unknown offer means we have missed messages
+        let update = OrderbookWriteOp::update(4, Side::Bid, OfferId::new(3), None, Some(dec!(1)));
+        assert!(sync_orderbook(&mut orderbook, vec![update]).is_err());
+    }
+
+    fn trade_partial() -> Message {
+        Message::Text(
+            json!({
+                "table": "trade",
+                "action": "partial",
+                "data": []
+            })
+            .to_string(),
+        )
+    }
+
+    fn dummy_feed(environment: BitMEXEnvironment, heartbeat: HeartbeatConfig) -> Feed<SystemClock> {
+        Feed {
+            environment,
+            apikey: None,
+            topics: vec!["trade:XBTUSD".to_string()],
+            symbol: "XBTUSD".to_string(),
+            info: None,
+            clock: SystemClock,
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(10)),
+            heartbeat,
+            watermark: Arc::new(AtomicU64::new(0)),
+            malformed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // connection states as `Ok`, messages as `Err`
+    async fn receive_events(
+        receiver: Receiver<StreamEvent>,
+        n: usize,
+    ) -> (
+        Receiver<StreamEvent>,
+        Vec<Result<ConnectionState, ParsedMessage>>,
+    ) {
+        tokio::task::spawn_blocking(move || {
+            let events = receiver
+                .iter()
+                .take(n)
+                .map(|event| match event {
+                    StreamEvent::Message(_, message) => Err(message),
+                    StreamEvent::Connection(state) => Ok(state),
+                })
+                .collect();
+            (receiver, events)
+        })
+        .await
+        .unwrap()
+    }
+
+    fn reconnected() -> Vec<Result<ConnectionState, ParsedMessage>> {
+        let message = Err(ParsedMessage::Execution(vec![]));
+        vec![
+            Ok(ConnectionState::Resyncing),
+            message.clone(),
+            Ok(ConnectionState::Disconnected),
+            Ok(ConnectionState::Resyncing),
+            message,
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_feed_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        // drops the first connection, then keeps the second one open
+        tokio::spawn(async move {
+            for i in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                ws.next().await.unwrap().unwrap();
+                ws.send(trade_partial()).await.unwrap();
+                if i == 0 {
+                    ws.close(None).await.unwrap();
+                } else {
+                    tokio::spawn(async move { while ws.next().await.is_some() {} });
+                }
+            }
+        });
+
+        let feed = dummy_feed(environment, HeartbeatConfig::default());
+        let (sender, receiver) = unbounded();
+        tokio::spawn(feed.run(sender, Arc::new(Notify::new())));
+
+        let (_, events) = receive_events(receiver, 5).await;
+        assert_eq!(events, reconnected());
+    }
+
+    #[tokio::test]
+    async fn test_feed_ignores_stale_resync() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                ws.next().await.unwrap().unwrap();
+                ws.send(trade_partial()).await.unwrap();
+                tokio::spawn(async move { while ws.next().await.is_some() {} });
+            }
+        });
+
+        // asked for before the connection is up
+        let resync = Arc::new(Notify::new());
+        resync.notify_one();
+
+        let feed = dummy_feed(environment, HeartbeatConfig::default());
+        let (sender, receiver) = unbounded();
+        tokio::spawn(feed.run(sender, resync.clone()));
+
+        let (receiver, events) = receive_events(receiver, 2).await;
+        assert_eq!(events, reconnected()[..2]);
+        let quiet = tokio::task::spawn_blocking(move || {
+            receiver.recv_timeout(std::time::Duration::from_millis(200))
+        });
+        assert!(quiet.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_feed_heartbeat() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        // the first connection goes silent, the second one answers pings
+        let (pinged, pinged_receiver) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let mut pinged = Some(pinged);
+            for i in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                ws.next().await.unwrap().unwrap();
+                ws.send(trade_partial()).await.unwrap();
+
+                let mut pinged = if i == 0 { pinged.take() } else { None };
+                tokio::spawn(async move {
+                    while let Some(Ok(message)) = ws.next().await {
+                        if message != Message::Text("ping".to_string()) {
+                            continue;
+                        }
+                        if i == 0 {
+                            if let Some(pinged) = pinged.take() {
+                                pinged.send(()).unwrap();
+                            }
+                        } else {
+                            ws.send(Message::Text("pong".to_string())).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        let heartbeat = HeartbeatConfig {
+            ping_interval_ms: 20,
+            timeout_ms: 100,
+        };
+        let feed = dummy_feed(environment, heartbeat);
+        let watermark = feed.watermark.clone();
+        let (sender, receiver) = unbounded();
+        tokio::spawn(feed.run(sender, Arc::new(Notify::new())));
+
+        let (receiver, events) = receive_events(receiver, 5).await;
+        assert_eq!(events, reconnected());
+        pinged_receiver.await.unwrap();
+
+        // pongs keep the second connection alive, without counting as data
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(watermark.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_feed_watermark() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        let (next, mut next_receiver) = tokio::sync::mpsc::unbounded_channel::<Message>();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap();
+            while let Some(message) = next_receiver.recv().await {
+                ws.send(message).await.unwrap();
+            }
+        });
+
+        let feed = dummy_feed(environment, HeartbeatConfig::default());
+        let watermark = feed.watermark.clone();
+        let (sender, receiver) = unbounded();
+        tokio::spawn(feed.run(sender, Arc::new(Notify::new())));
+
+        // trades are not what the watermark guards
+        next.send(trade_partial()).unwrap();
+        let (receiver, _) = receive_events(receiver, 2).await;
+        assert_eq!(watermark.load(Ordering::Relaxed), 0);
+
+        let orderbook = json!({
+            "table": "orderBookL2",
+            "action": "partial",
+            "data": [{
+                "symbol": "XBTUSD",
+                "id": 1,
+                "side": "Sell",
+                "size": 100,
+                "price": 17000,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            }]
+        });
+        next.send(Message::Text(orderbook.to_string())).unwrap();
+        receive_events(receiver, 1).await;
+        assert!(watermark.load(Ordering::Relaxed) > 0);
+    }
+
+    #[tokio::test]
+    async fn test_feed_resyncs_on_malformed_delta() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        // a delta without its price, then a fresh partial on the second connection
+        tokio::spawn(async move {
+            for i in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                ws.next().await.unwrap().unwrap();
+                if i == 0 {
+                    let insert = json!({
+                        "table": "orderBookL2",
+                        "action": "insert",
+                        "data": [{
+                            "symbol": "XBTUSD",
+                            "id": 1,
+                            "side": "Sell",
+                            "size": 100,
+                            "timestamp": "2023-01-01T00:00:00.000Z"
+                        }]
+                    });
+                    ws.send(Message::Text(insert.to_string())).await.unwrap();
+                } else {
+                    ws.send(trade_partial()).await.unwrap();
+                }
+                tokio::spawn(async move { while ws.next().await.is_some() {} });
+            }
+        });
+
+        let feed = dummy_feed(environment, HeartbeatConfig::default());
+        let malformed = feed.malformed.clone();
+        let (sender, receiver) = unbounded();
+        tokio::spawn(feed.run(sender, Arc::new(Notify::new())));
+
+        let (_, events) = receive_events(receiver, 4).await;
+        assert_eq!(
+            events,
+            vec![
+                Ok(ConnectionState::Resyncing),
+                Ok(ConnectionState::Resyncing),
+                Ok(ConnectionState::Resyncing),
+                Err(ParsedMessage::Execution(vec![])),
+            ]
+        );
+        assert_eq!(malformed.load(Ordering::Relaxed), 1);
+    }
+}
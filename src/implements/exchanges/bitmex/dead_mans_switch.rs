@@ -0,0 +1,227 @@
+use anyhow::Result;
+use log::*;
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use tokio::runtime::Builder;
+use tokio::sync::{watch, Mutex};
+use tokio::time::Duration;
+
+use super::transport::RestClient;
+use crate::clock::{Clock, SystemClock};
+
+/// Timing of the exchange-side timer which cancels all our orders unless re-armed in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeadMansSwitchConfig {
+    pub timeout_ms: u64,
+    pub rearm_interval_ms: u64,
+}
+
+impl Default for DeadMansSwitchConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 60_000,
+            rearm_interval_ms: 15_000,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CancelAllAfterRequest {
+    timeout: u64,
+}
+
+/// Arms the timer for `timeout_ms`, or disarms it with zero.
+pub(super) async fn cancel_all_after(client: &RestClient, timeout_ms: u64) -> Result<()> {
+    let req = CancelAllAfterRequest {
+        timeout: timeout_ms,
+    };
+    client
+        .request::<_, Value>(Method::POST, "/order/cancelAllAfter", &req)
+        .await?;
+    Ok(())
+}
+
+/// Re-arms the timer from a background thread as long as `heartbeat` was called within the
+/// last `timeout_ms`, so a hung or dead bot gets its orders canceled by the exchange.
+///
+/// The silence is measured in wall time rather than by the client's clock, since the
+/// exchange's timer runs in real time whatever clock the bot is given.
+pub(super) struct DeadMansSwitch {
+    client: RestClient,
+    healthy_at: Arc<AtomicU64>,
+    running: watch::Sender<bool>,
+    // held while talking to the exchange, so no re-arm can follow a disarm
+    requesting: Arc<Mutex<()>>,
+}
+
+impl DeadMansSwitch {
+    pub fn start(client: RestClient, config: DeadMansSwitchConfig) -> Self {
+        let healthy_at = Arc::new(AtomicU64::new(SystemClock.now()));
+        let (running, mut stopped) = watch::channel(true);
+        let requesting = Arc::new(Mutex::new(()));
+
+        thread::spawn({
+            let client = client.clone();
+            let healthy_at = healthy_at.clone();
+            let requesting = requesting.clone();
+            move || {
+                let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+                runtime.block_on(async move {
+                    let rearm_interval = Duration::from_millis(config.rearm_interval_ms);
+                    let mut interval = tokio::time::interval(rearm_interval);
+                    loop {
+                        tokio::select! {
+                            _ = interval.tick() => {
+                                let silence = SystemClock
+                                    .now()
+                                    .saturating_sub(healthy_at.load(Ordering::Relaxed));
+                                if silence >= config.timeout_ms {
+                                    warn!("no heartbeat for {}ms, letting the switch fire", silence);
+                                    continue;
+                                }
+                                let _requesting = requesting.lock().await;
+                                if !*stopped.borrow() {
+                                    break;
+                                }
+                                if let Err(e) = cancel_all_after(&client, config.timeout_ms).await {
+                                    error!("{:?}", e);
+                                }
+                            }
+                            // stopped or dropped
+                            _ = stopped.changed() => break,
+                        }
+                    }
+                });
+            }
+        });
+
+        Self {
+            client,
+            healthy_at,
+            running,
+            requesting,
+        }
+    }
+
+    pub fn heartbeat(&self) {
+        self.healthy_at.store(SystemClock.now(), Ordering::Relaxed);
+    }
+
+    /// Stops re-arming and cancels the timer, for a clean exit.
+    pub async fn disarm(&self) -> Result<()> {
+        let _ = self.running.send(false);
+        let _requesting = self.requesting.lock().await;
+        cancel_all_after(&self.client, 0).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crossbeam_channel::{unbounded, Receiver};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::super::environment::BitMEXEnvironment;
+    use crate::apikey::ApiKey;
+
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+    // stands in for the REST API, recording the body of each request
+    fn serve(runtime: &tokio::runtime::Runtime) -> (BitMEXEnvironment, Receiver<Value>) {
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+        let (sender, receiver) = unbounded();
+
+        runtime.spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = vec![0; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .to_lowercase()
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length: ")?.parse().ok())
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                let _ = sender.send(serde_json::from_str(&body).unwrap());
+
+                let response = "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}";
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (environment, receiver)
+    }
+
+    fn client(environment: &BitMEXEnvironment) -> RestClient {
+        let apikey = ApiKey::new("key".to_string(), "secret".to_string());
+        RestClient::new(environment, &apikey)
+    }
+
+    #[test]
+    fn test_dead_mans_switch_rearms_while_healthy() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (environment, requests) = serve(&runtime);
+
+        let config = DeadMansSwitchConfig {
+            timeout_ms: 1_000,
+            rearm_interval_ms: 20,
+        };
+        let switch = DeadMansSwitch::start(client(&environment), config);
+        for _ in 0..3 {
+            switch.heartbeat();
+            assert_eq!(
+                requests.recv_timeout(TIMEOUT).unwrap(),
+                json!({ "timeout": 1_000 })
+            );
+        }
+
+        runtime.block_on(switch.disarm()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(requests.try_iter().last(), Some(json!({ "timeout": 0 })));
+        assert!(requests
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+    }
+
+    #[test]
+    fn test_dead_mans_switch_fires_without_heartbeat() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (environment, requests) = serve(&runtime);
+
+        let config = DeadMansSwitchConfig {
+            timeout_ms: 100,
+            rearm_interval_ms: 20,
+        };
+        let _switch = DeadMansSwitch::start(client(&environment), config);
+        assert_eq!(
+            requests.recv_timeout(TIMEOUT).unwrap(),
+            json!({ "timeout": 100 })
+        );
+
+        // no more re-arming once silent for the timeout
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let _ = requests.try_iter().count();
+        assert!(requests
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::apikey::ApiKey;
+
+const TESTNET_URL: &str = "https://testnet.bitmex.com";
+const MAINNET_URL: &str = "https://www.bitmex.com";
+
+/// The BitMEX deployment the adapters talk to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BitMEXEnvironment {
+    Testnet,
+    Mainnet,
+    /// Base url of a mock, e.g. `http://127.0.0.1:8080`
+    Custom(String),
+}
+
+impl BitMEXEnvironment {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Testnet => "testnet",
+            Self::Mainnet => "mainnet",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    pub fn rest_url(&self) -> &str {
+        match self {
+            Self::Testnet => TESTNET_URL,
+            Self::Mainnet => MAINNET_URL,
+            Self::Custom(url) => url.trim_end_matches('/'),
+        }
+    }
+
+    pub fn websocket_url(&self) -> String {
+        let rest_url = self.rest_url();
+        let url = match rest_url.split_once("://") {
+            Some(("https", host)) => format!("wss://{host}"),
+            Some((_, host)) => format!("ws://{host}"),
+            None => format!("ws://{rest_url}"),
+        };
+        format!("{url}/realtime")
+    }
+
+    /// Keys are issued per deployment, so mainnet requires a key tagged `mainnet` and a
+    /// tagged key is only accepted by its own deployment. Mocks accept any key.
+    pub fn validate(&self, apikey: &ApiKey) -> Result<()> {
+        match (self, apikey.environment()) {
+            (Self::Custom(_), _) | (Self::Testnet, None) => Ok(()),
+            (environment, Some(tag)) if tag == environment.name() => Ok(()),
+            (environment, tag) => bail!(
+                "apikey for {} cannot be used on BitMEX {}",
+                tag.unwrap_or("an unspecified environment"),
+                environment
+            ),
+        }
+    }
+}
+
+impl fmt::Display for BitMEXEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.rest_url())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apikey(environment: Option<&str>) -> ApiKey {
+        let apikey = ApiKey::new("key".to_string(), "secret".to_string());
+        match environment {
+            Some(environment) => apikey.with_environment(environment),
+            None => apikey,
+        }
+    }
+
+    #[test]
+    fn test_environment_urls() {
+        let testnet = BitMEXEnvironment::Testnet;
+        assert_eq!(testnet.rest_url(), "https://testnet.bitmex.com");
+        assert_eq!(testnet.websocket_url(), "wss://testnet.bitmex.com/realtime");
+
+        let mainnet = BitMEXEnvironment::Mainnet;
+        assert_eq!(mainnet.rest_url(), "https://www.bitmex.com");
+        assert_eq!(mainnet.websocket_url(), "wss://www.bitmex.com/realtime");
+
+        let custom = BitMEXEnvironment::Custom("http://127.0.0.1:8080/".to_string());
+        assert_eq!(custom.rest_url(), "http://127.0.0.1:8080");
+        assert_eq!(custom.websocket_url(), "ws://127.0.0.1:8080/realtime");
+    }
+
+    #[test]
+    fn test_environment_validates_apikey() {
+        let testnet = BitMEXEnvironment::Testnet;
+        assert!(testnet.validate(&apikey(None)).is_ok());
+        assert!(testnet.validate(&apikey(Some("testnet"))).is_ok());
+        assert!(testnet.validate(&apikey(Some("mainnet"))).is_err());
+
+        let mainnet = BitMEXEnvironment::Mainnet;
+        assert!(mainnet.validate(&apikey(None)).is_err());
+        assert!(mainnet.validate(&apikey(Some("testnet"))).is_err());
+        assert!(mainnet.validate(&apikey(Some("mainnet"))).is_ok());
+
+        let custom = BitMEXEnvironment::Custom("http://127.0.0.1:8080".to_string());
+        assert!(custom.validate(&apikey(Some("mainnet"))).is_ok());
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+
+use tokio::runtime::Runtime;
+use tokio::time::Duration;
+
+use super::environment::BitMEXEnvironment;
+use super::parser::{deserialize_decimal, deserialize_decimal_option};
+use super::transport::RestClient;
+use crate::types::MarketInfo;
+
+pub const INSTRUMENT_REFRESH_MS: u64 = 60_000;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Instrument {
+    pub symbol: String,
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub tick_size: Decimal,
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub lot_size: Decimal,
+    // null on some instruments
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub max_order_qty: Option<Decimal>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub max_price: Option<Decimal>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub multiplier: Option<Decimal>,
+    pub settl_currency: String,
+}
+
+impl Instrument {
+    /// Missing limits are left unbounded and a missing multiplier is taken as one unit per
+    /// contract.
+    pub fn to_market_info(&self) -> MarketInfo {
+        MarketInfo {
+            max_order_size: self.max_order_qty.unwrap_or(Decimal::MAX),
+            min_order_size: self.lot_size,
+            lot_size: self.lot_size,
+            max_order_price: self.max_price.unwrap_or(Decimal::MAX),
+            min_order_price: self.tick_size,
+            tick_size: self.tick_size,
+            multiplier: self.multiplier.unwrap_or(Decimal::ONE),
+            settlement_currency: self.settl_currency.clone(),
+        }
+    }
+}
+
+pub(super) async fn fetch_instrument(client: &RestClient, symbol: &str) -> Result<Instrument> {
+    let instruments: Vec<Instrument> = client.get("/instrument", &[("symbol", symbol)]).await?;
+
+    instruments
+        .into_iter()
+        .find(|i| i.symbol == symbol)
+        .ok_or_else(|| anyhow!("instrument not found: {symbol}"))
+}
+
+pub(super) async fn fetch_market_info(client: &RestClient, symbol: &str) -> Result<MarketInfo> {
+    let instrument = fetch_instrument(client, symbol).await?;
+    Ok(instrument.to_market_info())
+}
+
+/// Fetches the market info of `symbol` and keeps it up to date on `runtime`.
+pub(super) fn watch_market_info(
+    runtime: &Runtime,
+    environment: &BitMEXEnvironment,
+    symbol: &str,
+) -> Result<Arc<RwLock<MarketInfo>>> {
+    let client = RestClient::public(environment);
+    let info = runtime
+        .block_on(fetch_market_info(&client, symbol))
+        .context("failed to fetch instrument")?;
+    let info = Arc::new(RwLock::new(info));
+    runtime.spawn(refresh_market_info(
+        client,
+        symbol.to_string(),
+        info.clone(),
+        INSTRUMENT_REFRESH_MS,
+    ));
+    Ok(info)
+}
+
+/// Polls the instrument definition, replacing `info` whenever the exchange changes it.
+pub(super) async fn refresh_market_info(
+    client: RestClient,
+    symbol: String,
+    info: Arc<RwLock<MarketInfo>>,
+    interval_ms: u64,
+) {
+    let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match fetch_market_info(&client, &symbol).await {
+            Ok(latest) => {
+                let mut guard = info.write().unwrap();
+                if *guard != latest {
+                    info!("market info updated:\n{:#?}", latest);
+                    *guard = latest;
+                }
+            }
+            Err(e) => error!("{:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // answers each request with the next body, repeating the last one
+    async fn serve(bodies: Vec<String>) -> RestClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let i = count.fetch_add(1, Ordering::SeqCst);
+                let body = bodies[i.min(bodies.len() - 1)].clone();
+
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        RestClient::public(&BitMEXEnvironment::Custom(format!("http://{addr}")))
+    }
+
+    fn instrument(tick_size: f64) -> String {
+        serde_json::json!([{
+            "symbol": "ETHUSD",
+            "tickSize": tick_size,
+            "lotSize": 1,
+            "maxOrderQty": 10000000,
+            "maxPrice": 1000000,
+            "multiplier": 100,
+            "settlCurrency": "XBt",
+            "state": "Open"
+        }])
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_market_info() {
+        let client = serve(vec![instrument(0.05)]).await;
+
+        let info = fetch_market_info(&client, "ETHUSD").await.unwrap();
+        assert_eq!(
+            info,
+            MarketInfo {
+                max_order_size: dec!(10000000),
+                min_order_size: dec!(1),
+                lot_size: dec!(1),
+                max_order_price: dec!(1000000),
+                min_order_price: dec!(0.05),
+                tick_size: dec!(0.05),
+                multiplier: dec!(100),
+                settlement_currency: "XBt".to_string(),
+            }
+        );
+
+        assert!(fetch_market_info(&client, "XBTUSD").await.is_err());
+    }
+
+    #[test]
+    fn test_missing_limits() {
+        let instrument: Instrument = serde_json::from_value(serde_json::json!({
+            "symbol": "XBTUSDTH23",
+            "tickSize": 0.5,
+            "lotSize": 100,
+            "maxOrderQty": null,
+            "maxPrice": null,
+            "settlCurrency": "USDt"
+        }))
+        .unwrap();
+        let info = instrument.to_market_info();
+        assert_eq!(info.max_order_size, Decimal::MAX);
+        assert_eq!(info.max_order_price, Decimal::MAX);
+        assert_eq!(info.multiplier, Decimal::ONE);
+        assert_eq!(info.tick_size, dec!(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_market_info() {
+        let client = serve(vec![instrument(0.05), instrument(0.1)]).await;
+
+        let info = fetch_market_info(&client, "ETHUSD").await.unwrap();
+        let info = Arc::new(RwLock::new(info));
+        tokio::spawn(refresh_market_info(
+            client,
+            "ETHUSD".to_string(),
+            info.clone(),
+            10,
+        ));
+
+        for _ in 0..100 {
+            if info.read().unwrap().tick_size() == dec!(0.1) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(info.read().unwrap().tick_size(), dec!(0.1));
+        assert_eq!(info.read().unwrap().min_order_price(), dec!(0.1));
+    }
+}
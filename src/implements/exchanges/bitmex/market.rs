@@ -1,38 +1,39 @@
 use anyhow::Result;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::unbounded;
 use log::*;
-use rust_decimal_macros::dec;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::thread;
 
-use futures::sink::SinkExt;
-use futures::stream::StreamExt;
 use tokio::runtime::Runtime;
-
-use bitmex::websocket::{BitMEXWebsocket, Command, Topic};
-
-use super::parser::{self, ParsedMessage};
-use crate::implements::writers::{OrderbookWriteOp, OrderbookWriter, OrderbookWriterResult};
+use tokio::sync::Notify;
+
+use super::connection::{
+    sync_orderbook, Backoff, ConnectionTracker, Feed, HeartbeatConfig, StreamEvent,
+};
+use super::environment::BitMEXEnvironment;
+use super::instrument::watch_market_info;
+use super::parser::ParsedMessage;
+use crate::clock::{Clock, SystemClock};
 use crate::interfaces::Market;
 use crate::pubsub::{PubSub, Subscription};
-use crate::types::{Execution, MarketInfo, Orderbook};
+use crate::types::{ConnectionState, Execution, MarketInfo, Orderbook, Ticker};
 
 pub struct BitMEXMarket {
     _runtime: Runtime,
     _updater: Option<thread::JoinHandle<()>>,
+    info: Arc<RwLock<MarketInfo>>,
     pubsub_orderbook: PubSub<Orderbook>,
     pubsub_execution: PubSub<Execution>,
+    pubsub_ticker: PubSub<Ticker>,
+    connection: ConnectionTracker,
+    watermark: Arc<AtomicU64>,
+    malformed: Arc<AtomicU64>,
 }
 
 impl Market for BitMEXMarket {
     fn info(&self) -> MarketInfo {
-        MarketInfo {
-            max_order_size: dec!(10000000),
-            min_order_size: dec!(100),
-            lot_size: dec!(100),
-            max_order_price: dec!(1000000),
-            min_order_price: dec!(1),
-            tick_size: dec!(0.5),
-        }
+        self.info.read().unwrap().clone()
     }
 
     fn orderbook(&self) -> Subscription<Orderbook> {
@@ -42,99 +43,157 @@ impl Market for BitMEXMarket {
     fn execution(&self) -> Subscription<Execution> {
         self.pubsub_execution.subscribe()
     }
+
+    fn ticker(&self) -> Subscription<Ticker> {
+        self.pubsub_ticker.subscribe()
+    }
+
+    fn connection(&self) -> Subscription<ConnectionState> {
+        self.connection.subscribe()
+    }
+
+    fn watermark(&self) -> Option<u64> {
+        Some(self.watermark.load(Ordering::Relaxed)).filter(|&watermark| watermark > 0)
+    }
 }
 
 impl BitMEXMarket {
-    pub fn connect() -> Self {
-        std::env::set_var("BITMEX_TESTNET", "1");
+    pub fn connect(environment: &BitMEXEnvironment, symbol: &str) -> Result<Self> {
+        Self::connect_with_clock(environment, symbol, SystemClock)
+    }
+
+    pub fn connect_with_clock<C>(
+        environment: &BitMEXEnvironment,
+        symbol: &str,
+        clock: C,
+    ) -> Result<Self>
+    where
+        C: Clock + 'static,
+    {
+        Self::connect_with_heartbeat(environment, symbol, HeartbeatConfig::default(), clock)
+    }
+
+    pub fn connect_with_heartbeat<C>(
+        environment: &BitMEXEnvironment,
+        symbol: &str,
+        heartbeat: HeartbeatConfig,
+        clock: C,
+    ) -> Result<Self>
+    where
+        C: Clock + 'static,
+    {
+        info!("BitMEX market on {}: {}", environment, symbol);
 
         let pubsub_orderbook = PubSub::new();
         let pubsub_execution = PubSub::new();
+        let pubsub_ticker = PubSub::new();
+        let connection = ConnectionTracker::new();
 
         let (sender, receiver) = unbounded();
+        let resync = Arc::new(Notify::new());
+
+        let runtime = Runtime::new()?;
 
-        let runtime = Runtime::new().unwrap();
-        runtime.spawn(start_websocket(sender));
+        let info = watch_market_info(&runtime, environment, symbol)?;
+
+        let feed = market_feed(environment, symbol, heartbeat, clock, Some(info.clone()));
+        let watermark = feed.watermark.clone();
+        let malformed = feed.malformed.clone();
+        runtime.spawn(feed.run(sender, resync.clone()));
 
         let updater = {
             let pubsub_orderbook = pubsub_orderbook.clone();
             let pubsub_execution = pubsub_execution.clone();
+            let pubsub_ticker = pubsub_ticker.clone();
+            let connection = connection.clone();
             thread::spawn(move || {
-                let mut orderbook = receive_orderbook(&receiver).unwrap();
-                pubsub_orderbook.publish(orderbook.clone());
-
-                for parsed in receiver {
-                    match parsed {
-                        ParsedMessage::Orderbook(ops) => {
-                            let mut writer = OrderbookWriter::new(&mut orderbook);
-                            for op in ops {
-                                writer.apply(op).unwrap();
+                // none while stale
+                let mut orderbook: Option<Orderbook> = None;
+                let mut ticker = Ticker::default();
+
+                for event in receiver {
+                    match event {
+                        StreamEvent::Connection(state) => {
+                            orderbook = None;
+                            connection.set(state);
+                        }
+                        StreamEvent::Message(_, ParsedMessage::Orderbook(ops)) => {
+                            if let Err(e) = sync_orderbook(&mut orderbook, ops) {
+                                error!("orderbook out of sync: {:?}", e);
+                                orderbook = None;
+                                connection.set(ConnectionState::Resyncing);
+                                resync.notify_one();
+                            }
+
+                            if let Some(orderbook) = &orderbook {
+                                connection.set(ConnectionState::Synced);
+                                pubsub_orderbook.publish(orderbook.clone());
                             }
-                            pubsub_orderbook.publish(orderbook.clone());
                         }
-                        ParsedMessage::Execution(executions) => {
+                        StreamEvent::Message(_, ParsedMessage::Execution(executions)) => {
                             for execution in executions {
                                 // TODO: maybe too expensive
                                 pubsub_execution.publish(execution);
                             }
                         }
+                        StreamEvent::Message(_, ParsedMessage::Ticker(updates)) => {
+                            if updates.is_empty() {
+                                continue;
+                            }
+                            for update in updates {
+                                update.apply(&mut ticker);
+                            }
+                            pubsub_ticker.publish(ticker.clone());
+                        }
                         _ => {}
                     }
                 }
             })
         };
 
-        Self {
+        Ok(Self {
             _runtime: runtime,
             _updater: Some(updater),
+            info,
             pubsub_orderbook,
             pubsub_execution,
-        }
+            pubsub_ticker,
+            connection,
+            watermark,
+            malformed,
+        })
     }
-}
 
-fn receive_orderbook(receiver: &Receiver<ParsedMessage>) -> OrderbookWriterResult<Orderbook> {
-    loop {
-        if let Some(ParsedMessage::Orderbook(messages)) = receiver.iter().next() {
-            let mut iter = messages.into_iter();
-            while let Some(message) = iter.next() {
-                if let OrderbookWriteOp::Snapshot(mut orderbook) = message {
-                    let mut writer = OrderbookWriter::new(&mut orderbook);
-                    for op in iter {
-                        writer.apply(op)?;
-                    }
-                    return Ok(orderbook);
-                }
-            }
-        }
+    /// Number of messages dropped so far because they could not be parsed.
+    pub fn malformed_messages(&self) -> u64 {
+        self.malformed.load(Ordering::Relaxed)
     }
 }
 
-async fn start_websocket(sender: Sender<ParsedMessage>) -> Result<()> {
-    loop {
-        let mut client = BitMEXWebsocket::new().await.unwrap();
-
-        client
-            .send(Command::Subscribe(vec![
-                Topic::OrderBookL2(Some("XBTUSD".to_string())),
-                Topic::Trade(Some("XBTUSD".to_string())),
-            ]))
-            .await
-            .unwrap();
-
-        while let Some(result) = client.next().await {
-            match result {
-                Ok(message) => {
-                    if let Some(parsed) = parser::parse_message(&message) {
-                        sender.send(parsed).unwrap();
-                    } else {
-                        debug!("parse failed: {:?}", message);
-                    }
-                }
-                Err(e) => {
-                    error!("{:?}", e);
-                }
-            }
-        }
+/// The public orderbook, trade, instrument and funding streams of `symbol`, with prices
+/// snapped to the tick size of `info` when given.
+pub(super) fn market_feed<C: Clock>(
+    environment: &BitMEXEnvironment,
+    symbol: &str,
+    heartbeat: HeartbeatConfig,
+    clock: C,
+    info: Option<Arc<RwLock<MarketInfo>>>,
+) -> Feed<C> {
+    Feed {
+        environment: environment.clone(),
+        apikey: None,
+        topics: vec![
+            format!("orderBookL2:{symbol}"),
+            format!("trade:{symbol}"),
+            format!("instrument:{symbol}"),
+            format!("funding:{symbol}"),
+        ],
+        symbol: symbol.to_string(),
+        info,
+        clock,
+        backoff: Backoff::default(),
+        heartbeat,
+        watermark: Arc::new(AtomicU64::new(0)),
+        malformed: Arc::new(AtomicU64::new(0)),
     }
 }
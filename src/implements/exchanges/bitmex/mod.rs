@@ -1,9 +1,21 @@
+mod account;
+mod connection;
 mod parser;
+mod transport;
 
 pub mod broker;
+pub mod dead_mans_switch;
+pub mod environment;
+pub mod instrument;
 pub mod market;
+pub mod recorder;
 pub mod status;
 
 pub use broker::*;
+pub use connection::HeartbeatConfig;
+pub use dead_mans_switch::DeadMansSwitchConfig;
+pub use environment::*;
+pub use instrument::*;
 pub use market::*;
+pub use recorder::*;
 pub use status::*;
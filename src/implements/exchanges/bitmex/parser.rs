@@ -1,25 +1,109 @@
 use chrono::{DateTime, Utc};
 use log::*;
 use rust_decimal::prelude::*;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Number, Value};
+use std::fmt::Debug;
+use thiserror::Error;
 
-use bitmex::rest::{Position, Side as RawSide, Trade};
+use bitmex::rest::Side as RawSide;
 use bitmex::websocket::{Action, BitMEXWsMessage, TableMessage};
 
 use crate::implements::writers::{OpenOrdersWriteOp, OrderbookWriteOp};
 use crate::types::{
-    Amount, Execution, Offer, OfferId, OpenOrders, OrderId, OrderState, Orderbook, Side, TradeId,
+    Amount, ClientOrderId, Execution, Fill, Liquidity, Offer, OfferId, OpenOrders, OrderId,
+    OrderResponse, OrderState, Orderbook, Price, RejectReason, Side, Ticker, TradeId,
 };
 
+/// Reads a JSON number from its shortest decimal representation instead of doing `f64`
+/// arithmetic on it, so 16999.5 is exactly 16999.5.
+pub(super) fn deserialize_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let number = Number::deserialize(deserializer)?;
+    number_to_decimal(&number).ok_or_else(|| D::Error::custom(format!("not a decimal: {number}")))
+}
+
+pub(super) fn deserialize_decimal_option<'de, D>(
+    deserializer: D,
+) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Number>::deserialize(deserializer)? {
+        Some(number) => number_to_decimal(&number)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("not a decimal: {number}"))),
+        None => Ok(None),
+    }
+}
+
+fn number_to_decimal(number: &Number) -> Option<Decimal> {
+    let text = number.to_string();
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .ok()
+}
+
+/// `price` on the grid of `tick_size`, when known.
+fn snap(price: Decimal, tick_size: Option<Decimal>) -> Result<Decimal, ParseError> {
+    match tick_size {
+        Some(tick_size) if !tick_size.is_zero() => price
+            .checked_div(tick_size)
+            .and_then(|ticks| ticks.round().checked_mul(tick_size))
+            .ok_or_else(|| invalid("price", price)),
+        _ => Ok(price),
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct OrderBookL2 {
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
     pub symbol: String,
     pub id: i64,
     pub side: RawSide,
     pub size: Option<i64>,
-    pub price: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub price: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Trade {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub side: Option<RawSide>,
+    pub size: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub price: Option<Decimal>,
+    #[serde(rename = "trdMatchID")]
+    pub trd_match_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Position {
+    pub symbol: String,
+    pub currency: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(rename = "currentQty")]
+    pub current_qty: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub leverage: Option<Decimal>,
+    #[serde(
+        rename = "avgEntryPrice",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub avg_entry_price: Option<Decimal>,
+    #[serde(
+        rename = "liquidationPrice",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub liquidation_price: Option<Decimal>,
+    #[serde(rename = "unrealisedPnl")]
+    pub unrealised_pnl: Option<i64>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -27,60 +111,262 @@ pub struct Order {
     pub timestamp: DateTime<Utc>,
     #[serde(rename = "orderID")]
     pub order_id: String,
+    #[serde(rename = "clOrdID")]
+    pub cl_ord_id: Option<String>, // empty when not given
+    pub symbol: Option<String>, // may be left out of updates
     #[serde(rename = "ordStatus")]
-    pub ord_status: String,
+    pub ord_status: Option<String>, // left out of amends
     #[serde(rename = "orderQty")]
     pub order_qty: Option<i64>,
-    pub price: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_decimal_option")]
+    pub price: Option<Decimal>,
     #[serde(rename = "leavesQty")]
     pub leaves_qty: Option<i64>,
     #[serde(rename = "cumQty")]
     pub cum_qty: Option<i64>,
     pub side: Option<RawSide>,
+    pub text: Option<String>,
+    pub error: Option<String>, // only on cancels which did not go through
+}
+
+/// Row of the private `execution` table, which reports every change to our orders.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExecutionReport {
+    pub timestamp: DateTime<Utc>,
+    #[serde(rename = "orderID")]
+    pub order_id: String,
+    #[serde(rename = "clOrdID")]
+    pub cl_ord_id: Option<String>,
+    pub symbol: String,
+    pub side: Option<RawSide>,
+    #[serde(rename = "execType")]
+    pub exec_type: Option<String>,
+    #[serde(
+        rename = "lastPx",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub last_px: Option<Decimal>,
+    #[serde(rename = "lastQty")]
+    pub last_qty: Option<i64>,
+    // in the settlement currency, e.g. satoshis for XBt, negative on rebates
+    #[serde(rename = "execComm")]
+    pub exec_comm: Option<i64>,
+    #[serde(rename = "lastLiquidityInd")]
+    pub last_liquidity_ind: Option<String>,
+}
+
+/// Row of the public `instrument` table, leaving out all but the ticker fields.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InstrumentTicker {
+    pub symbol: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(
+        rename = "markPrice",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub mark_price: Option<Decimal>,
+    // the index for perpetuals
+    #[serde(
+        rename = "indicativeSettlePrice",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub indicative_settle_price: Option<Decimal>,
+    #[serde(
+        rename = "fundingRate",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub funding_rate: Option<Decimal>,
+    #[serde(rename = "fundingTimestamp")]
+    pub funding_timestamp: Option<DateTime<Utc>>,
+    #[serde(rename = "openInterest")]
+    pub open_interest: Option<i64>,
+}
+
+/// Row of the public `funding` table, one per funding settled.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Funding {
+    pub symbol: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(
+        rename = "fundingRate",
+        default,
+        deserialize_with = "deserialize_decimal_option"
+    )]
+    pub funding_rate: Option<Decimal>,
+}
+
+/// Row of the private `margin` table, one per currency.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Margin {
+    pub currency: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(rename = "marginBalance")]
+    pub margin_balance: Option<i64>,
+    #[serde(rename = "availableMargin")]
+    pub available_margin: Option<i64>,
+}
+
+/// Row of the private `wallet` table, one per currency.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Wallet {
+    pub currency: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub amount: Option<i64>,
+}
+
+/// The fields of a `position`, `margin` or `wallet` row present in a message, the others
+/// being unchanged. Only position rows have a symbol.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountUpdate {
+    pub currency: String,
+    pub symbol: Option<String>,
+    pub timestamp: Option<u64>,
+    pub position: Option<Amount>,
+    pub wallet_balance: Option<Amount>,
+    pub margin_balance: Option<Amount>,
+    pub available_margin: Option<Amount>,
+    pub leverage: Option<Decimal>,
+    pub entry_price: Option<Decimal>,
+    pub liquidation_price: Option<Decimal>,
+    pub unrealised_pnl: Option<Amount>,
+}
+
+/// The fields of an `instrument` or `funding` row present in a message, the others being
+/// unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickerUpdate {
+    pub timestamp: Option<u64>,
+    pub mark_price: Option<Price>,
+    pub index_price: Option<Price>,
+    pub funding_rate: Option<Decimal>,
+    pub next_funding_time: Option<u64>,
+    pub last_funding_rate: Option<Decimal>,
+    pub open_interest: Option<Amount>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ParsedMessage {
     Orderbook(Vec<OrderbookWriteOp>),
     Execution(Vec<Execution>),
+    Ticker(Vec<TickerUpdate>),
+    Fill(Vec<Fill>),
     OpenOrders(Vec<OpenOrdersWriteOp>),
-    Position(Amount),
+    Account(Vec<AccountUpdate>),
 }
 
-pub fn parse_message(message: &BitMEXWsMessage) -> Option<ParsedMessage> {
-    match message {
-        BitMEXWsMessage::Table(table) => match table.table.as_str() {
-            "orderBookL2" => {
-                let parsed = parse_orderbook_ops(table)?;
-                Some(ParsedMessage::Orderbook(parsed))
-            }
-            "trade" => {
-                let parsed = parse_executions(table)?;
-                Some(ParsedMessage::Execution(parsed))
-            }
-            "order" => {
-                let parsed = parse_open_orders_ops(table)?;
-                Some(ParsedMessage::OpenOrders(parsed))
-            }
-            "position" => {
-                let parsed = parse_position(table)?;
-                Some(ParsedMessage::Position(parsed))
-            }
-            _ => None,
-        },
-        _ => None,
+/// Why a message from the exchange could not be parsed.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A row which does not have the layout of its table.
+    #[error("invalid {table} row: {reason}")]
+    InvalidRow { table: String, reason: String },
+    #[error("missing {0}")]
+    MissingField(&'static str),
+    #[error("invalid {field}: {value}")]
+    InvalidField { field: &'static str, value: String },
+    #[error("unexpected {action} on {table}")]
+    UnexpectedAction { table: String, action: String },
+}
+
+/// The rows of `table`, leaving out those of another symbol than `symbol` before they are
+/// deserialized, so a row we do not care about cannot fail the message. Rows without a
+/// symbol are kept, e.g. order updates which can only refer to orders we already track.
+fn rows<T: DeserializeOwned>(
+    table: &TableMessage<Value>,
+    symbol: Option<&str>,
+) -> Result<Vec<T>, ParseError> {
+    table
+        .data
+        .iter()
+        .filter(
+            |v| match (symbol, v.get("symbol").and_then(Value::as_str)) {
+                (Some(symbol), Some(row_symbol)) => row_symbol == symbol,
+                _ => true,
+            },
+        )
+        .map(|v| {
+            T::deserialize(v).map_err(|e| ParseError::InvalidRow {
+                table: table.table.clone(),
+                reason: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn required<T>(value: Option<T>, field: &'static str) -> Result<T, ParseError> {
+    value.ok_or(ParseError::MissingField(field))
+}
+
+fn invalid(field: &'static str, value: impl Debug) -> ParseError {
+    ParseError::InvalidField {
+        field,
+        value: format!("{value:?}"),
+    }
+}
+
+fn unexpected_action(table: &TableMessage<Value>) -> ParseError {
+    ParseError::UnexpectedAction {
+        table: table.table.clone(),
+        action: format!("{:?}", table.action),
     }
 }
 
-pub fn parse_orderbook_ops(table: &TableMessage<Value>) -> Option<Vec<OrderbookWriteOp>> {
-    let mut data = Vec::new();
-    for v in table.data.clone() {
-        let parsed: OrderBookL2 = serde_json::from_value(v).ok()?;
-        data.push(parsed);
+fn parse_side(side: Option<RawSide>) -> Result<Side, ParseError> {
+    match required(side, "side")? {
+        RawSide::Buy => Ok(Side::Bid),
+        RawSide::Sell => Ok(Side::Ask),
+        side => Err(invalid("side", side)),
     }
+}
+
+fn parse_millis(timestamp: DateTime<Utc>) -> Result<u64, ParseError> {
+    timestamp
+        .timestamp_millis()
+        .try_into()
+        .map_err(|_| invalid("timestamp", timestamp))
+}
+
+/// Parses a message, keeping only the rows of `symbol`, or none when it is not a table we
+/// know. Prices are snapped to `tick_size` when given.
+pub fn parse_message(
+    message: &BitMEXWsMessage,
+    symbol: &str,
+    tick_size: Option<Decimal>,
+) -> Result<Option<ParsedMessage>, ParseError> {
+    let table = match message {
+        BitMEXWsMessage::Table(table) => table,
+        _ => return Ok(None),
+    };
+    let parsed = match table.table.as_str() {
+        "orderBookL2" => ParsedMessage::Orderbook(parse_orderbook_ops(table, symbol, tick_size)?),
+        "trade" => ParsedMessage::Execution(parse_executions(table, symbol, tick_size)?),
+        "instrument" => ParsedMessage::Ticker(parse_instrument(table, symbol)?),
+        "funding" => ParsedMessage::Ticker(parse_funding(table, symbol)?),
+        "execution" => ParsedMessage::Fill(parse_fills(table, symbol, tick_size)?),
+        "order" => ParsedMessage::OpenOrders(parse_open_orders_ops(table, symbol, tick_size)?),
+        "position" => ParsedMessage::Account(parse_position(table, symbol)?.into_iter().collect()),
+        "margin" => ParsedMessage::Account(parse_margin(table)?),
+        "wallet" => ParsedMessage::Account(parse_wallet(table)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(parsed))
+}
 
-    let dt: DateTime<Utc> = data.first()?.timestamp.parse().unwrap();
-    let timestamp: u64 = dt.timestamp_millis().try_into().unwrap();
+pub fn parse_orderbook_ops(
+    table: &TableMessage<Value>,
+    symbol: &str,
+    tick_size: Option<Decimal>,
+) -> Result<Vec<OrderbookWriteOp>, ParseError> {
+    let data: Vec<OrderBookL2> = rows(table, Some(symbol))?;
+
+    let timestamp = match data.first() {
+        Some(o) => parse_millis(o.timestamp)?,
+        None => return Ok(Vec::new()),
+    };
 
     let mut ops = Vec::new();
     match table.action {
@@ -88,20 +374,11 @@ pub fn parse_orderbook_ops(table: &TableMessage<Value>) -> Option<Vec<OrderbookW
             let mut asks = Vec::new();
             let mut bids = Vec::new();
             for o in data {
-                let side = match o.side {
-                    RawSide::Buy => Side::Bid,
-                    RawSide::Sell => Side::Ask,
-                    _ => return None,
-                };
+                let side = parse_side(Some(o.side))?;
+                let price = snap(required(o.price, "price")?, tick_size)?;
+                let size = Decimal::from(required(o.size, "size")?);
 
-                let price = o.price.unwrap();
-                let size = o.size.unwrap();
-
-                let offer = Offer::new(
-                    OfferId::new(o.id),
-                    Decimal::from_f64(price).unwrap(),
-                    Decimal::from_i64(size).unwrap(),
-                );
+                let offer = Offer::new(OfferId::new(o.id), price, size);
 
                 match side {
                     Side::Ask => asks.push(offer),
@@ -117,34 +394,24 @@ pub fn parse_orderbook_ops(table: &TableMessage<Value>) -> Option<Vec<OrderbookW
         }
         Action::Insert => {
             for o in data {
-                let side = match o.side {
-                    RawSide::Buy => Side::Bid,
-                    RawSide::Sell => Side::Ask,
-                    _ => return None,
-                };
-
-                let price = o.price.unwrap();
-                let size = o.size.unwrap();
+                let side = parse_side(Some(o.side))?;
+                let price = snap(required(o.price, "price")?, tick_size)?;
+                let size = Decimal::from(required(o.size, "size")?);
 
                 ops.push(OrderbookWriteOp::create(
                     timestamp,
                     side,
                     OfferId::new(o.id),
-                    Decimal::from_f64(price).unwrap(),
-                    Decimal::from_i64(size).unwrap(),
+                    price,
+                    size,
                 ));
             }
         }
         Action::Update => {
             for o in data {
-                let side = match o.side {
-                    RawSide::Buy => Side::Bid,
-                    RawSide::Sell => Side::Ask,
-                    _ => return None,
-                };
-
-                let price = o.price.map(|p| Decimal::from_f64(p).unwrap());
-                let amount = o.size.map(|s| Decimal::from_i64(s).unwrap());
+                let side = parse_side(Some(o.side))?;
+                let price = o.price.map(|price| snap(price, tick_size)).transpose()?;
+                let amount = o.size.map(Decimal::from);
 
                 ops.push(OrderbookWriteOp::update(
                     timestamp,
@@ -157,11 +424,7 @@ pub fn parse_orderbook_ops(table: &TableMessage<Value>) -> Option<Vec<OrderbookW
         }
         Action::Delete => {
             for o in data {
-                let side = match o.side {
-                    RawSide::Buy => Side::Bid,
-                    RawSide::Sell => Side::Ask,
-                    _ => return None,
-                };
+                let side = parse_side(Some(o.side))?;
                 ops.push(OrderbookWriteOp::delete(
                     timestamp,
                     side,
@@ -171,124 +434,937 @@ pub fn parse_orderbook_ops(table: &TableMessage<Value>) -> Option<Vec<OrderbookW
         }
     }
 
-    Some(ops)
+    Ok(ops)
 }
 
-pub fn parse_executions(table: &TableMessage<Value>) -> Option<Vec<Execution>> {
+pub fn parse_executions(
+    table: &TableMessage<Value>,
+    symbol: &str,
+    tick_size: Option<Decimal>,
+) -> Result<Vec<Execution>, ParseError> {
     let mut executions = Vec::new();
-    for v in table.data.clone() {
-        let parsed: Trade = serde_json::from_value(v).ok()?;
+    for parsed in rows::<Trade>(table, Some(symbol))? {
+        // the side of the taker
+        let maker_side = parse_side(parsed.side)?.opposite();
+        let timestamp = parse_millis(parsed.timestamp)?;
+        let id = TradeId::new(required(parsed.trd_match_id, "trdMatchID")?);
+        let price = snap(required(parsed.price, "price")?, tick_size)?;
+        let amount = Decimal::from(required(parsed.size, "size")?);
+
+        executions.push(Execution::new(timestamp, id, maker_side, price, amount));
+    }
+
+    Ok(executions)
+}
 
-        let maker_side = match parsed.side? {
-            RawSide::Buy => Side::Ask,
-            RawSide::Sell => Side::Bid,
-            _ => return None,
+pub fn parse_instrument(
+    table: &TableMessage<Value>,
+    symbol: &str,
+) -> Result<Vec<TickerUpdate>, ParseError> {
+    if matches!(table.action, Action::Delete) {
+        return Ok(Vec::new());
+    }
+
+    let mut updates = Vec::new();
+    for parsed in rows::<InstrumentTicker>(table, Some(symbol))? {
+        updates.push(TickerUpdate {
+            timestamp: parse_timestamp(parsed.timestamp),
+            mark_price: parsed.mark_price,
+            index_price: parsed.indicative_settle_price,
+            funding_rate: parsed.funding_rate,
+            next_funding_time: parse_timestamp(parsed.funding_timestamp),
+            open_interest: parsed.open_interest.map(Amount::from),
+            ..Default::default()
+        });
+    }
+    Ok(updates)
+}
+
+pub fn parse_funding(
+    table: &TableMessage<Value>,
+    symbol: &str,
+) -> Result<Vec<TickerUpdate>, ParseError> {
+    if matches!(table.action, Action::Delete) {
+        return Ok(Vec::new());
+    }
+
+    let mut updates = Vec::new();
+    for parsed in rows::<Funding>(table, Some(symbol))? {
+        updates.push(TickerUpdate {
+            timestamp: parse_timestamp(parsed.timestamp),
+            last_funding_rate: parsed.funding_rate,
+            ..Default::default()
+        });
+    }
+    Ok(updates)
+}
+
+/// Our own trades, leaving out the other kinds of execution such as new or canceled orders.
+/// Fees are the exchange's own, in the settlement currency as for `AccountState`, since a
+/// fee worked out from the rate depends on whether the contract is linear or inverse.
+pub fn parse_fills(
+    table: &TableMessage<Value>,
+    symbol: &str,
+    tick_size: Option<Decimal>,
+) -> Result<Vec<Fill>, ParseError> {
+    let mut fills = Vec::new();
+    for parsed in rows::<ExecutionReport>(table, Some(symbol))? {
+        if parsed.exec_type.as_deref() != Some("Trade") {
+            continue;
+        }
+
+        let side = parse_side(parsed.side)?;
+        let liquidity = match required(parsed.last_liquidity_ind, "lastLiquidityInd")?.as_str() {
+            "AddedLiquidity" => Liquidity::Maker,
+            "RemovedLiquidity" => Liquidity::Taker,
+            other => return Err(invalid("lastLiquidityInd", other)),
         };
 
-        let timestamp: u64 = parsed.timestamp.timestamp_millis().try_into().unwrap();
-        let id = TradeId::new(parsed.trd_match_id?);
-        let price = Decimal::from_f64(parsed.price?)?;
-        let amount = Decimal::from_i64(parsed.size?)?;
+        let timestamp = parse_millis(parsed.timestamp)?;
+        let client_id = parse_client_order_id(parsed.cl_ord_id.as_deref());
+        let price = snap(required(parsed.last_px, "lastPx")?, tick_size)?;
+        let amount = Decimal::from(required(parsed.last_qty, "lastQty")?);
+        let fee = Decimal::from(required(parsed.exec_comm, "execComm")?);
 
-        executions.push(Execution::new(timestamp, id, maker_side, price, amount));
+        let fill = Fill::new(
+            timestamp,
+            OrderId::new(parsed.order_id),
+            side,
+            price,
+            amount,
+            fee,
+            liquidity,
+        );
+        fills.push(fill.with_client_id(client_id));
     }
 
-    Some(executions)
+    Ok(fills)
 }
 
-pub fn parse_open_orders_ops(table: &TableMessage<Value>) -> Option<Vec<OpenOrdersWriteOp>> {
+pub fn parse_open_orders_ops(
+    table: &TableMessage<Value>,
+    symbol: &str,
+    tick_size: Option<Decimal>,
+) -> Result<Vec<OpenOrdersWriteOp>, ParseError> {
     debug!("{:#?}", table);
     let mut ops = Vec::new();
     match table.action {
         Action::Partial => {
             let mut latest = 0;
             let mut orders = Vec::new();
-            for v in table.data.clone() {
-                let parsed: Order = serde_json::from_value(v).ok()?;
-                let timestamp: u64 = parsed.timestamp.timestamp_millis().try_into().unwrap();
+            for parsed in rows::<Order>(table, Some(symbol))? {
+                let timestamp = parse_millis(parsed.timestamp)?;
                 if timestamp > latest {
                     latest = timestamp;
                 }
 
-                orders.push(parse_order_state(parsed)?);
+                orders.push(parse_order_state(parsed, tick_size)?);
             }
 
             ops.push(OpenOrdersWriteOp::init(OpenOrders::new(latest, orders)));
         }
         Action::Update | Action::Insert => {
-            for v in table.data.clone() {
-                let parsed: Order = serde_json::from_value(v).ok()?;
-                let timestamp: u64 = parsed.timestamp.timestamp_millis().try_into().unwrap();
-                match parsed.ord_status.as_ref() {
-                    "New" => {
-                        let OrderState {
-                            id,
-                            price,
-                            amount,
-                            side,
-                        } = parse_order_state(parsed).unwrap();
-                        ops.push(OpenOrdersWriteOp::create(
-                            timestamp, id, side, price, amount,
-                        ));
-                    }
-                    "Canceled" | "Filled" => {
+            for parsed in rows::<Order>(table, Some(symbol))? {
+                let timestamp = parse_millis(parsed.timestamp)?;
+                match (parsed.ord_status.as_deref(), &table.action) {
+                    (Some("Canceled" | "Filled"), _) => {
                         let id = OrderId::new(parsed.order_id);
                         ops.push(OpenOrdersWriteOp::delete(timestamp, id));
                     }
-                    "PartiallyFilled" => match table.action {
-                        Action::Insert => {
-                            let OrderState {
-                                id,
-                                price,
-                                amount,
-                                side,
-                            } = parse_order_state(parsed).unwrap();
-                            ops.push(OpenOrdersWriteOp::create(
-                                timestamp, id, side, price, amount,
-                            ));
-                        }
-                        Action::Update => {
-                            let id = OrderId::new(parsed.order_id);
-                            let amount = Decimal::from_i64(parsed.leaves_qty.unwrap()).unwrap();
-                            ops.push(OpenOrdersWriteOp::update(timestamp, id, None, None, amount));
-                        }
-                        _ => {
-                            unreachable!()
-                        }
-                    },
+                    (Some("New" | "PartiallyFilled"), Action::Insert) => {
+                        let order = parse_order_state(parsed, tick_size)?;
+                        ops.push(OpenOrdersWriteOp::insert(timestamp, order));
+                    }
+                    // partial fills and amends, which may leave out the status
+                    (_, Action::Update) => {
+                        let id = OrderId::new(parsed.order_id);
+                        let price = parsed
+                            .price
+                            .map(|price| snap(price, tick_size))
+                            .transpose()?;
+                        let amount = parsed.leaves_qty.map(Decimal::from);
+                        ops.push(OpenOrdersWriteOp::update(
+                            timestamp, id, None, price, amount,
+                        ));
+                    }
                     _ => {}
                 }
             }
         }
-        _ => return None,
+        _ => return Err(unexpected_action(table)),
+    }
+
+    Ok(ops)
+}
+
+pub fn parse_client_order_id(cl_ord_id: Option<&str>) -> Option<ClientOrderId> {
+    cl_ord_id
+        .filter(|id| !id.is_empty())
+        .map(ClientOrderId::new)
+}
+
+/// Accepts the order, unless the exchange refused it right away or could not cancel it.
+pub fn parse_order_response(order: Order) -> OrderResponse {
+    if let Some(error) = &order.error {
+        // e.g. "Unable to cancel order due to existing state: Filled"
+        debug!("{}: {}", order.order_id, error);
+        return OrderResponse::Reject(RejectReason::OrderNotFound);
     }
 
-    Some(ops)
+    let text = order.text.clone().unwrap_or_default();
+    match order.ord_status.as_deref() {
+        Some("Canceled") if text.contains("ParticipateDoNotInitiate") => {
+            OrderResponse::Reject(RejectReason::WouldCross)
+        }
+        Some("Rejected") => OrderResponse::Reject(RejectReason::Other(text)),
+        _ => {
+            let client_id = parse_client_order_id(order.cl_ord_id.as_deref());
+            OrderResponse::Accept(OrderId::new(order.order_id), client_id)
+        }
+    }
 }
 
-pub fn parse_order_state(order: Order) -> Option<OrderState> {
+pub fn parse_order_state(
+    order: Order,
+    tick_size: Option<Decimal>,
+) -> Result<OrderState, ParseError> {
+    let client_id = parse_client_order_id(order.cl_ord_id.as_deref());
     let id = OrderId::new(order.order_id);
-    let price = Decimal::from_f64(order.price?)?;
-    let amount = Decimal::from_i64(order.leaves_qty?)?;
-    let side = match order.side? {
-        RawSide::Buy => Side::Bid,
-        RawSide::Sell => Side::Ask,
-        _ => return None,
-    };
-    Some(OrderState::new(id, side, price, amount))
+    let price = snap(required(order.price, "price")?, tick_size)?;
+    let amount = Decimal::from(required(order.leaves_qty, "leavesQty")?);
+    let side = parse_side(order.side)?;
+    Ok(OrderState::new(id, side, price, amount).with_client_id(client_id))
 }
 
-pub fn parse_position(table: &TableMessage<Value>) -> Option<Amount> {
-    let mut values = Vec::new();
-    match table.action {
-        Action::Partial | Action::Update => {
-            for v in table.data.clone() {
-                let parsed: Position = serde_json::from_value(v).ok()?;
-                let _timestamp: u64 = parsed.timestamp?.timestamp_millis().try_into().unwrap();
-                values.push(Amount::from_i64(parsed.current_qty?)?);
+fn parse_timestamp(timestamp: Option<DateTime<Utc>>) -> Option<u64> {
+    timestamp?.timestamp_millis().try_into().ok()
+}
+
+/// The position of `symbol`, with the rows of the message folded into one.
+pub fn parse_position(
+    table: &TableMessage<Value>,
+    symbol: &str,
+) -> Result<Option<AccountUpdate>, ParseError> {
+    if matches!(table.action, Action::Delete) {
+        return Ok(None);
+    }
+
+    let mut update: Option<AccountUpdate> = None;
+    for parsed in rows::<Position>(table, Some(symbol))? {
+        let row = AccountUpdate {
+            currency: required(parsed.currency, "currency")?,
+            symbol: Some(parsed.symbol),
+            timestamp: parse_timestamp(parsed.timestamp),
+            position: parsed.current_qty.and_then(Amount::from_i64),
+            leverage: parsed.leverage,
+            entry_price: parsed.avg_entry_price,
+            liquidation_price: parsed.liquidation_price,
+            unrealised_pnl: parsed.unrealised_pnl.and_then(Amount::from_i64),
+            ..Default::default()
+        };
+        update = Some(match update {
+            Some(update) => update.merge(row),
+            None => row,
+        });
+    }
+    Ok(update)
+}
+
+pub fn parse_margin(table: &TableMessage<Value>) -> Result<Vec<AccountUpdate>, ParseError> {
+    if matches!(table.action, Action::Delete) {
+        return Ok(Vec::new());
+    }
+
+    let mut updates = Vec::new();
+    for parsed in rows::<Margin>(table, None)? {
+        updates.push(AccountUpdate {
+            currency: parsed.currency,
+            timestamp: parse_timestamp(parsed.timestamp),
+            margin_balance: parsed.margin_balance.and_then(Amount::from_i64),
+            available_margin: parsed.available_margin.and_then(Amount::from_i64),
+            ..Default::default()
+        });
+    }
+    Ok(updates)
+}
+
+pub fn parse_wallet(table: &TableMessage<Value>) -> Result<Vec<AccountUpdate>, ParseError> {
+    if matches!(table.action, Action::Delete) {
+        return Ok(Vec::new());
+    }
+
+    let mut updates = Vec::new();
+    for parsed in rows::<Wallet>(table, None)? {
+        updates.push(AccountUpdate {
+            currency: parsed.currency,
+            timestamp: parse_timestamp(parsed.timestamp),
+            wallet_balance: parsed.amount.and_then(Amount::from_i64),
+            ..Default::default()
+        });
+    }
+    Ok(updates)
+}
+
+impl TickerUpdate {
+    /// Sets the fields present in `self` on `ticker`.
+    pub fn apply(self, ticker: &mut Ticker) {
+        ticker.timestamp = self.timestamp.unwrap_or(ticker.timestamp);
+        ticker.mark_price = self.mark_price.or(ticker.mark_price);
+        ticker.index_price = self.index_price.or(ticker.index_price);
+        ticker.funding_rate = self.funding_rate.or(ticker.funding_rate);
+        ticker.next_funding_time = self.next_funding_time.or(ticker.next_funding_time);
+        ticker.last_funding_rate = self.last_funding_rate.or(ticker.last_funding_rate);
+        ticker.open_interest = self.open_interest.or(ticker.open_interest);
+    }
+}
+
+impl AccountUpdate {
+    /// `self` with the fields present in the later `other`.
+    pub fn merge(self, other: AccountUpdate) -> AccountUpdate {
+        AccountUpdate {
+            currency: other.currency,
+            symbol: other.symbol.or(self.symbol),
+            timestamp: other.timestamp.or(self.timestamp),
+            position: other.position.or(self.position),
+            wallet_balance: other.wallet_balance.or(self.wallet_balance),
+            margin_balance: other.margin_balance.or(self.margin_balance),
+            available_margin: other.available_margin.or(self.available_margin),
+            leverage: other.leverage.or(self.leverage),
+            entry_price: other.entry_price.or(self.entry_price),
+            liquidation_price: other.liquidation_price.or(self.liquidation_price),
+            unrealised_pnl: other.unrealised_pnl.or(self.unrealised_pnl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+
+    fn table(value: Value) -> TableMessage<Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse_filters_symbol() {
+        let orders = table(json!({
+            "table": "order",
+            "action": "partial",
+            "data": [
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "a",
+                    "clOrdID": "mm-1-0",
+                    "symbol": "XBTUSD",
+                    "ordStatus": "New",
+                    "orderQty": 100,
+                    "price": 16000.5,
+                    "leavesQty": 100,
+                    "cumQty": 0,
+                    "side": "Buy"
+                },
+                {
+                    "timestamp": "2023-01-01T00:00:01.000Z",
+                    "orderID": "b",
+                    "clOrdID": "",
+                    "symbol": "ETHUSD",
+                    "ordStatus": "New",
+                    "orderQty": 10,
+                    "price": 1200,
+                    "leavesQty": 10,
+                    "cumQty": 0,
+                    "side": "Sell"
+                }
+            ]
+        }));
+        assert_eq!(
+            parse_open_orders_ops(&orders, "XBTUSD", None),
+            Ok(vec![OpenOrdersWriteOp::init(OpenOrders::new(
+                1672531200000,
+                vec![
+                    OrderState::new(OrderId::new("a"), Side::Bid, dec!(16000.5), dec!(100))
+                        .with_client_id(ClientOrderId::new("mm-1-0"))
+                ]
+            ))])
+        );
+
+        let position = |symbol: &str, qty: i64| {
+            json!({
+                "account": 1,
+                "symbol": symbol,
+                "currency": "XBt",
+                "underlying": "XBT",
+                "quoteCurrency": "USD",
+                "currentQty": qty,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            })
+        };
+        let positions = table(json!({
+            "table": "position",
+            "action": "update",
+            "data": [position("XBTUSD", 300), position("ETHUSD", -20)]
+        }));
+        let qty = |symbol| parse_position(&positions, symbol).map(|u| u.map(|u| u.position));
+        assert_eq!(qty("XBTUSD"), Ok(Some(Some(dec!(300)))));
+        assert_eq!(qty("ETHUSD"), Ok(Some(Some(dec!(-20)))));
+        assert_eq!(qty("XBTUSDT"), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_amend() {
+        let amend = table(json!({
+            "table": "order",
+            "action": "update",
+            "data": [
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "a",
+                    "symbol": "XBTUSD",
+                    "price": 16001,
+                    "leavesQty": 50
+                },
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "b",
+                    "ordStatus": "Canceled"
+                }
+            ]
+        }));
+        assert_eq!(
+            parse_open_orders_ops(&amend, "XBTUSD", None),
+            Ok(vec![
+                OpenOrdersWriteOp::update(
+                    1672531200000,
+                    OrderId::new("a"),
+                    None,
+                    dec!(16001),
+                    dec!(50)
+                ),
+                OpenOrdersWriteOp::delete(1672531200000, OrderId::new("b")),
+            ])
+        );
+    }
+
+    // frames in the exact shape BitMEX sends them, one per line
+    const FRAMES: &str = include_str!("testdata/frames.jsonl");
+
+    #[test]
+    fn test_parse_frames() {
+        let frames: Vec<BitMEXWsMessage> = FRAMES
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let parse = |frame| parse_message(frame, "XBTUSD", Some(dec!(0.5))).unwrap();
+
+        match parse(&frames[0]) {
+            Some(ParsedMessage::Orderbook(ops)) => match &ops[..] {
+                [OrderbookWriteOp::Snapshot(orderbook)] => {
+                    assert_eq!(orderbook.best_ask_price(), Some(dec!(17000)));
+                    assert_eq!(orderbook.best_bid_price(), Some(dec!(16999.5)));
+                }
+                other => panic!("unexpected ops: {:?}", other),
+            },
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let id = OrderId::new("6c3ee5ba-4a3b-4e5f-9c2d-8e1f0a7b3c21");
+        assert_eq!(
+            parse(&frames[1]),
+            Some(ParsedMessage::OpenOrders(vec![OpenOrdersWriteOp::insert(
+                1673005200123,
+                OrderState::new(id.clone(), Side::Bid, dec!(16999.5), dec!(100))
+                    .with_client_id(ClientOrderId::new("mm-1673005200000-0"))
+            )]))
+        );
+        assert_eq!(
+            parse(&frames[2]),
+            Some(ParsedMessage::Fill(vec![Fill::new(
+                1673005201457,
+                id,
+                Side::Bid,
+                dec!(16999.5),
+                dec!(40),
+                dec!(-23),
+                Liquidity::Maker
+            )
+            .with_client_id(ClientOrderId::new("mm-1673005200000-0"))]))
+        );
+    }
+
+    #[test]
+    fn test_parse_exact_prices() {
+        let orderbook: TableMessage<Value> = serde_json::from_str(
+            r#"{"table":"orderBookL2","action":"partial","data":[
+                {"symbol":"XBTUSD","id":8798300000,"side":"Sell","size":1200,"price":17000,"timestamp":"2023-01-01T00:00:00.000Z"},
+                {"symbol":"ETHUSD","id":29699752460,"side":"Sell","size":30,"price":1234.55,"timestamp":"2023-01-01T00:00:00.000Z"}
+            ]}"#,
+        )
+        .unwrap();
+        let best = |symbol, tick_size| match parse_orderbook_ops(&orderbook, symbol, tick_size) {
+            Ok(ops) => match &ops[..] {
+                [OrderbookWriteOp::Snapshot(orderbook)] => {
+                    (orderbook.best_ask_price(), orderbook.best_bid_price())
+                }
+                other => panic!("unexpected ops: {:?}", other),
+            },
+            Err(e) => panic!("not parsed: {}", e),
+        };
+        assert_eq!(best("XBTUSD", Some(dec!(0.5))), (Some(dec!(17000)), None));
+        assert_eq!(
+            best("ETHUSD", Some(dec!(0.05))),
+            (Some(dec!(1234.55)), None)
+        );
+
+        let orders: TableMessage<Value> = serde_json::from_str(
+            r#"{"table":"order","action":"insert","data":[
+                {"orderID":"a","symbol":"ETHUSD","ordStatus":"New","side":"Buy","orderQty":10,"leavesQty":10,"price":1234.5,"timestamp":"2023-01-01T00:00:00.000Z"}
+            ]}"#,
+        )
+        .unwrap();
+        // one tick below the best ask, exactly
+        let price = dec!(1234.55) - dec!(0.05);
+        assert_eq!(
+            parse_open_orders_ops(&orders, "ETHUSD", Some(dec!(0.05))),
+            Ok(vec![OpenOrdersWriteOp::insert(
+                1672531200000,
+                OrderState::new(OrderId::new("a"), Side::Bid, price, dec!(10))
+            )])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |name: &str, action: &str, row: Value| {
+            let message = BitMEXWsMessage::Table(table(json!({
+                "table": name,
+                "action": action,
+                "data": [row]
+            })));
+            parse_message(&message, "XBTUSD", Some(dec!(0.5)))
+        };
+        let level = |price: Value| {
+            json!({
+                "symbol": "XBTUSD",
+                "id": 1,
+                "side": "Sell",
+                "size": 100,
+                "price": price,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            })
+        };
+
+        assert!(parse("orderBookL2", "partial", level(json!(17000))).is_ok());
+        // a row of another symbol is left out before it can fail the message
+        let levels = table(json!({
+            "table": "orderBookL2",
+            "action": "partial",
+            "data": [level(json!(17000)), { "symbol": "ETHUSD", "id": "bad" }]
+        }));
+        assert!(parse_orderbook_ops(&levels, "XBTUSD", None).is_ok());
+        assert!(parse_orderbook_ops(&levels, "ETHUSD", None).is_err());
+        assert_eq!(
+            parse("orderBookL2", "partial", level(Value::Null)),
+            Err(ParseError::MissingField("price"))
+        );
+        assert!(matches!(
+            parse("orderBookL2", "partial", level(json!("17000"))),
+            Err(ParseError::InvalidRow { .. })
+        ));
+        // too large to be snapped
+        assert!(matches!(
+            parse("orderBookL2", "insert", level(json!(7e28))),
+            Err(ParseError::InvalidField { field: "price", .. })
+        ));
+        assert!(matches!(
+            parse(
+                "trade",
+                "insert",
+                json!({
+                    "timestamp": "1969-12-31T23:59:59.000Z",
+                    "symbol": "XBTUSD",
+                    "side": "Buy",
+                    "size": 100,
+                    "price": 17000,
+                    "trdMatchID": "a"
+                })
+            ),
+            Err(ParseError::InvalidField {
+                field: "timestamp",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("order", "delete", json!({})),
+            Err(ParseError::UnexpectedAction { .. })
+        ));
+        assert_eq!(parse("liquidation", "partial", json!({})), Ok(None));
+    }
+
+    // arbitrary JSON, with strings the parser looks for
+    fn arb_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            prop::sample::select(vec![
+                "Buy",
+                "Sell",
+                "XBTUSD",
+                "New",
+                "PartiallyFilled",
+                "Canceled",
+                "Filled",
+                "Trade",
+                "AddedLiquidity",
+                "RemovedLiquidity",
+                "2023-01-01T00:00:00.000Z",
+                "1900-01-01T00:00:00.000Z",
+            ])
+            .prop_map(Value::from),
+            ".*".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map(".*", inner, 0..4)
+                    .prop_map(|fields| Value::Object(fields.into_iter().collect())),
+            ]
+        })
+    }
+
+    // rows made of the fields of the tables, with arbitrary values
+    fn arb_row() -> impl Strategy<Value = Value> {
+        const FIELDS: &[&str] = &[
+            "timestamp",
+            "symbol",
+            "id",
+            "side",
+            "size",
+            "price",
+            "trdMatchID",
+            "orderID",
+            "clOrdID",
+            "ordStatus",
+            "leavesQty",
+            "error",
+            "execType",
+            "lastPx",
+            "lastQty",
+            "execComm",
+            "lastLiquidityInd",
+            "currency",
+            "currentQty",
+            "leverage",
+            "avgEntryPrice",
+            "marginBalance",
+            "amount",
+            "markPrice",
+            "indicativeSettlePrice",
+            "fundingRate",
+            "fundingTimestamp",
+            "openInterest",
+        ];
+        prop::collection::btree_map(prop::sample::select(FIELDS), arb_value(), 0..FIELDS.len())
+            .prop_map(|fields| {
+                Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(field, value)| (field.to_string(), value))
+                        .collect(),
+                )
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_never_panics(
+            name in prop::sample::select(vec![
+                "orderBookL2", "trade", "instrument", "funding", "execution", "order", "position",
+                "margin", "wallet",
+            ]),
+            action in prop::sample::select(vec!["partial", "insert", "update", "delete"]),
+            data in prop::collection::vec(prop_oneof![arb_row(), arb_value()], 0..4),
+            tick_size in prop::option::of(
+                (0i64..1_000_000, 0u32..10).prop_map(|(n, scale)| Decimal::new(n, scale))
+            ),
+        ) {
+            let message = BitMEXWsMessage::Table(table(json!({
+                "table": name,
+                "action": action,
+                "data": data
+            })));
+            let _ = parse_message(&message, "XBTUSD", tick_size);
+        }
+
+        #[test]
+        fn test_parse_any_message_never_panics(value in arb_value()) {
+            if let Ok(message) = serde_json::from_value::<BitMEXWsMessage>(value) {
+                let _ = parse_message(&message, "XBTUSD", None);
             }
         }
-        _ => return None,
     }
-    values.last().copied()
+
+    #[test]
+    fn test_parse_account() {
+        let positions = table(json!({
+            "table": "position",
+            "action": "partial",
+            "data": [{
+                "account": 1,
+                "symbol": "XBTUSD",
+                "currency": "XBt",
+                "currentQty": 100,
+                "leverage": 10,
+                "avgEntryPrice": 16000.5,
+                "liquidationPrice": 14600,
+                "unrealisedPnl": -1500,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            }]
+        }));
+        assert_eq!(
+            parse_position(&positions, "XBTUSD"),
+            Ok(Some(AccountUpdate {
+                currency: "XBt".to_string(),
+                symbol: Some("XBTUSD".to_string()),
+                timestamp: Some(1672531200000),
+                position: Some(dec!(100)),
+                leverage: Some(dec!(10)),
+                entry_price: Some(dec!(16000.5)),
+                liquidation_price: Some(dec!(14600)),
+                unrealised_pnl: Some(dec!(-1500)),
+                ..Default::default()
+            }))
+        );
+
+        let margins = table(json!({
+            "table": "margin",
+            "action": "update",
+            "data": [{
+                "account": 1,
+                "currency": "XBt",
+                "marginBalance": 99000000,
+                "availableMargin": 90000000,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            }]
+        }));
+        assert_eq!(
+            parse_margin(&margins),
+            Ok(vec![AccountUpdate {
+                currency: "XBt".to_string(),
+                timestamp: Some(1672531200000),
+                margin_balance: Some(dec!(99000000)),
+                available_margin: Some(dec!(90000000)),
+                ..Default::default()
+            }])
+        );
+
+        let wallets = table(json!({
+            "table": "wallet",
+            "action": "partial",
+            "data": [{ "account": 1, "currency": "XBt", "amount": 100000000 }]
+        }));
+        assert_eq!(
+            parse_wallet(&wallets),
+            Ok(vec![AccountUpdate {
+                currency: "XBt".to_string(),
+                wallet_balance: Some(dec!(100000000)),
+                ..Default::default()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_ticker() {
+        let instruments = table(json!({
+            "table": "instrument",
+            "action": "partial",
+            "data": [{
+                "symbol": "XBTUSD",
+                "state": "Open",
+                "markPrice": 16999.37,
+                "indicativeSettlePrice": 16995.12,
+                "fundingRate": 0.0001,
+                "fundingTimestamp": "2023-01-01T04:00:00.000Z",
+                "openInterest": 525000000,
+                "timestamp": "2023-01-01T00:00:00.000Z"
+            }]
+        }));
+        let mark = table(json!({
+            "table": "instrument",
+            "action": "update",
+            "data": [{
+                "symbol": "XBTUSD",
+                "markPrice": 17000.01,
+                "timestamp": "2023-01-01T00:00:05.000Z"
+            }]
+        }));
+        let fundings = table(json!({
+            "table": "funding",
+            "action": "insert",
+            "data": [{
+                "symbol": "XBTUSD",
+                "fundingInterval": "2000-01-01T08:00:00.000Z",
+                "fundingRate": -0.000125,
+                "fundingRateDaily": -0.000375,
+                "timestamp": "2023-01-01T04:00:00.000Z"
+            }]
+        }));
+
+        let mut ticker = Ticker::default();
+        for table in [&instruments, &mark] {
+            for update in parse_instrument(table, "XBTUSD").unwrap() {
+                update.apply(&mut ticker);
+            }
+        }
+        assert_eq!(
+            ticker,
+            Ticker {
+                timestamp: 1672531205000,
+                mark_price: Some(dec!(17000.01)),
+                index_price: Some(dec!(16995.12)),
+                funding_rate: Some(dec!(0.0001)),
+                next_funding_time: Some(1672545600000),
+                last_funding_rate: None,
+                open_interest: Some(dec!(525000000)),
+            }
+        );
+        assert_eq!(ticker.basis(), Some(dec!(4.89)));
+
+        assert_eq!(
+            parse_funding(&fundings, "XBTUSD"),
+            Ok(vec![TickerUpdate {
+                timestamp: Some(1672545600000),
+                last_funding_rate: Some(dec!(-0.000125)),
+                ..Default::default()
+            }])
+        );
+        assert_eq!(parse_funding(&fundings, "ETHUSD"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_parse_fills() {
+        let executions = table(json!({
+            "table": "execution",
+            "action": "insert",
+            "data": [
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "a",
+                    "clOrdID": "mm-1-0",
+                    "symbol": "XBTUSD",
+                    "side": "Buy",
+                    "execType": "Trade",
+                    "lastPx": 16000,
+                    "lastQty": 100,
+                    "commission": -0.0001,
+                    "execComm": -62,
+                    "lastLiquidityInd": "AddedLiquidity"
+                },
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "b",
+                    "clOrdID": "",
+                    "symbol": "XBTUSD",
+                    "side": "Sell",
+                    "execType": "Trade",
+                    "lastPx": 15999.5,
+                    "lastQty": 200,
+                    "commission": 0.00075,
+                    "execComm": 938,
+                    "lastLiquidityInd": "RemovedLiquidity"
+                },
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "c",
+                    "symbol": "XBTUSD",
+                    "side": "Buy",
+                    "execType": "New"
+                },
+                {
+                    "timestamp": "2023-01-01T00:00:00.000Z",
+                    "orderID": "d",
+                    "symbol": "ETHUSD",
+                    "side": "Buy",
+                    "execType": "Trade",
+                    "lastPx": 1200,
+                    "lastQty": 1,
+                    "commission": 0.00075,
+                    "execComm": 938,
+                    "lastLiquidityInd": "RemovedLiquidity"
+                }
+            ]
+        }));
+        assert_eq!(
+            parse_fills(&executions, "XBTUSD", None),
+            Ok(vec![
+                Fill::new(
+                    1672531200000,
+                    OrderId::new("a"),
+                    Side::Bid,
+                    dec!(16000),
+                    dec!(100),
+                    dec!(-62),
+                    Liquidity::Maker
+                )
+                .with_client_id(ClientOrderId::new("mm-1-0")),
+                Fill::new(
+                    1672531200000,
+                    OrderId::new("b"),
+                    Side::Ask,
+                    dec!(15999.5),
+                    dec!(200),
+                    dec!(938),
+                    Liquidity::Taker
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_order_response() {
+        let order = |status: &str, extra: Value| -> Order {
+            let mut order = json!({
+                "timestamp": "2023-01-01T00:00:00.000Z",
+                "orderID": "a",
+                "clOrdID": "c",
+                "ordStatus": status
+            });
+            order
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(order).unwrap()
+        };
+
+        assert_eq!(
+            parse_order_response(order("New", json!({}))),
+            OrderResponse::Accept(OrderId::new("a"), Some(ClientOrderId::new("c")))
+        );
+        assert_eq!(
+            parse_order_response(order(
+                "Canceled",
+                json!({ "text": "Canceled: Canceled via API." })
+            )),
+            OrderResponse::Accept(OrderId::new("a"), Some(ClientOrderId::new("c")))
+        );
+        assert_eq!(
+            parse_order_response(order(
+                "Canceled",
+                json!({ "text": "Canceled: Order had execInst of ParticipateDoNotInitiate" })
+            )),
+            OrderResponse::Reject(RejectReason::WouldCross)
+        );
+        assert_eq!(
+            parse_order_response(order(
+                "Filled",
+                json!({ "error": "Unable to cancel order due to existing state: Filled" })
+            )),
+            OrderResponse::Reject(RejectReason::OrderNotFound)
+        );
+    }
 }
@@ -0,0 +1,66 @@
+use crossbeam_channel::unbounded;
+use log::*;
+use std::sync::Arc;
+use std::thread;
+
+use tokio::runtime::Runtime;
+use tokio::sync::Notify;
+
+use super::connection::{HeartbeatConfig, StreamEvent};
+use super::environment::BitMEXEnvironment;
+use super::market::market_feed;
+use super::parser::ParsedMessage;
+use crate::clock::SystemClock;
+use crate::implements::records::{Record, RecordWriter};
+
+/// Records the public orderbook and trade streams next to `BitMEXMarket`.
+pub struct BitMEXRecorder {
+    _runtime: Runtime,
+    _recorder: Option<thread::JoinHandle<()>>,
+}
+
+impl BitMEXRecorder {
+    pub fn start(environment: &BitMEXEnvironment, symbol: &str, mut writer: RecordWriter) -> Self {
+        info!("BitMEX recorder on {}: {}", environment, symbol);
+
+        let (sender, receiver) = unbounded();
+
+        let runtime = Runtime::new().unwrap();
+        let feed = market_feed(
+            environment,
+            symbol,
+            HeartbeatConfig::default(),
+            SystemClock,
+            None,
+        );
+        runtime.spawn(feed.run(sender, Arc::new(Notify::new())));
+
+        // a reconnection is followed by a fresh snapshot, which the replay starts over from
+        let recorder = thread::spawn(move || {
+            for event in receiver {
+                let record = match event {
+                    StreamEvent::Message(received, ParsedMessage::Orderbook(ops)) => {
+                        Record::new(received, ops)
+                    }
+                    StreamEvent::Message(received, ParsedMessage::Execution(executions)) => {
+                        Record::new(received, executions)
+                    }
+                    StreamEvent::Connection(state) => {
+                        warn!("recorder connection: {:?}", state);
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                if let Err(e) = writer.write(&record) {
+                    error!("{:?}", e);
+                }
+            }
+        });
+
+        Self {
+            _runtime: runtime,
+            _recorder: Some(recorder),
+        }
+    }
+}
@@ -1,27 +1,36 @@
 use anyhow::Result;
-use chrono::{Duration, Utc};
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::unbounded;
 use log::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
 
-use futures::sink::SinkExt;
-use futures::stream::StreamExt;
 use tokio::runtime::Runtime;
-
-use bitmex::websocket::{BitMEXWebsocket, Command, Topic};
-
-use super::parser::{self, ParsedMessage};
+use tokio::sync::Notify;
+
+use super::account::AccountTracker;
+use super::connection::{
+    sync_open_orders, Backoff, ConnectionTracker, Feed, HeartbeatConfig, StreamEvent,
+};
+use super::environment::BitMEXEnvironment;
+use super::instrument::watch_market_info;
+use super::parser::ParsedMessage;
 use crate::apikey::ApiKey;
-use crate::implements::writers::{OpenOrdersWriteOp, OpenOrdersWriter, OpenOrdersWriterResult};
+use crate::clock::{Clock, SystemClock};
 use crate::interfaces::Status;
 use crate::pubsub::{PubSub, Subscription};
-use crate::types::{Inventory, OpenOrders};
+use crate::types::{AccountState, ConnectionState, Fill, Inventory, OpenOrders};
 
 pub struct BitMEXStatus {
     _runtime: Runtime,
     _updater: Option<thread::JoinHandle<()>>,
     pubsub_inventory: PubSub<Inventory>,
     pubsub_open_orders: PubSub<OpenOrders>,
+    pubsub_fill: PubSub<Fill>,
+    pubsub_account: PubSub<AccountState>,
+    connection: ConnectionTracker,
+    watermark: Arc<AtomicU64>,
+    malformed: Arc<AtomicU64>,
 }
 
 impl Status for BitMEXStatus {
@@ -32,47 +41,142 @@ impl Status for BitMEXStatus {
     fn open_orders(&self) -> Subscription<OpenOrders> {
         self.pubsub_open_orders.subscribe()
     }
+
+    fn fills(&self) -> Subscription<Fill> {
+        self.pubsub_fill.subscribe()
+    }
+
+    fn account(&self) -> Subscription<AccountState> {
+        self.pubsub_account.subscribe()
+    }
+
+    fn connection(&self) -> Subscription<ConnectionState> {
+        self.connection.subscribe()
+    }
+
+    fn watermark(&self) -> Option<u64> {
+        Some(self.watermark.load(Ordering::Relaxed)).filter(|&watermark| watermark > 0)
+    }
 }
 
 impl BitMEXStatus {
-    pub fn connect(apikey: &ApiKey) -> Self {
-        std::env::set_var("BITMEX_TESTNET", "1");
+    pub fn connect(environment: &BitMEXEnvironment, apikey: &ApiKey, symbol: &str) -> Result<Self> {
+        Self::connect_with_clock(environment, apikey, symbol, SystemClock)
+    }
+
+    pub fn connect_with_clock<C>(
+        environment: &BitMEXEnvironment,
+        apikey: &ApiKey,
+        symbol: &str,
+        clock: C,
+    ) -> Result<Self>
+    where
+        C: Clock + 'static,
+    {
+        Self::connect_with_heartbeat(
+            environment,
+            apikey,
+            symbol,
+            HeartbeatConfig::default(),
+            clock,
+        )
+    }
+
+    pub fn connect_with_heartbeat<C>(
+        environment: &BitMEXEnvironment,
+        apikey: &ApiKey,
+        symbol: &str,
+        heartbeat: HeartbeatConfig,
+        clock: C,
+    ) -> Result<Self>
+    where
+        C: Clock + 'static,
+    {
+        environment.validate(apikey)?;
+        info!("BitMEX status on {}: {}", environment, symbol);
 
         let pubsub_inventory = PubSub::new();
         let pubsub_open_orders = PubSub::new();
+        let pubsub_fill = PubSub::new();
+        let pubsub_account = PubSub::new();
+        let connection = ConnectionTracker::new();
 
         let (sender, receiver) = unbounded();
-
-        let runtime = Runtime::new().unwrap();
-        runtime.spawn(start_websocket(
-            sender,
-            apikey.key().to_string(),
-            apikey.secret().to_string(),
-        ));
+        let resync = Arc::new(Notify::new());
+
+        // order and fill prices are snapped to the tick size like the market's
+        let runtime = Runtime::new()?;
+        let info = watch_market_info(&runtime, environment, symbol)?;
+        let currency = info.read().unwrap().settlement_currency().to_string();
+
+        let feed = Feed {
+            environment: environment.clone(),
+            apikey: Some(apikey.clone()),
+            topics: vec![
+                "order".to_string(),
+                "position".to_string(),
+                "execution".to_string(),
+                "margin".to_string(),
+                "wallet".to_string(),
+            ],
+            symbol: symbol.to_string(),
+            info: Some(info),
+            clock,
+            backoff: Backoff::default(),
+            heartbeat,
+            watermark: Arc::new(AtomicU64::new(0)),
+            malformed: Arc::new(AtomicU64::new(0)),
+        };
+        let watermark = feed.watermark.clone();
+        let malformed = feed.malformed.clone();
+        runtime.spawn(feed.run(sender, resync.clone()));
 
         let updater = {
             let pubsub_inventory = pubsub_inventory.clone();
             let pubsub_open_orders = pubsub_open_orders.clone();
+            let pubsub_fill = pubsub_fill.clone();
+            let pubsub_account = pubsub_account.clone();
+            let connection = connection.clone();
             thread::spawn(move || {
-                let mut open_orders = receive_open_orders(&receiver).unwrap();
-                pubsub_open_orders.publish(open_orders.clone());
-
-                for parsed in receiver {
-                    match parsed {
-                        ParsedMessage::OpenOrders(ops) => {
-                            let mut writer = OpenOrdersWriter::new(&mut open_orders);
-                            for op in ops {
-                                if let Err(e) = writer.apply(op) {
-                                    error!("{:?}", e);
+                // none while stale
+                let mut open_orders: Option<OpenOrders> = None;
+                let mut account = AccountTracker::new(currency);
+
+                for event in receiver {
+                    match event {
+                        StreamEvent::Connection(state) => {
+                            open_orders = None;
+                            connection.set(state);
+                        }
+                        StreamEvent::Message(_, ParsedMessage::OpenOrders(ops)) => {
+                            if let Err(e) = sync_open_orders(&mut open_orders, ops) {
+                                error!("open orders out of sync: {:?}", e);
+                                open_orders = None;
+                                connection.set(ConnectionState::Resyncing);
+                                resync.notify_one();
+                            }
+
+                            if let Some(open_orders) = &open_orders {
+                                connection.set(ConnectionState::Synced);
+                                pubsub_open_orders.publish(open_orders.clone());
+                            }
+                        }
+                        StreamEvent::Message(_, ParsedMessage::Account(updates)) => {
+                            for update in updates {
+                                if let Some(position) = update.position {
+                                    pubsub_inventory.publish(Inventory::Position(position));
+                                }
+                                if let Some(state) = account.apply(update) {
+                                    pubsub_account.publish(state);
                                 }
                             }
-                            pubsub_open_orders.publish(open_orders.clone());
                         }
-                        ParsedMessage::Position(position) => {
-                            let inventory = Inventory::Position(position);
-                            pubsub_inventory.publish(inventory);
+                        StreamEvent::Message(_, ParsedMessage::Fill(fills)) => {
+                            for fill in fills {
+                                pubsub_fill.publish(fill);
+                            }
                         }
-                        _ => {
+                        StreamEvent::Message(..) => {
                             error!("unexpected message");
                         }
                     }
@@ -80,66 +184,21 @@ impl BitMEXStatus {
             })
         };
 
-        Self {
+        Ok(Self {
             _runtime: runtime,
             _updater: Some(updater),
             pubsub_inventory,
             pubsub_open_orders,
-        }
-    }
-}
-
-async fn start_websocket(
-    sender: Sender<ParsedMessage>,
-    api_key: String,
-    api_secret: String,
-) -> Result<()> {
-    loop {
-        let mut client = BitMEXWebsocket::with_credential(&api_key, &api_secret)
-            .await
-            .unwrap();
-
-        let expires = (Utc::now() + Duration::seconds(1_000_000_000))
-            .timestamp()
-            .try_into()
-            .unwrap();
-        client.send(Command::authenticate(expires)).await.unwrap();
-
-        client
-            .send(Command::Subscribe(vec![Topic::Order, Topic::Position]))
-            .await
-            .unwrap();
-
-        while let Some(result) = client.next().await {
-            match result {
-                Ok(message) => {
-                    if let Some(parsed) = parser::parse_message(&message) {
-                        sender.send(parsed).unwrap();
-                    } else {
-                        debug!("parse failed: {:?}", message);
-                    }
-                }
-                Err(e) => {
-                    error!("{:?}", e);
-                }
-            }
-        }
+            pubsub_fill,
+            pubsub_account,
+            connection,
+            watermark,
+            malformed,
+        })
     }
-}
 
-fn receive_open_orders(receiver: &Receiver<ParsedMessage>) -> OpenOrdersWriterResult<OpenOrders> {
-    loop {
-        if let Some(ParsedMessage::OpenOrders(ops)) = receiver.iter().next() {
-            let mut iter = ops.into_iter();
-            while let Some(op) = iter.next() {
-                if let OpenOrdersWriteOp::Snapshot(mut open_orders) = op {
-                    let mut writer = OpenOrdersWriter::new(&mut open_orders);
-                    for op in iter {
-                        writer.apply(op)?;
-                    }
-                    return Ok(open_orders);
-                }
-            }
-        }
+    /// Number of messages dropped so far because they could not be parsed.
+    pub fn malformed_messages(&self) -> u64 {
+        self.malformed.load(Ordering::Relaxed)
     }
 }
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use log::*;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use futures::sink::SinkExt;
+use futures::stream::StreamExt;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use bitmex::websocket::BitMEXWsMessage;
+
+use super::environment::BitMEXEnvironment;
+use crate::apikey::ApiKey;
+use crate::clock::{Clock, SystemClock};
+
+const API_PATH: &str = "/api/v1";
+const REQUEST_EXPIRES_SECS: u64 = 60;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+/// Why a REST request failed.
+#[derive(Error, Debug)]
+pub(super) enum RestError {
+    /// Answered with an error status, with the message from the body when there is one.
+    #[error("{status}: {message}")]
+    Status { status: u16, message: String },
+    /// Failed or timed out in transit, so the request may or may not have taken effect.
+    #[error(transparent)]
+    Transport(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+// e.g. {"error":{"message":"Invalid orderID","name":"HTTPError"}}
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorMessage,
+}
+
+#[derive(Deserialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+/// Signature of an authenticated request as described in the BitMEX API docs.
+pub(super) fn sign(secret: &str, verb: &str, path: &str, expires: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{verb}{path}{expires}{body}").as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// REST client bound to one environment, signing its requests when it has an API key.
+/// Requests expire relative to `clock`.
+#[derive(Clone)]
+pub(super) struct RestClient {
+    client: reqwest::Client,
+    base_url: String,
+    apikey: Option<ApiKey>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RestClient {
+    pub fn new(environment: &BitMEXEnvironment, apikey: &ApiKey) -> Self {
+        Self {
+            apikey: Some(apikey.clone()),
+            ..Self::public(environment)
+        }
+    }
+
+    /// Client of the public endpoints only.
+    pub fn public(environment: &BitMEXEnvironment) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .build()
+                .unwrap(),
+            base_url: environment.rest_url().to_string(),
+            apikey: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub async fn request<T, R>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        payload: &T,
+    ) -> Result<R, RestError>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let path = format!("{API_PATH}{endpoint}");
+        let body = serde_json::to_string(payload)?;
+        let signature = self.apikey.as_ref().map(|apikey| {
+            let expires = self.clock.now() / 1000 + REQUEST_EXPIRES_SECS;
+            let signature = sign(apikey.secret(), method.as_str(), &path, expires, &body);
+            (apikey.key(), expires, signature)
+        });
+
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("content-type", "application/json");
+        if let Some((key, expires, signature)) = signature {
+            request = request
+                .header("api-expires", expires)
+                .header("api-key", key)
+                .header("api-signature", signature);
+        }
+        read_response(request.body(body).send().await?).await
+    }
+
+    /// Reads a public endpoint with `query` in the URL, unsigned.
+    pub async fn get<Q, R>(&self, endpoint: &str, query: &Q) -> Result<R, RestError>
+    where
+        Q: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let url = format!("{}{API_PATH}{endpoint}", self.base_url);
+        read_response(self.client.get(url).query(query).send().await?).await
+    }
+}
+
+async fn read_response<R: DeserializeOwned>(response: reqwest::Response) -> Result<R, RestError> {
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await?;
+        let message = match serde_json::from_str::<ErrorResponse>(&text) {
+            Ok(body) => body.error.message,
+            Err(_) => text,
+        };
+        return Err(RestError::Status {
+            status: status.as_u16(),
+            message,
+        });
+    }
+    Ok(response.json().await?)
+}
+
+/// Websocket connection to the realtime API of one environment.
+pub(super) struct WsClient {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl WsClient {
+    pub async fn connect(environment: &BitMEXEnvironment) -> Result<Self> {
+        let (stream, _) = tokio_tungstenite::connect_async(environment.websocket_url()).await?;
+        Ok(Self { stream })
+    }
+
+    pub async fn authenticate(&mut self, apikey: &ApiKey, expires: u64) -> Result<()> {
+        let signature = sign(apikey.secret(), "GET", "/realtime", expires, "");
+        self.send(json!({
+            "op": "authKeyExpires",
+            "args": [apikey.key(), expires, signature],
+        }))
+        .await
+    }
+
+    pub async fn subscribe(&mut self, topics: &[String]) -> Result<()> {
+        self.send(json!({ "op": "subscribe", "args": topics }))
+            .await
+    }
+
+    /// Next message from the server, or `None` once the connection is closed or broken.
+    pub async fn next(&mut self) -> Option<Result<BitMEXWsMessage>> {
+        loop {
+            match self.stream.next().await? {
+                Ok(Message::Text(text)) if text == "pong" => {
+                    return Some(Ok(BitMEXWsMessage::Pong))
+                }
+                Ok(Message::Text(text)) => {
+                    return Some(serde_json::from_str(&text).map_err(Into::into));
+                }
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    error!("{:?}", e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        self.stream.send(Message::Text("ping".to_string())).await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, command: serde_json::Value) -> Result<()> {
+        self.stream.send(Message::Text(command.to_string())).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::SimulatedClock;
+
+    use serde_json::Value;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_sign() {
+        // example from the BitMEX API key docs
+        let signature = sign(
+            "chNOOS4KvNXR_Xq4k4c9qsfoKWvnDecLATCRlcBwyKDYnWgO",
+            "GET",
+            "/api/v1/instrument",
+            1518064236,
+            "",
+        );
+        assert_eq!(
+            signature,
+            "c7682d435d0cfe87c16098df34ef2eb5a549d4c5a3c2b1f0f77b8af73423bf00"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rest_client_signs_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let body = "{\"ok\":true}";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let apikey = ApiKey::new("key".to_string(), "secret".to_string());
+        let clock = SimulatedClock::new(1_518_064_236_000);
+        let client = RestClient::new(&environment, &apikey).with_clock(clock);
+        let response: Value = client
+            .request(Method::POST, "/order", &json!({ "symbol": "XBTUSD" }))
+            .await
+            .unwrap();
+        assert_eq!(response, json!({ "ok": true }));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("post /api/v1/order "));
+        assert!(request.contains("api-key: key"));
+        assert!(request.contains("api-expires: 1518064296"));
+        assert!(request.contains("api-signature: "));
+    }
+
+    #[tokio::test]
+    async fn test_rest_client_reads_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            stream.read(&mut buf).await.unwrap();
+            let body = "{\"error\":{\"message\":\"Invalid orderID\",\"name\":\"HTTPError\"}}";
+            let response = format!(
+                "HTTP/1.1 400 Bad Request\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let apikey = ApiKey::new("key".to_string(), "secret".to_string());
+        let client = RestClient::new(&environment, &apikey);
+        let response = client
+            .request::<_, Value>(Method::DELETE, "/order", &json!({ "orderID": "a" }))
+            .await;
+        match response {
+            Err(RestError::Status { status, message }) => {
+                assert_eq!(status, 400);
+                assert_eq!(message, "Invalid orderID");
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let environment =
+            BitMEXEnvironment::Custom(format!("http://{}", listener.local_addr().unwrap()));
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let subscribe = ws.next().await.unwrap().unwrap();
+            ws.send(Message::Text(
+                json!({
+                    "table": "trade",
+                    "action": "insert",
+                    "data": []
+                })
+                .to_string(),
+            ))
+            .await
+            .unwrap();
+            ws.close(None).await.unwrap();
+            subscribe.into_text().unwrap()
+        });
+
+        let mut client = WsClient::connect(&environment).await.unwrap();
+        client
+            .subscribe(&["trade:XBTUSD".to_string()])
+            .await
+            .unwrap();
+        match client.next().await {
+            Some(Ok(BitMEXWsMessage::Table(table))) => assert_eq!(table.table, "trade"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(client.next().await.is_none());
+
+        let subscribe: Value = serde_json::from_str(&server.await.unwrap()).unwrap();
+        assert_eq!(
+            subscribe,
+            json!({ "op": "subscribe", "args": ["trade:XBTUSD"] })
+        );
+    }
+}
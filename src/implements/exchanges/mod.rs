@@ -1 +1,4 @@
 pub mod bitmex;
+pub mod paper;
+pub mod replay;
+pub mod simulated;
@@ -0,0 +1,472 @@
+use crossbeam_channel::select;
+use log::*;
+use rust_decimal::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use async_trait::async_trait;
+
+use crate::backtest::{take_liquidity, QueueFillModel};
+use crate::interfaces::{Broker, Market, Status};
+use crate::pubsub::{PubSub, Subscription};
+use crate::types::{
+    AccountState, Amount, ConnectionState, Fill, Inventory, Liquidity, MarketInfo, NewOrder,
+    OpenOrders, Order, OrderId, OrderResponse, OrderState, OrderType, Orderbook, RejectReason,
+    Side, UpdateOrder,
+};
+
+/// Paper trading on live market data of any `Market`, without credentials.
+///
+/// Marketable orders take the displayed liquidity without moving the book, and resting
+/// orders are filled by real trades with `QueueFillModel`, free of fees. The matching
+/// `PaperStatus` is obtained with `status`.
+pub struct PaperBroker {
+    info: MarketInfo,
+    shared: PaperShared,
+    _updater: Option<thread::JoinHandle<()>>,
+}
+
+#[derive(Clone)]
+pub struct PaperStatus {
+    shared: PaperShared,
+}
+
+#[derive(Clone)]
+struct PaperShared {
+    currency: String,
+    state: Arc<Mutex<PaperState>>,
+    pubsub_inventory: PubSub<Inventory>,
+    pubsub_open_orders: PubSub<OpenOrders>,
+    pubsub_fill: PubSub<Fill>,
+    pubsub_account: PubSub<AccountState>,
+    pubsub_connection: PubSub<ConnectionState>,
+}
+
+#[derive(Default)]
+struct PaperState {
+    nonce: u64,
+    timestamp: u64,
+    orderbook: Option<Orderbook>,
+    model: QueueFillModel,
+    position: Amount,
+    fills: Vec<Fill>, // not yet published
+}
+
+impl PaperBroker {
+    pub fn connect<M: Market>(market: &M) -> Self {
+        let shared = PaperShared {
+            currency: market.info().settlement_currency,
+            state: Arc::new(Mutex::new(PaperState::default())),
+            pubsub_inventory: PubSub::new(),
+            pubsub_open_orders: PubSub::new(),
+            pubsub_fill: PubSub::new(),
+            pubsub_account: PubSub::new(),
+            pubsub_connection: PubSub::new(),
+        };
+
+        let orderbook = market.orderbook();
+        let execution = market.execution();
+
+        let updater = {
+            let shared = shared.clone();
+            thread::spawn(move || loop {
+                select! {
+                    recv(orderbook.as_receiver()) -> msg => match msg {
+                        Ok(orderbook) => shared.update_orderbook(orderbook),
+                        Err(_) => break,
+                    },
+                    recv(execution.as_receiver()) -> msg => match msg {
+                        Ok(execution) => {
+                            let mut guard = shared.state.lock().unwrap();
+                            guard.timestamp = guard.timestamp.max(execution.timestamp());
+                            let fills = guard.model.on_execution(&execution);
+                            shared.settle_maker_fills(&mut guard, fills);
+                        }
+                        Err(_) => break,
+                    },
+                }
+            })
+        };
+
+        Self {
+            info: market.info(),
+            shared,
+            _updater: Some(updater),
+        }
+    }
+
+    pub fn status(&self) -> PaperStatus {
+        PaperStatus {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl PaperShared {
+    fn update_orderbook(&self, orderbook: Orderbook) {
+        let mut guard = self.state.lock().unwrap();
+        guard.timestamp = guard.timestamp.max(orderbook.timestamp());
+        let fills = guard.model.on_orderbook(&orderbook);
+        guard.orderbook = Some(orderbook);
+        self.settle_maker_fills(&mut guard, fills);
+    }
+
+    fn settle_maker_fills(&self, state: &mut PaperState, fills: Vec<(OrderState, Amount)>) {
+        if fills.is_empty() {
+            return;
+        }
+
+        for (order, amount) in fills {
+            debug!("paper fill: {} {:?} {}", order.id(), order.side(), amount);
+            let fill = Fill::new(
+                state.timestamp,
+                order.id().clone(),
+                order.side(),
+                order.price(),
+                amount,
+                Amount::zero(),
+                Liquidity::Maker,
+            );
+            state.settle(fill.with_client_id(order.client_id().cloned()));
+        }
+        self.publish(state);
+    }
+
+    fn publish(&self, state: &mut PaperState) {
+        for fill in state.fills.drain(..) {
+            self.pubsub_fill.publish(fill);
+        }
+        self.pubsub_inventory.publish(state.inventory());
+        self.pubsub_open_orders.publish(state.open_orders());
+        self.pubsub_account.publish(state.account(&self.currency));
+    }
+}
+
+impl PaperState {
+    fn inventory(&self) -> Inventory {
+        Inventory::Position(self.position)
+    }
+
+    fn open_orders(&self) -> OpenOrders {
+        self.model.open_orders(self.timestamp)
+    }
+
+    // no margin is modeled, so only the position is known
+    fn account(&self, currency: &str) -> AccountState {
+        AccountState::new(self.timestamp, currency, self.position)
+    }
+
+    /// Takes the displayed liquidity and rests the remainder of a limit order under `id`.
+    fn place(&mut self, orderbook: &Orderbook, id: OrderId, order: NewOrder) {
+        let side = order.order_side();
+        let mut remaining = order.amount();
+        for (offer, amount) in take_liquidity(orderbook, &order) {
+            debug!(
+                "paper fill: {} {:?} {} @ {}",
+                id,
+                side,
+                amount,
+                offer.price()
+            );
+            remaining -= amount;
+            let fill = Fill::new(
+                self.timestamp,
+                id.clone(),
+                side,
+                offer.price(),
+                amount,
+                Amount::zero(),
+                Liquidity::Taker,
+            );
+            self.settle(fill.with_client_id(order.client_id().cloned()));
+        }
+
+        if order.order_type() == OrderType::Limit && !remaining.is_zero() {
+            let state = OrderState::new(id, side, order.price(), remaining)
+                .with_client_id(order.client_id().cloned());
+            self.model.insert(orderbook, state);
+        }
+    }
+
+    // reduced at the same price the order keeps its queue position, otherwise it is re-placed
+    fn amend(
+        &mut self,
+        orderbook: &Orderbook,
+        order: UpdateOrder,
+    ) -> Result<OrderState, RejectReason> {
+        let current = match self.model.get(order.id()) {
+            Some(queued) => queued.order().clone(),
+            None => return Err(RejectReason::OrderNotFound),
+        };
+        order.check(&current)?;
+
+        let new_order = order
+            .new_order()
+            .clone()
+            .with_client_id(current.client_id().cloned());
+
+        if order.keeps_priority(&current) {
+            self.model.reduce(current.id(), new_order.amount());
+        } else {
+            self.model.remove(current.id());
+            self.place(orderbook, current.id().clone(), new_order);
+        }
+        Ok(current)
+    }
+
+    fn settle(&mut self, fill: Fill) {
+        match fill.side() {
+            Side::Ask => self.position -= fill.amount(),
+            Side::Bid => self.position += fill.amount(),
+        }
+        self.fills.push(fill);
+    }
+}
+
+impl Status for PaperStatus {
+    fn inventory(&self) -> Subscription<Inventory> {
+        let subscription = self.shared.pubsub_inventory.subscribe();
+        let inventory = self.shared.state.lock().unwrap().inventory();
+        self.shared.pubsub_inventory.publish(inventory);
+        subscription
+    }
+
+    fn open_orders(&self) -> Subscription<OpenOrders> {
+        let subscription = self.shared.pubsub_open_orders.subscribe();
+        let open_orders = self.shared.state.lock().unwrap().open_orders();
+        self.shared.pubsub_open_orders.publish(open_orders);
+        subscription
+    }
+
+    fn fills(&self) -> Subscription<Fill> {
+        self.shared.pubsub_fill.subscribe()
+    }
+
+    fn account(&self) -> Subscription<AccountState> {
+        let subscription = self.shared.pubsub_account.subscribe();
+        let account = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .account(&self.shared.currency);
+        self.shared.pubsub_account.publish(account);
+        subscription
+    }
+
+    // paper orders are kept locally, so they never go stale
+    fn connection(&self) -> Subscription<ConnectionState> {
+        let subscription = self.shared.pubsub_connection.subscribe();
+        self.shared
+            .pubsub_connection
+            .publish(ConnectionState::Synced);
+        subscription
+    }
+}
+
+#[async_trait]
+impl Broker for PaperBroker {
+    async fn submit(&self, order: Order) -> OrderResponse {
+        let mut guard = self.shared.state.lock().unwrap();
+
+        match order {
+            Order::New(new_order) => {
+                if let Err(reason) = self.info.check(&new_order) {
+                    debug!("invalid order: {:?}", new_order);
+                    return OrderResponse::Reject(reason);
+                }
+                let orderbook = match guard.orderbook.clone() {
+                    Some(orderbook) => orderbook,
+                    None => {
+                        debug!("no market data yet");
+                        return OrderResponse::Reject(RejectReason::Other(
+                            "no market data yet".to_string(),
+                        ));
+                    }
+                };
+
+                let id = OrderId::new(format!("paper-{}", guard.nonce));
+                guard.nonce += 1;
+
+                let client_id = new_order.client_id().cloned();
+                guard.place(&orderbook, id.clone(), new_order);
+                self.shared.publish(&mut guard);
+                OrderResponse::Accept(id, client_id)
+            }
+            Order::Update(update_order) => {
+                if let Err(reason) = self.info.check(update_order.new_order()) {
+                    debug!("invalid order: {:?}", update_order);
+                    return OrderResponse::Reject(reason);
+                }
+                let orderbook = match guard.orderbook.clone() {
+                    Some(orderbook) => orderbook,
+                    None => {
+                        debug!("no market data yet");
+                        return OrderResponse::Reject(RejectReason::Other(
+                            "no market data yet".to_string(),
+                        ));
+                    }
+                };
+
+                match guard.amend(&orderbook, update_order) {
+                    Ok(order) => {
+                        self.shared.publish(&mut guard);
+                        OrderResponse::Accept(order.id, order.client_id)
+                    }
+                    Err(reason) => OrderResponse::Reject(reason),
+                }
+            }
+            Order::Cancel(cancel_order) => match guard.model.remove(cancel_order.id()) {
+                Some(order) => {
+                    self.shared.publish(&mut guard);
+                    OrderResponse::Accept(order.id, order.client_id)
+                }
+                None => OrderResponse::Reject(RejectReason::OrderNotFound),
+            },
+        }
+    }
+
+    async fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId> {
+        let mut guard = self.shared.state.lock().unwrap();
+
+        let ids: Vec<OrderId> = guard
+            .model
+            .orders()
+            .map(|queued| queued.order())
+            .filter(|o| side.map_or(true, |side| o.side() == side))
+            .map(|o| o.id().clone())
+            .collect();
+        for id in &ids {
+            guard.model.remove(id);
+        }
+
+        self.shared.publish(&mut guard);
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+    use rust_decimal_macros::dec;
+    use std::time::Duration;
+
+    use crate::implements::exchanges::simulated::SimulatedExchange;
+    use crate::types::{Execution, Offer, OfferId, TradeId};
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn dummy_orderbook() -> Orderbook {
+        Orderbook::new(
+            0,
+            vec![
+                Offer::new(OfferId::new(160000), dec!(16000.0), dec!(300)),
+                Offer::new(OfferId::new(170000), dec!(17000.0), dec!(1000)),
+            ],
+            vec![Offer::new(OfferId::new(140000), dec!(14000.0), dec!(1000))],
+        )
+    }
+
+    #[test]
+    fn test_paper_trading() {
+        // stands in for the live feed
+        let market = SimulatedExchange::new(MarketInfo::for_test(), dummy_orderbook());
+        let broker = PaperBroker::connect(&market);
+        let status = broker.status();
+
+        let inventory = status.inventory();
+        let open_orders = status.open_orders();
+        let fills = status.fills();
+        assert_eq!(
+            inventory.as_receiver().recv_timeout(TIMEOUT).unwrap(),
+            Inventory::Position(dec!(0))
+        );
+        open_orders.as_receiver().recv_timeout(TIMEOUT).unwrap();
+
+        // wait for the initial book
+        while broker.shared.state.lock().unwrap().orderbook.is_none() {
+            thread::yield_now();
+        }
+        let bid = Order::create(OrderType::Limit, Side::Bid, dec!(14000.0), dec!(500));
+        let id = match block_on(broker.submit(bid)) {
+            OrderResponse::Accept(id, _) => id,
+            OrderResponse::Reject(reason) => panic!("rejected: {}", reason),
+        };
+        open_orders.as_receiver().recv_timeout(TIMEOUT).unwrap();
+
+        // queue of 1000 ahead, so 200 of ours trade
+        market.trade(Execution::new(
+            10,
+            TradeId::new(1),
+            Side::Bid,
+            dec!(14000.0),
+            dec!(1200),
+        ));
+        let orders = loop {
+            let orders = open_orders.as_receiver().recv_timeout(TIMEOUT).unwrap();
+            if orders.timestamp() == 10 {
+                break orders;
+            }
+        };
+        assert_eq!(
+            orders,
+            OpenOrders::new(
+                10,
+                vec![OrderState::new(
+                    id.clone(),
+                    Side::Bid,
+                    dec!(14000.0),
+                    dec!(300)
+                )]
+            )
+        );
+        assert_eq!(
+            fills.as_receiver().recv_timeout(TIMEOUT).unwrap(),
+            Fill::new(
+                10,
+                id.clone(),
+                Side::Bid,
+                dec!(14000.0),
+                dec!(200),
+                dec!(0),
+                Liquidity::Maker
+            )
+        );
+
+        // takes 300 at 16000 and 200 at 17000
+        let take = Order::create(OrderType::Market, Side::Bid, dec!(0), dec!(500));
+        assert!(matches!(
+            block_on(broker.submit(take)),
+            OrderResponse::Accept(..)
+        ));
+        let position = loop {
+            let position = inventory.as_receiver().recv_timeout(TIMEOUT).unwrap();
+            if position.position() == dec!(700) {
+                break position;
+            }
+        };
+        assert_eq!(position, Inventory::Position(dec!(700)));
+        let taken: Vec<_> = (0..2)
+            .map(|_| fills.as_receiver().recv_timeout(TIMEOUT).unwrap())
+            .map(|fill| (fill.price(), fill.amount(), fill.liquidity()))
+            .collect();
+        assert_eq!(
+            taken,
+            vec![
+                (dec!(16000.0), dec!(300), Liquidity::Taker),
+                (dec!(17000.0), dec!(200), Liquidity::Taker),
+            ]
+        );
+
+        assert!(matches!(
+            block_on(broker.submit(Order::cancel(id.clone()))),
+            OrderResponse::Accept(..)
+        ));
+        assert!(matches!(
+            block_on(broker.submit(Order::cancel(id))),
+            OrderResponse::Reject(RejectReason::OrderNotFound)
+        ));
+    }
+}
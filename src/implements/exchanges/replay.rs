@@ -0,0 +1,277 @@
+use log::*;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::implements::records::{RecordEvent, RecordReader, RecordResult};
+use crate::implements::writers::{OrderbookWriteOp, OrderbookWriter};
+use crate::interfaces::Market;
+use crate::pubsub::{PubSub, Subscription};
+use crate::types::{ConnectionState, Execution, MarketInfo, Orderbook, Ticker};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReplayMode {
+    AsFastAsPossible,
+    WallClock,
+}
+
+/// A `Market` which replays a recording instead of a live feed.
+///
+/// Replay starts on the first orderbook subscription, and all subscriptions are closed once
+/// the recording is exhausted. Recordings hold no ticker, so that one stays silent.
+pub struct ReplayMarket {
+    info: MarketInfo,
+    mode: ReplayMode,
+    reader: Mutex<Option<RecordReader>>,
+    replayer: Mutex<Option<thread::JoinHandle<()>>>,
+    pubsub_orderbook: PubSub<Orderbook>,
+    pubsub_execution: PubSub<Execution>,
+    pubsub_ticker: PubSub<Ticker>,
+    pubsub_connection: PubSub<ConnectionState>,
+}
+
+impl Market for ReplayMarket {
+    fn info(&self) -> MarketInfo {
+        self.info.clone()
+    }
+
+    fn orderbook(&self) -> Subscription<Orderbook> {
+        let subscription = self.pubsub_orderbook.subscribe();
+        self.start();
+        subscription
+    }
+
+    fn execution(&self) -> Subscription<Execution> {
+        self.pubsub_execution.subscribe()
+    }
+
+    fn ticker(&self) -> Subscription<Ticker> {
+        self.pubsub_ticker.subscribe()
+    }
+
+    fn connection(&self) -> Subscription<ConnectionState> {
+        let subscription = self.pubsub_connection.subscribe();
+        self.pubsub_connection.publish(ConnectionState::Synced);
+        subscription
+    }
+}
+
+impl ReplayMarket {
+    pub fn open<P>(path: P, info: MarketInfo, mode: ReplayMode) -> RecordResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let reader = RecordReader::open(path)?;
+        Ok(Self::new(reader, info, mode))
+    }
+
+    pub fn new(reader: RecordReader, info: MarketInfo, mode: ReplayMode) -> Self {
+        Self {
+            info,
+            mode,
+            reader: Mutex::new(Some(reader)),
+            replayer: Mutex::new(None),
+            pubsub_orderbook: PubSub::new(),
+            pubsub_execution: PubSub::new(),
+            pubsub_ticker: PubSub::new(),
+            pubsub_connection: PubSub::new(),
+        }
+    }
+
+    pub fn mode(&self) -> ReplayMode {
+        self.mode
+    }
+
+    /// Blocks until the replay has finished, if it has been started.
+    pub fn join(&self) {
+        let replayer = self.replayer.lock().unwrap().take();
+        if let Some(replayer) = replayer {
+            replayer.join().unwrap();
+        }
+    }
+
+    fn start(&self) {
+        if let Some(reader) = self.reader.lock().unwrap().take() {
+            let mode = self.mode;
+            let pubsub_orderbook = self.pubsub_orderbook.clone();
+            let pubsub_execution = self.pubsub_execution.clone();
+            let pubsub_ticker = self.pubsub_ticker.clone();
+            let pubsub_connection = self.pubsub_connection.clone();
+            let replayer = thread::spawn(move || {
+                replay(reader, mode, &pubsub_orderbook, &pubsub_execution);
+                pubsub_orderbook.unsubscribe_all();
+                pubsub_execution.unsubscribe_all();
+                pubsub_ticker.unsubscribe_all();
+                pubsub_connection.unsubscribe_all();
+            });
+            *self.replayer.lock().unwrap() = Some(replayer);
+        }
+    }
+}
+
+fn replay(
+    reader: RecordReader,
+    mode: ReplayMode,
+    pubsub_orderbook: &PubSub<Orderbook>,
+    pubsub_execution: &PubSub<Execution>,
+) {
+    let mut orderbook: Option<Orderbook> = None;
+    let mut origin: Option<(u64, Instant)> = None;
+
+    for result in reader {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                error!("{:?}", e);
+                break;
+            }
+        };
+
+        if mode == ReplayMode::WallClock {
+            let (start, started_at) =
+                *origin.get_or_insert_with(|| (record.timestamp(), Instant::now()));
+            let elapsed = Duration::from_millis(record.timestamp().saturating_sub(start));
+            let wait = (started_at + elapsed).saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+
+        match record.event {
+            RecordEvent::Orderbook(ops) => {
+                for op in ops {
+                    match op {
+                        OrderbookWriteOp::Snapshot(snapshot) => orderbook = Some(snapshot),
+                        op => {
+                            // deltas before the first snapshot are meaningless
+                            if let Some(orderbook) = orderbook.as_mut() {
+                                if let Err(e) = OrderbookWriter::new(orderbook).apply(op) {
+                                    error!("{:?}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if let Some(orderbook) = &orderbook {
+                    pubsub_orderbook.publish(orderbook.clone());
+                }
+            }
+            RecordEvent::Execution(executions) => {
+                for execution in executions {
+                    pubsub_execution.publish(execution);
+                }
+            }
+        }
+    }
+
+    info!("replay finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::implements::records::Record;
+    use crate::types::{Offer, OfferId, Side, TradeId};
+
+    fn dummy_recording(name: &str) -> PathBuf {
+        let records = vec![
+            // delta before the first snapshot
+            Record::new(
+                0,
+                vec![OrderbookWriteOp::delete(0, Side::Ask, OfferId::new(170))],
+            ),
+            Record::new(
+                100,
+                vec![OrderbookWriteOp::init(Orderbook::new(
+                    100,
+                    vec![Offer::new(OfferId::new(170), dec!(17000), dec!(100))],
+                    vec![Offer::new(OfferId::new(160), dec!(16000), dec!(200))],
+                ))],
+            ),
+            Record::new(
+                150,
+                vec![Execution::new(
+                    150,
+                    TradeId::new("t1"),
+                    Side::Bid,
+                    dec!(16000),
+                    dec!(50),
+                )],
+            ),
+            Record::new(
+                200,
+                vec![OrderbookWriteOp::update(
+                    200,
+                    Side::Bid,
+                    OfferId::new(160),
+                    None,
+                    dec!(150),
+                )],
+            ),
+        ];
+
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        for record in records {
+            writeln!(file, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+        }
+        path
+    }
+
+    fn replay_with(mode: ReplayMode) -> (Vec<Orderbook>, Vec<Execution>) {
+        let path = dummy_recording(&format!("replay-{:?}", mode));
+        let market = ReplayMarket::open(&path, MarketInfo::for_test(), mode).unwrap();
+
+        let execution = market.execution();
+        let orderbook = market.orderbook();
+        market.join();
+        std::fs::remove_file(&path).unwrap();
+
+        let orderbooks = orderbook.as_receiver().try_iter().collect();
+        let executions = execution.as_receiver().try_iter().collect();
+        assert!(orderbook.disconnected());
+        assert!(execution.disconnected());
+
+        (orderbooks, executions)
+    }
+
+    #[test]
+    fn test_replay_market_as_fast_as_possible() {
+        let (orderbooks, executions) = replay_with(ReplayMode::AsFastAsPossible);
+
+        assert_eq!(
+            orderbooks,
+            vec![
+                Orderbook::new(
+                    100,
+                    vec![Offer::new(OfferId::new(170), dec!(17000), dec!(100))],
+                    vec![Offer::new(OfferId::new(160), dec!(16000), dec!(200))],
+                ),
+                Orderbook::new(
+                    200,
+                    vec![Offer::new(OfferId::new(170), dec!(17000), dec!(100))],
+                    vec![Offer::new(OfferId::new(160), dec!(16000), dec!(150))],
+                ),
+            ]
+        );
+        assert_eq!(executions.len(), 1);
+    }
+
+    #[test]
+    fn test_replay_market_wall_clock() {
+        let started_at = Instant::now();
+        let (orderbooks, executions) = replay_with(ReplayMode::WallClock);
+
+        assert!(started_at.elapsed() >= Duration::from_millis(200));
+        assert_eq!(orderbooks.len(), 2);
+        assert_eq!(executions.len(), 1);
+    }
+}
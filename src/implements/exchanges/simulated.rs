@@ -0,0 +1,1070 @@
+use log::*;
+use rust_decimal::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+
+use crate::implements::simulation::{ConsumeLiquidity, FixedLatency};
+use crate::implements::writers::{
+    OpenOrdersWriteOp, OpenOrdersWriter, OrderbookWriteOp, OrderbookWriter, OrderbookWriterResult,
+};
+use crate::interfaces::{Broker, ImpactModel, LatencyModel, Market, Status};
+use crate::pubsub::{PubSub, Subscription};
+use crate::types::{
+    AccountState, Amount, CancelOrder, ConnectionState, Execution, Fill, Inventory, Liquidity,
+    MarketInfo, NewOrder, Offer, OfferId, OpenOrders, Order, OrderId, OrderResponse, OrderState,
+    OrderType, Orderbook, Price, RejectReason, Side, Ticker, TradeId, UpdateOrder,
+};
+
+/// An in-process exchange which matches our orders against its own orderbook.
+///
+/// The orderbook is driven by `apply` (market data) and `trade` (public trades), and our
+/// resting orders are placed into the same book, as they would be on a real venue.
+/// Subscribing to the orderbook, inventory or open orders publishes the current state
+/// immediately, so `Bot::run` can warm up without any external feed.
+///
+/// Orders reach the exchange after the `LatencyModel`'s delay in market data time, so with any
+/// latency a submission only completes once `apply`, `trade` or `deliver` gets there. The book
+/// reacts to our marketable orders through the `ImpactModel`, which by default consumes the
+/// liquidity taken. Our fills are free of fees, and there is no funding: the ticker only has
+/// the mid price as both mark and index price.
+#[derive(Clone)]
+pub struct SimulatedExchange {
+    info: MarketInfo,
+    state: Arc<Mutex<SimulatedState>>,
+    pubsub_orderbook: PubSub<Orderbook>,
+    pubsub_execution: PubSub<Execution>,
+    pubsub_ticker: PubSub<Ticker>,
+    pubsub_inventory: PubSub<Inventory>,
+    pubsub_open_orders: PubSub<OpenOrders>,
+    pubsub_fill: PubSub<Fill>,
+    pubsub_account: PubSub<AccountState>,
+    pubsub_connection: PubSub<ConnectionState>,
+}
+
+struct SimulatedState {
+    nonce: u64,
+    orderbook: Orderbook,
+    open_orders: OpenOrders,
+    position: Amount,
+    fills: Vec<Fill>, // not yet published
+    latency: Box<dyn LatencyModel + Send>,
+    impact: Box<dyn ImpactModel + Send>,
+    in_flight: Vec<InFlight>,
+}
+
+struct InFlight {
+    arrives_at: u64,
+    order: Order,
+    response: oneshot::Sender<OrderResponse>,
+}
+
+impl SimulatedExchange {
+    pub fn new(info: MarketInfo, orderbook: Orderbook) -> Self {
+        let open_orders = OpenOrders::new(orderbook.timestamp(), vec![]);
+        let state = SimulatedState {
+            nonce: 0,
+            orderbook,
+            open_orders,
+            position: Amount::zero(),
+            fills: Vec::new(),
+            latency: Box::new(FixedLatency::default()),
+            impact: Box::new(ConsumeLiquidity),
+            in_flight: Vec::new(),
+        };
+
+        Self {
+            info,
+            state: Arc::new(Mutex::new(state)),
+            pubsub_orderbook: PubSub::new(),
+            pubsub_execution: PubSub::new(),
+            pubsub_ticker: PubSub::new(),
+            pubsub_inventory: PubSub::new(),
+            pubsub_open_orders: PubSub::new(),
+            pubsub_fill: PubSub::new(),
+            pubsub_account: PubSub::new(),
+            pubsub_connection: PubSub::new(),
+        }
+    }
+
+    pub fn with_latency_model(self, latency: impl LatencyModel + Send + 'static) -> Self {
+        self.state.lock().unwrap().latency = Box::new(latency);
+        self
+    }
+
+    pub fn with_impact_model(self, impact: impl ImpactModel + Send + 'static) -> Self {
+        self.state.lock().unwrap().impact = Box::new(impact);
+        self
+    }
+
+    // the simulation is always in sync with itself
+    fn subscribe_connection(&self) -> Subscription<ConnectionState> {
+        let subscription = self.pubsub_connection.subscribe();
+        self.pubsub_connection.publish(ConnectionState::Synced);
+        subscription
+    }
+
+    /// Executes the orders which have reached the exchange by `timestamp`.
+    pub fn deliver(&self, timestamp: u64) {
+        let mut guard = self.state.lock().unwrap();
+        self.deliver_in_flight(&mut guard, timestamp);
+    }
+
+    /// Applies a market data update, then fills our resting orders crossed by it.
+    pub fn apply(&self, op: impl Into<OrderbookWriteOp>) -> OrderbookWriterResult<()> {
+        let mut guard = self.state.lock().unwrap();
+
+        let op = op.into();
+        self.deliver_in_flight(&mut guard, op.timestamp());
+
+        let snapshot = matches!(op, OrderbookWriteOp::Snapshot(_));
+        OrderbookWriter::new(&mut guard.orderbook).apply(op)?;
+
+        // a snapshot from the feed does not know about our resting orders
+        if snapshot {
+            let orders: Vec<OrderState> = guard.open_orders.orders().cloned().collect();
+            let timestamp = guard.orderbook.timestamp();
+            let mut writer = OrderbookWriter::new(&mut guard.orderbook);
+            for order in orders {
+                writer.apply(OrderbookWriteOp::create(
+                    timestamp,
+                    order.side(),
+                    OfferId::own(order.id()),
+                    order.price(),
+                    order.amount(),
+                ))?;
+            }
+        }
+
+        let executions = guard.match_resting_orders()?;
+        self.publish(&mut guard, executions);
+
+        Ok(())
+    }
+
+    /// Publishes a public trade and fills our resting orders it trades through. The trade goes
+    /// to the offers in book order, so whatever rests ahead of ours is filled first and we get
+    /// at most what is left of it. The other offers are left for the feed to reduce. Our part
+    /// of the trade is published as fills only, since the trade already carries its volume.
+    pub fn trade(&self, execution: Execution) {
+        let mut guard = self.state.lock().unwrap();
+        self.deliver_in_flight(&mut guard, execution.timestamp());
+
+        let mut remaining = execution.amount();
+
+        let maker_side = execution.maker_side();
+        let offers: &[Offer] = match maker_side {
+            Side::Ask => &guard.orderbook.asks,
+            Side::Bid => &guard.orderbook.bids,
+        };
+        let traded: Vec<(OfferId, Amount)> = offers
+            .iter()
+            .take_while(|offer| match maker_side {
+                Side::Ask => offer.price() <= execution.price(),
+                Side::Bid => offer.price() >= execution.price(),
+            })
+            .map(|offer| (offer.id().clone(), offer.amount()))
+            .collect();
+
+        for (id, available) in traded {
+            if remaining.is_zero() {
+                break;
+            }
+            let amount = remaining.min(available);
+            remaining -= amount;
+
+            let order = guard
+                .open_orders
+                .orders()
+                .find(|o| OfferId::own(o.id()) == id)
+                .cloned();
+            if let Some(order) = order {
+                guard.fill_resting_order(execution.timestamp(), &order, amount);
+            }
+        }
+
+        self.publish(&mut guard, vec![execution]);
+    }
+
+    pub fn current_orderbook(&self) -> Orderbook {
+        self.state.lock().unwrap().orderbook.clone()
+    }
+
+    pub fn current_open_orders(&self) -> OpenOrders {
+        self.state.lock().unwrap().open_orders.clone()
+    }
+
+    pub fn current_inventory(&self) -> Inventory {
+        Inventory::Position(self.state.lock().unwrap().position)
+    }
+
+    fn current_ticker(&self) -> Ticker {
+        ticker(&self.state.lock().unwrap().orderbook)
+    }
+
+    // margin is not simulated, so only the position is known
+    fn current_account(&self) -> AccountState {
+        let guard = self.state.lock().unwrap();
+        self.account_state(&guard)
+    }
+
+    fn account_state(&self, state: &SimulatedState) -> AccountState {
+        let timestamp = state.orderbook.timestamp();
+        AccountState::new(timestamp, &self.info.settlement_currency, state.position)
+    }
+
+    fn publish(&self, state: &mut SimulatedState, executions: Vec<Execution>) {
+        for execution in executions {
+            self.pubsub_execution.publish(execution);
+        }
+        for fill in state.fills.drain(..) {
+            self.pubsub_fill.publish(fill);
+        }
+        self.pubsub_orderbook.publish(state.orderbook.clone());
+        self.pubsub_ticker.publish(ticker(&state.orderbook));
+        self.pubsub_inventory
+            .publish(Inventory::Position(state.position));
+        self.pubsub_account.publish(self.account_state(state));
+        self.pubsub_open_orders.publish(state.open_orders.clone());
+    }
+
+    fn deliver_in_flight(&self, state: &mut SimulatedState, timestamp: u64) {
+        state.in_flight.sort_by_key(|f| f.arrives_at);
+
+        while state
+            .in_flight
+            .first()
+            .map_or(false, |f| f.arrives_at <= timestamp)
+        {
+            let InFlight {
+                order, response, ..
+            } = state.in_flight.remove(0);
+            let _ = response.send(self.execute(state, order));
+        }
+    }
+
+    fn execute(&self, state: &mut SimulatedState, order: Order) -> OrderResponse {
+        match order {
+            Order::New(new_order) => {
+                if let Err(reason) = self.info.check(&new_order) {
+                    debug!("invalid order: {:?}", new_order);
+                    return OrderResponse::Reject(reason);
+                }
+
+                let client_id = new_order.client_id().cloned();
+                match state.submit_new_order(new_order) {
+                    Ok((id, executions)) => {
+                        self.publish(state, executions);
+                        OrderResponse::Accept(id, client_id)
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        OrderResponse::Reject(RejectReason::Other(e.to_string()))
+                    }
+                }
+            }
+            Order::Update(update_order) => {
+                if let Err(reason) = self.info.check(update_order.new_order()) {
+                    debug!("invalid order: {:?}", update_order);
+                    return OrderResponse::Reject(reason);
+                }
+
+                match state.submit_update_order(update_order) {
+                    Ok(Ok((order, executions))) => {
+                        self.publish(state, executions);
+                        OrderResponse::Accept(order.id, order.client_id)
+                    }
+                    Ok(Err(reason)) => OrderResponse::Reject(reason),
+                    Err(e) => {
+                        error!("{:?}", e);
+                        OrderResponse::Reject(RejectReason::Other(e.to_string()))
+                    }
+                }
+            }
+            Order::Cancel(cancel_order) => match state.submit_cancel_order(cancel_order) {
+                Some(order) => {
+                    self.publish(state, vec![]);
+                    OrderResponse::Accept(order.id, order.client_id)
+                }
+                None => OrderResponse::Reject(RejectReason::OrderNotFound),
+            },
+        }
+    }
+}
+
+impl SimulatedState {
+    fn next_nonce(&mut self) -> u64 {
+        let nonce = self.nonce;
+        self.nonce += 1;
+        nonce
+    }
+
+    fn is_own_offer(&self, id: &OfferId) -> bool {
+        self.open_orders
+            .orders()
+            .any(|o| &OfferId::own(o.id()) == id)
+    }
+
+    /// Takes liquidity from the opposite side of the book, skipping our own offers.
+    fn take(
+        &mut self,
+        side: Side,
+        limit: Option<Price>,
+        amount: Amount,
+    ) -> OrderbookWriterResult<(Amount, Vec<Execution>)> {
+        let timestamp = self.orderbook.timestamp();
+        let maker_side = side.opposite();
+
+        let mut remaining = amount;
+        let mut fills = Vec::new();
+        {
+            let offers: &[Offer] = match maker_side {
+                Side::Ask => &self.orderbook.asks,
+                Side::Bid => &self.orderbook.bids,
+            };
+            for offer in offers {
+                if remaining.is_zero() {
+                    break;
+                }
+
+                let crossed = match (maker_side, limit) {
+                    (_, None) => true,
+                    (Side::Ask, Some(limit)) => offer.price() <= limit,
+                    (Side::Bid, Some(limit)) => offer.price() >= limit,
+                };
+                if !crossed {
+                    break;
+                }
+
+                if self.is_own_offer(offer.id()) {
+                    continue;
+                }
+
+                let amount = remaining.min(offer.amount());
+                remaining -= amount;
+                fills.push((offer.clone(), amount));
+            }
+        }
+
+        let mut executions = Vec::new();
+        for (offer, amount) in &fills {
+            let id = TradeId::new(self.next_nonce());
+            executions.push(Execution::new(
+                timestamp,
+                id,
+                maker_side,
+                offer.price(),
+                *amount,
+            ));
+        }
+
+        if !fills.is_empty() {
+            let ops = self.impact.impact(&self.orderbook, side, &fills);
+            let mut writer = OrderbookWriter::new(&mut self.orderbook);
+            for op in ops {
+                writer.apply(op)?;
+            }
+        }
+
+        let filled = amount - remaining;
+        match side {
+            Side::Ask => self.position -= filled,
+            Side::Bid => self.position += filled,
+        }
+
+        Ok((remaining, executions))
+    }
+
+    /// Fills our resting orders against any offer crossing them in the book.
+    fn match_resting_orders(&mut self) -> OrderbookWriterResult<Vec<Execution>> {
+        let timestamp = self.orderbook.timestamp();
+        let mut executions = Vec::new();
+
+        let orders: Vec<OrderState> = self.open_orders.orders().cloned().collect();
+        for order in orders {
+            let crossing: Vec<(OfferId, Amount)> = match order.side() {
+                Side::Ask => self
+                    .orderbook
+                    .bids()
+                    .take_while(|offer| offer.price() >= order.price())
+                    .map(|offer| (offer.id().clone(), offer.amount()))
+                    .collect(),
+                Side::Bid => self
+                    .orderbook
+                    .asks()
+                    .take_while(|offer| offer.price() <= order.price())
+                    .map(|offer| (offer.id().clone(), offer.amount()))
+                    .collect(),
+            };
+
+            let mut remaining = order.amount();
+            for (id, available) in crossing {
+                if remaining.is_zero() {
+                    break;
+                }
+                if self.is_own_offer(&id) {
+                    continue;
+                }
+
+                let amount = remaining.min(available);
+                remaining -= amount;
+
+                let left = available - amount;
+                let op = if left.is_zero() {
+                    OrderbookWriteOp::delete(timestamp, order.side().opposite(), id)
+                } else {
+                    OrderbookWriteOp::update(timestamp, order.side().opposite(), id, None, left)
+                };
+                OrderbookWriter::new(&mut self.orderbook).apply(op)?;
+
+                self.fill_resting_order(timestamp, &order, amount);
+                let id = TradeId::new(self.next_nonce());
+                executions.push(Execution::new(
+                    timestamp,
+                    id,
+                    order.side(),
+                    order.price(),
+                    amount,
+                ));
+            }
+        }
+
+        Ok(executions)
+    }
+
+    /// Fills `amount` of our resting order at its own price, as the maker.
+    fn fill_resting_order(&mut self, timestamp: u64, order: &OrderState, amount: Amount) {
+        let offer_id = OfferId::own(order.id());
+        let left = self
+            .open_orders
+            .orders()
+            .find(|o| o.id() == order.id())
+            .map(|o| o.amount() - amount)
+            .unwrap_or_else(Amount::zero);
+
+        let book_op = if left.is_zero() {
+            OrderbookWriteOp::delete(timestamp, order.side(), offer_id)
+        } else {
+            OrderbookWriteOp::update(timestamp, order.side(), offer_id, None, left)
+        };
+        if let Err(e) = OrderbookWriter::new(&mut self.orderbook).apply(book_op) {
+            error!("{:?}", e);
+        }
+
+        let op = OpenOrdersWriteOp::execution(timestamp, order.id().clone(), amount);
+        if let Err(e) = OpenOrdersWriter::new(&mut self.open_orders).apply(op) {
+            error!("{:?}", e);
+        }
+
+        match order.side() {
+            Side::Ask => self.position -= amount,
+            Side::Bid => self.position += amount,
+        }
+
+        let fill = Fill::new(
+            timestamp,
+            order.id().clone(),
+            order.side(),
+            order.price(),
+            amount,
+            Amount::zero(),
+            Liquidity::Maker,
+        );
+        self.fills
+            .push(fill.with_client_id(order.client_id().cloned()));
+    }
+
+    fn submit_new_order(
+        &mut self,
+        order: NewOrder,
+    ) -> OrderbookWriterResult<(OrderId, Vec<Execution>)> {
+        let id = OrderId::new(self.next_nonce());
+        let executions = self.place_order(id.clone(), order)?;
+        Ok((id, executions))
+    }
+
+    /// Amends a resting order in place when it keeps priority, otherwise re-enters it with
+    /// the same ids. Returns the order as it was, or the reason an unknown order or a change of
+    /// side or type is rejected.
+    fn submit_update_order(
+        &mut self,
+        order: UpdateOrder,
+    ) -> OrderbookWriterResult<Result<(OrderState, Vec<Execution>), RejectReason>> {
+        let timestamp = self.orderbook.timestamp();
+        let current = match self.open_orders.orders().find(|o| o.id() == order.id()) {
+            Some(current) => current.clone(),
+            None => return Ok(Err(RejectReason::OrderNotFound)),
+        };
+        if let Err(reason) = order.check(&current) {
+            return Ok(Err(reason));
+        }
+
+        let new_order = order
+            .new_order()
+            .clone()
+            .with_client_id(current.client_id().cloned());
+
+        let id = current.id().clone();
+        if order.keeps_priority(&current) {
+            OrderbookWriter::new(&mut self.orderbook).apply(OrderbookWriteOp::update(
+                timestamp,
+                current.side(),
+                OfferId::own(&id),
+                None,
+                new_order.amount(),
+            ))?;
+
+            let op =
+                OpenOrdersWriteOp::update(timestamp, id.clone(), None, None, new_order.amount());
+            if let Err(e) = OpenOrdersWriter::new(&mut self.open_orders).apply(op) {
+                error!("{:?}", e);
+            }
+            return Ok(Ok((current, vec![])));
+        }
+
+        self.submit_cancel_order(current.to_cancel_order());
+        let executions = self.place_order(id, new_order)?;
+        Ok(Ok((current, executions)))
+    }
+
+    /// Matches `order` against the book and rests the remainder of a limit order under `id`.
+    fn place_order(
+        &mut self,
+        id: OrderId,
+        order: NewOrder,
+    ) -> OrderbookWriterResult<Vec<Execution>> {
+        let timestamp = self.orderbook.timestamp();
+
+        let limit = match order.order_type() {
+            OrderType::Limit => Some(order.price()),
+            OrderType::Market => None,
+        };
+        let (remaining, executions) = self.take(order.order_side(), limit, order.amount())?;
+        for execution in &executions {
+            let fill = Fill::new(
+                timestamp,
+                id.clone(),
+                order.order_side(),
+                execution.price(),
+                execution.amount(),
+                Amount::zero(),
+                Liquidity::Taker,
+            );
+            self.fills
+                .push(fill.with_client_id(order.client_id().cloned()));
+        }
+
+        if order.order_type() == OrderType::Limit && !remaining.is_zero() {
+            OrderbookWriter::new(&mut self.orderbook).apply(OrderbookWriteOp::create(
+                timestamp,
+                order.order_side(),
+                OfferId::own(&id),
+                order.price(),
+                remaining,
+            ))?;
+
+            let state = OrderState::new(id, order.order_side(), order.price(), remaining)
+                .with_client_id(order.client_id().cloned());
+            let op = OpenOrdersWriteOp::insert(timestamp, state);
+            if let Err(e) = OpenOrdersWriter::new(&mut self.open_orders).apply(op) {
+                error!("{:?}", e);
+            }
+        }
+
+        Ok(executions)
+    }
+
+    fn submit_cancel_order(&mut self, order: CancelOrder) -> Option<OrderState> {
+        let timestamp = self.orderbook.timestamp();
+        let state = self
+            .open_orders
+            .orders()
+            .find(|o| o.id() == order.id())
+            .cloned()?;
+
+        let op = OrderbookWriteOp::delete(timestamp, state.side(), OfferId::own(state.id()));
+        if let Err(e) = OrderbookWriter::new(&mut self.orderbook).apply(op) {
+            error!("{:?}", e);
+        }
+
+        let op = OpenOrdersWriteOp::delete(timestamp, state.id().clone());
+        OpenOrdersWriter::new(&mut self.open_orders)
+            .apply(op)
+            .ok()?;
+
+        Some(state)
+    }
+}
+
+impl Market for SimulatedExchange {
+    fn info(&self) -> MarketInfo {
+        self.info.clone()
+    }
+
+    fn orderbook(&self) -> Subscription<Orderbook> {
+        let subscription = self.pubsub_orderbook.subscribe();
+        self.pubsub_orderbook.publish(self.current_orderbook());
+        subscription
+    }
+
+    fn execution(&self) -> Subscription<Execution> {
+        self.pubsub_execution.subscribe()
+    }
+
+    fn ticker(&self) -> Subscription<Ticker> {
+        let subscription = self.pubsub_ticker.subscribe();
+        self.pubsub_ticker.publish(self.current_ticker());
+        subscription
+    }
+
+    fn connection(&self) -> Subscription<ConnectionState> {
+        self.subscribe_connection()
+    }
+}
+
+fn ticker(orderbook: &Orderbook) -> Ticker {
+    let mut ticker = Ticker::new(orderbook.timestamp());
+    ticker.mark_price = orderbook.mid_price();
+    ticker.index_price = ticker.mark_price;
+    ticker
+}
+
+impl Status for SimulatedExchange {
+    fn inventory(&self) -> Subscription<Inventory> {
+        let subscription = self.pubsub_inventory.subscribe();
+        self.pubsub_inventory.publish(self.current_inventory());
+        subscription
+    }
+
+    fn open_orders(&self) -> Subscription<OpenOrders> {
+        let subscription = self.pubsub_open_orders.subscribe();
+        self.pubsub_open_orders.publish(self.current_open_orders());
+        subscription
+    }
+
+    fn fills(&self) -> Subscription<Fill> {
+        self.pubsub_fill.subscribe()
+    }
+
+    fn account(&self) -> Subscription<AccountState> {
+        let subscription = self.pubsub_account.subscribe();
+        self.pubsub_account.publish(self.current_account());
+        subscription
+    }
+
+    fn connection(&self) -> Subscription<ConnectionState> {
+        self.subscribe_connection()
+    }
+}
+
+#[async_trait]
+impl Broker for SimulatedExchange {
+    async fn submit(&self, order: Order) -> OrderResponse {
+        let receiver = {
+            let mut guard = self.state.lock().unwrap();
+
+            let timestamp = guard.orderbook.timestamp();
+            let latency = guard.latency.latency(timestamp, &order);
+            if latency == 0 {
+                return self.execute(&mut guard, order);
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            guard.in_flight.push(InFlight {
+                arrives_at: timestamp + latency,
+                order,
+                response: sender,
+            });
+            receiver
+        };
+
+        receiver.await.unwrap_or_else(|_| {
+            OrderResponse::Reject(RejectReason::Other(
+                "exchange dropped the order".to_string(),
+            ))
+        })
+    }
+
+    // each cancel is delayed like a single one
+    async fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId> {
+        let ids: Vec<OrderId> = self
+            .current_open_orders()
+            .orders()
+            .filter(|o| side.map_or(true, |side| o.side() == side))
+            .map(|o| o.id().clone())
+            .collect();
+
+        let cancels = ids.into_iter().map(|id| self.submit(Order::cancel(id)));
+        futures::future::join_all(cancels)
+            .await
+            .into_iter()
+            .filter_map(|response| match response {
+                OrderResponse::Accept(id, _) => Some(id),
+                OrderResponse::Reject(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::FutureExt;
+    use rust_decimal_macros::dec;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::bot::{Bot, Config};
+    use crate::implements::simulation::NoImpact;
+    use crate::strategies::dbo::DepthBasedOffering;
+
+    #[test]
+    fn test_simulated_exchange_submit() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+
+        // resting order
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(14500),
+            dec!(300),
+        )));
+        let id = match response {
+            OrderResponse::Accept(id, _) => id,
+            OrderResponse::Reject(reason) => panic!("rejected: {}", reason),
+        };
+        assert_eq!(
+            exchange.current_open_orders().orders().collect::<Vec<_>>(),
+            vec![&OrderState::new(
+                id.clone(),
+                Side::Bid,
+                dec!(14500),
+                dec!(300)
+            )],
+        );
+        assert_eq!(
+            exchange.current_orderbook().best_bid_price(),
+            Some(dec!(14500))
+        );
+        let ticker = exchange.ticker().as_receiver().try_recv().unwrap();
+        assert_eq!(
+            ticker.mark_price(),
+            exchange.current_orderbook().mid_price()
+        );
+        assert_eq!(ticker.basis(), Some(dec!(0)));
+
+        // marketable order
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Ask,
+            dec!(14000),
+            dec!(500),
+        )));
+        assert!(matches!(response, OrderResponse::Accept(..)));
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(-500))
+        );
+        assert_eq!(
+            exchange.current_orderbook().bids().nth(1),
+            Some(&Offer::new(OfferId::new(140000), dec!(14000), dec!(500))),
+        );
+
+        // invalid order
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Ask,
+            dec!(16000.3),
+            dec!(100),
+        )));
+        assert_eq!(response, OrderResponse::Reject(RejectReason::InvalidPrice));
+
+        // cancel
+        let response = block_on(exchange.submit(Order::cancel(id.clone())));
+        assert_eq!(response, OrderResponse::Accept(id.clone(), None));
+        assert_eq!(exchange.current_open_orders().orders().count(), 0);
+        assert_eq!(
+            exchange.current_orderbook().best_bid_price(),
+            Some(dec!(14000))
+        );
+
+        let response = block_on(exchange.submit(Order::cancel(id)));
+        assert_eq!(response, OrderResponse::Reject(RejectReason::OrderNotFound));
+    }
+
+    #[test]
+    fn test_simulated_exchange_amend() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Bid,
+            dec!(14500),
+            dec!(300),
+        )));
+        let id = match response {
+            OrderResponse::Accept(id, _) => id,
+            OrderResponse::Reject(reason) => panic!("rejected: {}", reason),
+        };
+
+        // reduce in place
+        let amend = NewOrder::new(OrderType::Limit, Side::Bid, dec!(14500), dec!(200));
+        let response = block_on(exchange.submit(Order::update(id.clone(), amend)));
+        assert_eq!(response, OrderResponse::Accept(id.clone(), None));
+        assert_eq!(
+            exchange.current_orderbook().best_bid(),
+            Some(&Offer::new(OfferId::own(&id), dec!(14500), dec!(200))),
+        );
+
+        // move through the ask, filling 1000 and resting the rest under the same id
+        let amend = NewOrder::new(OrderType::Limit, Side::Bid, dec!(16000), dec!(1200));
+        let response = block_on(exchange.submit(Order::update(id.clone(), amend)));
+        assert_eq!(response, OrderResponse::Accept(id.clone(), None));
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(1000))
+        );
+        assert_eq!(
+            exchange.current_open_orders().orders().collect::<Vec<_>>(),
+            vec![&OrderState::new(
+                id.clone(),
+                Side::Bid,
+                dec!(16000),
+                dec!(200)
+            )],
+        );
+
+        // side cannot change
+        let amend = NewOrder::new(OrderType::Limit, Side::Ask, dec!(16000), dec!(200));
+        let response = block_on(exchange.submit(Order::update(id, amend)));
+        assert!(matches!(
+            response,
+            OrderResponse::Reject(RejectReason::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_simulated_exchange_fill_resting_orders() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+        let executions = exchange.execution();
+        let fills = exchange.fills();
+
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Ask,
+            dec!(15000),
+            dec!(500),
+        )));
+        let id = match response {
+            OrderResponse::Accept(id, _) => id,
+            OrderResponse::Reject(reason) => panic!("rejected: {}", reason),
+        };
+
+        // public trade through our ask
+        exchange.trade(Execution::new(
+            1,
+            TradeId::new("t1"),
+            Side::Ask,
+            dec!(15000),
+            dec!(200),
+        ));
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(-200))
+        );
+
+        // incoming bid crossing our ask
+        exchange
+            .apply(OrderbookWriteOp::create(
+                2,
+                Side::Bid,
+                OfferId::new(150005),
+                dec!(15000.5),
+                dec!(1000),
+            ))
+            .unwrap();
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(-500))
+        );
+        assert_eq!(exchange.current_open_orders().orders().count(), 0);
+        assert_eq!(
+            exchange.current_orderbook().best_bid(),
+            Some(&Offer::new(OfferId::new(150005), dec!(15000.5), dec!(700))),
+        );
+
+        // the public trade already carries our part of it, the crossing bid trades anew
+        let executions: Vec<_> = executions.try_iter().unwrap().collect();
+        assert_eq!(
+            executions.iter().map(|e| e.amount()).collect::<Vec<_>>(),
+            vec![dec!(200), dec!(300)]
+        );
+        assert!(executions.iter().all(|e| e.maker_side() == Side::Ask));
+
+        let fill = |timestamp, amount| {
+            let price = dec!(15000);
+            Fill::new(
+                timestamp,
+                id.clone(),
+                Side::Ask,
+                price,
+                amount,
+                dec!(0),
+                Liquidity::Maker,
+            )
+        };
+        assert_eq!(
+            fills.try_iter().unwrap().collect::<Vec<_>>(),
+            vec![fill(1, dec!(200)), fill(2, dec!(300))]
+        );
+    }
+
+    #[test]
+    fn test_simulated_exchange_drives_bot() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+
+        let feeder = {
+            let exchange = exchange.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                exchange
+                    .apply(OrderbookWriteOp::update(
+                        1,
+                        Side::Ask,
+                        OfferId::new(160000),
+                        None,
+                        dec!(2000),
+                    ))
+                    .unwrap();
+            })
+        };
+
+        let config = Config {
+            num_iteration: 3,
+            test: false,
+            max_position: None,
+        };
+        let policy = DepthBasedOffering::new(dec!(500), dec!(1000));
+        let mut bot = Bot::new(
+            config,
+            exchange.clone(),
+            exchange.clone(),
+            exchange.clone(),
+            policy,
+        );
+        let open_orders = exchange.open_orders();
+        bot.run().unwrap();
+        feeder.join().unwrap();
+
+        // quoted both sides, then canceled everything on shutdown
+        assert!(open_orders
+            .as_receiver()
+            .try_iter()
+            .any(|o| o.ask_amount() == dec!(500) && o.bid_amount() == dec!(500)));
+        assert_eq!(exchange.current_open_orders().orders().count(), 0);
+    }
+
+    #[test]
+    fn test_simulated_exchange_trade_queue() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test());
+
+        // an offer of the feed under the id our first order gets, ahead of it in the queue
+        exchange
+            .apply(OrderbookWriteOp::create(
+                1,
+                Side::Ask,
+                OfferId::new(0),
+                dec!(15000),
+                dec!(300),
+            ))
+            .unwrap();
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Limit,
+            Side::Ask,
+            dec!(15000),
+            dec!(500),
+        )));
+        let id = match response {
+            OrderResponse::Accept(id, _) => id,
+            OrderResponse::Reject(reason) => panic!("rejected: {}", reason),
+        };
+        assert_eq!(id, OrderId::new(0));
+
+        // the queue ahead takes the trade first
+        let trade = |timestamp, amount| {
+            exchange.trade(Execution::new(
+                timestamp,
+                TradeId::new(timestamp),
+                Side::Ask,
+                dec!(15000),
+                amount,
+            ))
+        };
+        trade(2, dec!(400));
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(-100))
+        );
+
+        // the offer of the feed is not mistaken for ours
+        let response = block_on(exchange.submit(Order::create(
+            OrderType::Market,
+            Side::Bid,
+            dec!(0),
+            dec!(200),
+        )));
+        assert!(matches!(response, OrderResponse::Accept(..)));
+        assert_eq!(exchange.current_inventory(), Inventory::Position(dec!(100)));
+        assert_eq!(
+            exchange
+                .current_orderbook()
+                .asks()
+                .find(|offer| offer.id() == &OfferId::new(0)),
+            Some(&Offer::new(OfferId::new(0), dec!(15000), dec!(100)))
+        );
+
+        // at most the rest of our order
+        trade(3, dec!(1000));
+        assert_eq!(
+            exchange.current_inventory(),
+            Inventory::Position(dec!(-300))
+        );
+        assert_eq!(exchange.current_open_orders().orders().count(), 0);
+    }
+
+    #[test]
+    fn test_simulated_exchange_latency_and_impact() {
+        let exchange = SimulatedExchange::new(MarketInfo::for_test(), Orderbook::for_test())
+            .with_latency_model(FixedLatency::new(100, 100))
+            .with_impact_model(NoImpact);
+
+        let order = Order::create(OrderType::Market, Side::Bid, dec!(0), dec!(300));
+        let mut response = Box::pin(exchange.submit(order));
+        assert!((&mut response).now_or_never().is_none());
+
+        // still on its way
+        exchange
+            .apply(OrderbookWriteOp::update(
+                50,
+                Side::Bid,
+                OfferId::new(140000),
+                None,
+                dec!(900),
+            ))
+            .unwrap();
+        assert!((&mut response).now_or_never().is_none());
+        assert_eq!(exchange.current_inventory(), Inventory::Position(dec!(0)));
+
+        exchange.deliver(100);
+        assert!(matches!(block_on(response), OrderResponse::Accept(..)));
+        assert_eq!(exchange.current_inventory(), Inventory::Position(dec!(300)));
+        // the taken liquidity is replenished
+        assert_eq!(
+            exchange.current_orderbook().best_ask(),
+            Some(&Offer::new(OfferId::new(160000), dec!(16000.0), dec!(1000)))
+        );
+    }
+}
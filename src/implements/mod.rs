@@ -1,2 +1,4 @@
 pub mod exchanges;
+pub mod records;
+pub mod simulation;
 pub mod writers;
@@ -0,0 +1,26 @@
+//! Recorded market data.
+//!
+//! A recording is a sequence of JSON lines, one [`Record`] per line, in the order the messages
+//! were received. Each record carries the receive timestamp in milliseconds since the unix epoch
+//! and either a batch of orderbook ops or a batch of trades, e.g.
+//!
+//! ```text
+//! {"timestamp":1671926400123,"event":{"orderbook":[{"delete":{"timestamp":1671926400120,"side":"Ask","id":"8799830000"}}]}}
+//! {"timestamp":1671926400130,"event":{"execution":[{"timestamp":1671926400128,"id":"00a1...","maker_side":"Bid","price":"16999.5","amount":"100"}]}}
+//! ```
+//!
+//! Prices and amounts are written as decimal strings so that they read back exactly.
+//!
+//! Recordings are append-only and split into files by [`RecordWriter`] as
+//! `<prefix>-<timestamp>.jsonl`, where `<timestamp>` is the 13 digit receive timestamp of the
+//! first record in the file, followed by `-<n>` for the n-th further file started within the
+//! same millisecond. Reading the files sorted by timestamp and `n` (see
+//! [`RecordReader::open_dir`]) yields the records in the order they were received.
+
+mod reader;
+mod record;
+mod writer;
+
+pub use reader::*;
+pub use record::*;
+pub use writer::*;
@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use super::record::Record;
+use super::writer::list_record_files;
+
+#[derive(Error, Debug)]
+pub enum RecordError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid record at {path:?}:{line}: {source}")]
+    InvalidRecord {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+pub type RecordResult<T> = Result<T, RecordError>;
+
+/// Reads records from one or more recording files, in the given order.
+pub struct RecordReader {
+    paths: Vec<PathBuf>,
+    current: Option<(PathBuf, io::Lines<BufReader<File>>, usize)>,
+}
+
+impl RecordReader {
+    pub fn open<P>(path: P) -> RecordResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_all(vec![path])
+    }
+
+    pub fn open_all<I, P>(paths: I) -> RecordResult<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut paths: Vec<PathBuf> = paths.into_iter().map(|p| p.as_ref().to_owned()).collect();
+        paths.reverse();
+
+        let mut reader = Self {
+            paths,
+            current: None,
+        };
+        reader.next_file()?;
+
+        Ok(reader)
+    }
+
+    pub fn open_dir<P>(dir: P, prefix: &str) -> RecordResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_all(list_record_files(dir, prefix)?)
+    }
+
+    pub fn read_all<P>(path: P) -> RecordResult<Vec<Record>>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open(path)?.collect()
+    }
+
+    fn next_file(&mut self) -> RecordResult<bool> {
+        match self.paths.pop() {
+            Some(path) => {
+                let lines = BufReader::new(File::open(&path)?).lines();
+                self.current = Some((path, lines, 0));
+                Ok(true)
+            }
+            None => {
+                self.current = None;
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = RecordResult<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, lines, line) = self.current.as_mut()?;
+
+            match lines.next() {
+                Some(Ok(text)) => {
+                    *line += 1;
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+
+                    let result =
+                        serde_json::from_str(&text).map_err(|source| RecordError::InvalidRecord {
+                            path: path.clone(),
+                            line: *line,
+                            source,
+                        });
+                    return Some(result);
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                None => {
+                    if let Err(e) = self.next_file() {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+
+    use crate::implements::writers::OrderbookWriteOp;
+    use crate::types::{Execution, Offer, OfferId, Orderbook, Side, TradeId};
+
+    #[test]
+    fn test_record_reader() {
+        let records = vec![
+            Record::new(
+                1,
+                vec![OrderbookWriteOp::init(Orderbook::new(
+                    1,
+                    vec![Offer::new(OfferId::new(170), dec!(16999.5), dec!(100))],
+                    vec![Offer::new(OfferId::new(160), dec!(16000), dec!(200))],
+                ))],
+            ),
+            Record::new(
+                2,
+                vec![Execution::new(
+                    2,
+                    TradeId::new("t1"),
+                    Side::Bid,
+                    dec!(16000),
+                    dec!(50),
+                )],
+            ),
+            Record::new(
+                3,
+                vec![OrderbookWriteOp::update(
+                    3,
+                    Side::Bid,
+                    OfferId::new(160),
+                    None,
+                    dec!(150),
+                )],
+            ),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("record-reader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = vec![dir.join("0.jsonl"), dir.join("1.jsonl")];
+
+        let mut file = File::create(&paths[0]).unwrap();
+        for record in &records[..2] {
+            writeln!(file, "{}", serde_json::to_string(record).unwrap()).unwrap();
+        }
+        let mut file = File::create(&paths[1]).unwrap();
+        writeln!(file, "{}", serde_json::to_string(&records[2]).unwrap()).unwrap();
+
+        let read = RecordReader::open_all(&paths)
+            .unwrap()
+            .collect::<RecordResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, records);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::implements::writers::OrderbookWriteOp;
+use crate::types::Execution;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp: u64, // ms, at receive
+    pub event: RecordEvent,
+}
+
+impl Record {
+    pub fn new(timestamp: u64, event: impl Into<RecordEvent>) -> Self {
+        Self {
+            timestamp,
+            event: event.into(),
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn event(&self) -> &RecordEvent {
+        &self.event
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordEvent {
+    Orderbook(Vec<OrderbookWriteOp>),
+    Execution(Vec<Execution>),
+}
+
+impl From<Vec<OrderbookWriteOp>> for RecordEvent {
+    fn from(ops: Vec<OrderbookWriteOp>) -> Self {
+        Self::Orderbook(ops)
+    }
+}
+
+impl From<Vec<Execution>> for RecordEvent {
+    fn from(executions: Vec<Execution>) -> Self {
+        Self::Execution(executions)
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::reader::RecordResult;
+use super::record::Record;
+
+pub const RECORD_FILE_EXTENSION: &str = "jsonl";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Size(u64),     // bytes
+    Interval(u64), // ms
+}
+
+/// Appends records to `<dir>/<prefix>-<timestamp>.jsonl`, starting a new file on rotation.
+///
+/// `<timestamp>` is the zero padded receive timestamp of the first record in the file, so a
+/// new writer and every rotation normally start a new file. A rotation never reuses an
+/// existing file, so a further file started within the same millisecond gets the next `-<n>`
+/// suffix. Only when a new writer's first record falls in the millisecond of existing files
+/// does it append to the last of them, which keeps the records in order by name.
+pub struct RecordWriter {
+    dir: PathBuf,
+    prefix: String,
+    rotation: Rotation,
+    current: Option<CurrentFile>,
+}
+
+struct CurrentFile {
+    path: PathBuf,
+    started_at: u64,
+    written: u64,
+    writer: LineWriter<File>,
+}
+
+impl RecordWriter {
+    pub fn create<P>(dir: P, prefix: impl ToString, rotation: Rotation) -> RecordResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_owned(),
+            prefix: prefix.to_string(),
+            rotation,
+            current: None,
+        })
+    }
+
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|c| c.path.as_path())
+    }
+
+    pub fn write(&mut self, record: &Record) -> RecordResult<()> {
+        let mut line = serde_json::to_vec(record).expect("records are always serializable");
+        line.push(b'\n');
+
+        let rotate = match (&self.current, self.rotation) {
+            (None, _) => true,
+            (Some(current), Rotation::Size(max_bytes)) => current.written >= max_bytes,
+            (Some(current), Rotation::Interval(interval)) => {
+                record.timestamp() >= current.started_at + interval
+            }
+        };
+        if rotate {
+            self.rotate(record.timestamp())?;
+        }
+
+        let current = self.current.as_mut().expect("must exists");
+        current.writer.write_all(&line)?;
+        current.written += line.len() as u64;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> RecordResult<()> {
+        if let Some(current) = self.current.as_mut() {
+            current.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self, timestamp: u64) -> RecordResult<()> {
+        self.flush()?;
+
+        let mut sequence = 0;
+        while self.path(timestamp, sequence).exists() {
+            sequence += 1;
+        }
+        if self.current.is_none() && sequence > 0 {
+            sequence -= 1;
+        }
+        let path = self.path(timestamp, sequence);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+
+        self.current = Some(CurrentFile {
+            path,
+            started_at: timestamp,
+            written,
+            writer: LineWriter::new(file),
+        });
+
+        Ok(())
+    }
+
+    fn path(&self, timestamp: u64, sequence: u64) -> PathBuf {
+        let name = match sequence {
+            0 => format!("{}-{:013}", self.prefix, timestamp),
+            sequence => format!("{}-{:013}-{}", self.prefix, timestamp, sequence),
+        };
+        self.dir.join(format!("{}.{}", name, RECORD_FILE_EXTENSION))
+    }
+}
+
+// the timestamp and sequence of a file named by `RecordWriter`
+fn record_file_key(name: &str, prefix: &str) -> Option<(u64, u64)> {
+    let stem = name
+        .strip_prefix(prefix)?
+        .strip_prefix('-')?
+        .strip_suffix(RECORD_FILE_EXTENSION)?
+        .strip_suffix('.')?;
+    match stem.split_once('-') {
+        Some((timestamp, sequence)) => Some((timestamp.parse().ok()?, sequence.parse().ok()?)),
+        None => Some((stem.parse().ok()?, 0)),
+    }
+}
+
+/// Lists the files of a recording written by `RecordWriter`, in replay order.
+pub fn list_record_files<P>(dir: P, prefix: &str) -> RecordResult<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir.as_ref())? {
+        let path = entry?.path();
+
+        let key = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| record_file_key(name, prefix));
+        if let Some(key) = key {
+            paths.push((key, path));
+        }
+    }
+    paths.sort();
+
+    Ok(paths.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::implements::records::RecordReader;
+    use crate::types::{Execution, Side, TradeId};
+
+    fn dummy_record(timestamp: u64) -> Record {
+        Record::new(
+            timestamp,
+            vec![Execution::new(
+                timestamp,
+                TradeId::new(timestamp),
+                Side::Bid,
+                dec!(16000),
+                dec!(100),
+            )],
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_record_writer_rotation_interval() {
+        let dir = temp_dir("record-writer-interval");
+        let mut writer = RecordWriter::create(&dir, "xbtusd", Rotation::Interval(1000)).unwrap();
+
+        let records: Vec<_> = [0, 500, 999, 1000, 2500, 2600]
+            .into_iter()
+            .map(dummy_record)
+            .collect();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let paths = list_record_files(&dir, "xbtusd").unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("xbtusd-0000000000000.jsonl"),
+                dir.join("xbtusd-0000000001000.jsonl"),
+                dir.join("xbtusd-0000000002500.jsonl"),
+            ]
+        );
+
+        let read = RecordReader::open_dir(&dir, "xbtusd")
+            .unwrap()
+            .collect::<RecordResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, records);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_writer_rotation_size() {
+        let dir = temp_dir("record-writer-size");
+        let mut writer = RecordWriter::create(&dir, "xbtusd", Rotation::Size(1)).unwrap();
+
+        for timestamp in 0..3 {
+            writer.write(&dummy_record(timestamp)).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(list_record_files(&dir, "xbtusd").unwrap().len(), 3);
+
+        // reopening appends to the existing file
+        let mut writer = RecordWriter::create(&dir, "xbtusd", Rotation::Size(1 << 20)).unwrap();
+        writer.write(&dummy_record(2)).unwrap();
+        writer.flush().unwrap();
+
+        let paths = list_record_files(&dir, "xbtusd").unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(RecordReader::open(&paths[2]).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_writer_rotation_same_millisecond() {
+        let dir = temp_dir("record-writer-same-ms");
+        let mut writer = RecordWriter::create(&dir, "xbtusd", Rotation::Size(1)).unwrap();
+
+        let records: Vec<_> = (0..12).map(|_| dummy_record(5)).collect();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+
+        // in the order they were written, not in the order of their names
+        let paths = list_record_files(&dir, "xbtusd").unwrap();
+        assert_eq!(paths.len(), 12);
+        assert_eq!(paths[0], dir.join("xbtusd-0000000000005.jsonl"));
+        assert_eq!(paths[2], dir.join("xbtusd-0000000000005-2.jsonl"));
+        assert_eq!(paths[11], dir.join("xbtusd-0000000000005-11.jsonl"));
+        for path in &paths {
+            assert_eq!(RecordReader::open(path).unwrap().count(), 1);
+        }
+
+        // a new writer carries on with the latest file
+        let mut writer = RecordWriter::create(&dir, "xbtusd", Rotation::Size(1 << 20)).unwrap();
+        writer.write(&dummy_record(5)).unwrap();
+        assert_eq!(writer.current_path(), Some(paths[11].as_path()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use crate::implements::writers::OrderbookWriteOp;
+use crate::interfaces::ImpactModel;
+use crate::types::{Amount, Offer, Orderbook, Side};
+
+/// The book does not react to our orders, as if the taken liquidity was replenished at once.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoImpact;
+
+impl ImpactModel for NoImpact {
+    fn impact(&mut self, _: &Orderbook, _: Side, _: &[(Offer, Amount)]) -> Vec<OrderbookWriteOp> {
+        vec![]
+    }
+}
+
+/// The liquidity we took is gone until the market data says otherwise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConsumeLiquidity;
+
+impl ImpactModel for ConsumeLiquidity {
+    fn impact(
+        &mut self,
+        orderbook: &Orderbook,
+        side: Side,
+        fills: &[(Offer, Amount)],
+    ) -> Vec<OrderbookWriteOp> {
+        let timestamp = orderbook.timestamp();
+        let maker_side = side.opposite();
+
+        fills
+            .iter()
+            .map(|(offer, amount)| {
+                let left = offer.amount() - amount;
+                if left.is_zero() {
+                    OrderbookWriteOp::delete(timestamp, maker_side, offer.id().clone())
+                } else {
+                    OrderbookWriteOp::update(timestamp, maker_side, offer.id().clone(), None, left)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rust_decimal_macros::dec;
+
+    use crate::types::OfferId;
+
+    #[test]
+    fn test_consume_liquidity() {
+        let a1 = Offer::new(OfferId::new(1), dec!(101), dec!(10));
+        let a2 = Offer::new(OfferId::new(2), dec!(102), dec!(10));
+        let orderbook = Orderbook::new(5, vec![a1.clone(), a2.clone()], vec![]);
+        let fills = vec![(a1, dec!(10)), (a2, dec!(4))];
+
+        assert!(NoImpact.impact(&orderbook, Side::Bid, &fills).is_empty());
+        assert_eq!(
+            ConsumeLiquidity.impact(&orderbook, Side::Bid, &fills),
+            vec![
+                OrderbookWriteOp::delete(5, Side::Ask, OfferId::new(1)),
+                OrderbookWriteOp::update(5, Side::Ask, OfferId::new(2), None, dec!(6)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::Distribution;
+
+use crate::interfaces::LatencyModel;
+use crate::types::Order;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FixedLatency {
+    entry: u64,  // ms
+    cancel: u64, // ms
+}
+
+impl FixedLatency {
+    pub fn new(entry: u64, cancel: u64) -> Self {
+        Self { entry, cancel }
+    }
+}
+
+impl LatencyModel for FixedLatency {
+    fn latency(&mut self, _timestamp: u64, order: &Order) -> u64 {
+        match order {
+            Order::New(_) | Order::Update(_) => self.entry,
+            Order::Cancel(_) => self.cancel,
+        }
+    }
+}
+
+/// Samples latencies in ms from a distribution, reproducibly for a given seed.
+#[derive(Clone, Debug)]
+pub struct DistributionLatency<D> {
+    distribution: D,
+    rng: StdRng,
+}
+
+impl<D> DistributionLatency<D>
+where
+    D: Distribution<f64>,
+{
+    pub fn new(distribution: D, seed: u64) -> Self {
+        Self {
+            distribution,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<D> LatencyModel for DistributionLatency<D>
+where
+    D: Distribution<f64>,
+{
+    fn latency(&mut self, _timestamp: u64, _order: &Order) -> u64 {
+        let sample = self.distribution.sample(&mut self.rng);
+        sample.max(0.0).round() as u64
+    }
+}
+
+/// Replays latencies measured live, cycling when they run out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayedLatency {
+    latencies: Vec<u64>,
+    index: usize,
+}
+
+impl ReplayedLatency {
+    pub fn new(latencies: Vec<u64>) -> Self {
+        Self {
+            latencies,
+            index: 0,
+        }
+    }
+
+    /// From (sent, acknowledged) timestamps of orders, in ms.
+    pub fn from_acks<I>(acks: I) -> Self
+    where
+        I: IntoIterator<Item = (u64, u64)>,
+    {
+        let latencies = acks
+            .into_iter()
+            .map(|(sent, acked)| acked.saturating_sub(sent))
+            .collect();
+        Self::new(latencies)
+    }
+}
+
+impl LatencyModel for ReplayedLatency {
+    fn latency(&mut self, _timestamp: u64, _order: &Order) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        let latency = self.latencies[self.index % self.latencies.len()];
+        self.index += 1;
+        latency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand_distr::Normal;
+    use rust_decimal_macros::dec;
+
+    use crate::types::{OrderId, OrderType, Side};
+
+    #[test]
+    fn test_latency_models() {
+        let new = Order::create(OrderType::Limit, Side::Bid, dec!(100), dec!(1));
+        let cancel = Order::cancel(OrderId::new(1));
+
+        let mut fixed = FixedLatency::new(50, 20);
+        assert_eq!(fixed.latency(0, &new), 50);
+        assert_eq!(fixed.latency(0, &cancel), 20);
+
+        let mut replayed = ReplayedLatency::from_acks(vec![(0, 30), (100, 110)]);
+        let latencies: Vec<u64> = (0..3).map(|_| replayed.latency(0, &new)).collect();
+        assert_eq!(latencies, vec![30, 10, 30]);
+
+        // same seed, same latencies
+        let sample = |seed| {
+            let mut model = DistributionLatency::new(Normal::new(40.0, 10.0).unwrap(), seed);
+            (0..100).map(|_| model.latency(0, &new)).collect::<Vec<_>>()
+        };
+        assert_eq!(sample(7), sample(7));
+        assert_ne!(sample(7), sample(8));
+        let mean = sample(7).iter().sum::<u64>() as f64 / 100.0;
+        assert!((30.0..50.0).contains(&mean));
+    }
+}
@@ -0,0 +1,5 @@
+mod impact;
+mod latency;
+
+pub use impact::*;
+pub use latency::*;
@@ -32,6 +32,11 @@ impl OpenOrdersWriteOp {
         CreateOp::new(timestamp, id, side, price, amount).into()
     }
 
+    /// Creates `order` as is, e.g. along with its client id.
+    pub fn insert(timestamp: u64, order: OrderState) -> Self {
+        CreateOp { timestamp, order }.into()
+    }
+
     pub fn update(
         timestamp: u64,
         id: OrderId,
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::types::{Amount, Offer, OfferId, Orderbook, Price, Side};
@@ -12,7 +13,8 @@ pub enum UpdateOrderbookError {
 
 pub type OrderbookWriterResult<T> = Result<T, UpdateOrderbookError>;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderbookWriteOp {
     Snapshot(Orderbook),
     Create(CreateOp),
@@ -42,6 +44,15 @@ impl OrderbookWriteOp {
     pub fn delete(timestamp: u64, side: Side, id: OfferId) -> Self {
         DeleteOp::new(timestamp, side, id).into()
     }
+
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Self::Snapshot(orderbook) => orderbook.timestamp(),
+            Self::Create(op) => op.timestamp(),
+            Self::Update(op) => op.timestamp(),
+            Self::Delete(op) => op.timestamp(),
+        }
+    }
 }
 
 impl From<Orderbook> for OrderbookWriteOp {
@@ -68,7 +79,7 @@ impl From<DeleteOp> for OrderbookWriteOp {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CreateOp {
     pub timestamp: u64,
     pub side: Side,
@@ -119,7 +130,7 @@ impl CreateOp {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UpdateOp {
     pub timestamp: u64,
     pub side: Side,
@@ -176,7 +187,7 @@ impl UpdateOp {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DeleteOp {
     pub timestamp: u64,
     pub side: Side,
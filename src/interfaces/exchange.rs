@@ -1,20 +1,55 @@
 use async_trait::async_trait;
 
 use crate::pubsub::Subscription;
-use crate::types::{Execution, Inventory, MarketInfo, OpenOrders, Order, OrderResponse, Orderbook};
+use crate::types::{
+    AccountState, ConnectionState, Execution, Fill, Inventory, MarketInfo, OpenOrders, Order,
+    OrderId, OrderResponse, Orderbook, Side, Ticker,
+};
 
 pub trait Market {
     fn info(&self) -> MarketInfo;
     fn orderbook(&self) -> Subscription<Orderbook>;
     fn execution(&self) -> Subscription<Execution>;
+    /// Mark and index prices, funding and open interest, as far as the venue reports them.
+    fn ticker(&self) -> Subscription<Ticker>;
+    fn connection(&self) -> Subscription<ConnectionState>;
+
+    /// Receive time (ms) of the latest orderbook data from the exchange, if tracked.
+    fn watermark(&self) -> Option<u64> {
+        None
+    }
 }
 
 pub trait Status {
     fn inventory(&self) -> Subscription<Inventory>;
     fn open_orders(&self) -> Subscription<OpenOrders>;
+    fn fills(&self) -> Subscription<Fill>;
+    /// Margin and position details of the account, as far as the venue reports them.
+    fn account(&self) -> Subscription<AccountState>;
+    fn connection(&self) -> Subscription<ConnectionState>;
+
+    /// Receive time (ms) of the latest open orders data from the exchange, if tracked.
+    fn watermark(&self) -> Option<u64> {
+        None
+    }
 }
 
 #[async_trait]
 pub trait Broker {
     async fn submit(&self, order: Order) -> OrderResponse;
+
+    /// Submits several orders at once, responding to each in the same order.
+    async fn submit_batch(&self, orders: Vec<Order>) -> Vec<OrderResponse> {
+        futures::future::join_all(orders.into_iter().map(|order| self.submit(order))).await
+    }
+
+    /// Cancels all our open orders, or only those on `side`, returning the canceled ids.
+    async fn cancel_all(&self, side: Option<Side>) -> Vec<OrderId>;
+
+    /// Tells a broker guarding against our death, e.g. with a dead man's switch, that we are
+    /// still alive.
+    fn heartbeat(&self) {}
+
+    /// Stops guarding against our death on a clean exit.
+    async fn disarm(&self) {}
 }
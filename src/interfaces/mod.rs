@@ -1,7 +1,9 @@
 pub mod database;
 pub mod exchange;
+pub mod simulation;
 pub mod strategy;
 
 pub use database::*;
 pub use exchange::*;
+pub use simulation::*;
 pub use strategy::*;
@@ -0,0 +1,18 @@
+use crate::implements::writers::OrderbookWriteOp;
+use crate::types::{Amount, Offer, Order, Orderbook, Side};
+
+pub trait LatencyModel {
+    /// Milliseconds for an order sent at `timestamp` to reach the exchange.
+    fn latency(&mut self, timestamp: u64, order: &Order) -> u64;
+}
+
+pub trait ImpactModel {
+    /// Changes to `orderbook` after our marketable order on `side` took `fills` from it,
+    /// as (offer, amount taken) in the order they were walked.
+    fn impact(
+        &mut self,
+        orderbook: &Orderbook,
+        side: Side,
+        fills: &[(Offer, Amount)],
+    ) -> Vec<OrderbookWriteOp>;
+}
@@ -1,4 +1,7 @@
-use crate::types::{Execution, Inventory, MarketInfo, OpenOrders, Order, Orderbook};
+use crate::types::{
+    AccountState, ConnectionState, Execution, Fill, Inventory, MarketInfo, OpenOrders, Order,
+    Orderbook, Ticker,
+};
 
 pub trait Policy {
     fn evaluate(&self, observation: impl Observation) -> Vec<Order>;
@@ -7,10 +10,24 @@ pub trait Policy {
 pub trait Observation {
     fn info(&self) -> &MarketInfo;
     fn executions(&self) -> &[Execution];
+    /// Executions of our own orders.
+    fn fills(&self) -> &[Fill];
     fn orderbook(&self) -> &Orderbook;
+    /// Mark and index prices and funding, once reported.
+    fn ticker(&self) -> Option<&Ticker>;
     fn inventory(&self) -> &Inventory;
+    /// Margin and position details, once reported.
+    fn account(&self) -> Option<&AccountState>;
     fn open_orders(&self) -> &OpenOrders;
     fn pending_orders(&self) -> &[Order];
+    /// The least healthy of the market and status streams.
+    fn connection(&self) -> ConnectionState;
+    /// Time (ms) since the latest orderbook data, if tracked.
+    fn orderbook_age(&self) -> Option<u64>;
+    /// Time (ms) since the ticker was last updated by the exchange, once reported.
+    fn ticker_age(&self) -> Option<u64>;
+    /// Time (ms) since the status last received open orders data, per its watermark, if tracked.
+    fn open_orders_age(&self) -> Option<u64>;
 }
 
 impl<'a, S> Observation for &'a S
@@ -25,14 +42,26 @@ where
         (*self).executions()
     }
 
+    fn fills(&self) -> &[Fill] {
+        (*self).fills()
+    }
+
     fn orderbook(&self) -> &Orderbook {
         (*self).orderbook()
     }
 
+    fn ticker(&self) -> Option<&Ticker> {
+        (*self).ticker()
+    }
+
     fn inventory(&self) -> &Inventory {
         (*self).inventory()
     }
 
+    fn account(&self) -> Option<&AccountState> {
+        (*self).account()
+    }
+
     fn open_orders(&self) -> &OpenOrders {
         (*self).open_orders()
     }
@@ -40,4 +69,20 @@ where
     fn pending_orders(&self) -> &[Order] {
         (*self).pending_orders()
     }
+
+    fn connection(&self) -> ConnectionState {
+        (*self).connection()
+    }
+
+    fn orderbook_age(&self) -> Option<u64> {
+        (*self).orderbook_age()
+    }
+
+    fn ticker_age(&self) -> Option<u64> {
+        (*self).ticker_age()
+    }
+
+    fn open_orders_age(&self) -> Option<u64> {
+        (*self).open_orders_age()
+    }
 }
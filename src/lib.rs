@@ -1,5 +1,7 @@
 pub mod apikey;
+pub mod backtest;
 pub mod bot;
+pub mod clock;
 pub mod components;
 pub mod implements;
 pub mod interfaces;
@@ -1,16 +1,27 @@
 use crossbeam_channel::{select, Receiver, RecvError};
 
 use crate::interfaces::Observation as ObservationInterface;
-use crate::types::{Execution, Inventory, MarketInfo, OpenOrders, Order, Orderbook};
+use crate::types::{
+    AccountState, ConnectionState, Execution, Fill, Inventory, MarketInfo, OpenOrders, Order,
+    Orderbook, Ticker,
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Observation {
     info: MarketInfo,
     executions: Vec<Execution>,
+    fills: Vec<Fill>,
     orderbook: Orderbook,
+    ticker: Option<Ticker>,
     inventory: Inventory,
+    account: Option<AccountState>,
     open_orders: OpenOrders,
     pending_orders: Vec<Order>,
+    market_connection: ConnectionState,
+    status_connection: ConnectionState,
+    orderbook_age: Option<u64>,
+    ticker_age: Option<u64>,
+    open_orders_age: Option<u64>,
 }
 
 impl Observation {
@@ -25,21 +36,31 @@ impl Observation {
         Self {
             info,
             executions,
+            fills: Vec::new(),
             orderbook,
+            ticker: None,
             inventory,
+            account: None,
             open_orders,
             pending_orders,
+            market_connection: ConnectionState::Synced,
+            status_connection: ConnectionState::Synced,
+            orderbook_age: None,
+            ticker_age: None,
+            open_orders_age: None,
         }
     }
 
     pub fn warmup(
         info: MarketInfo,
         execution_receiver: &Receiver<Execution>,
+        fill_receiver: &Receiver<Fill>,
         orderbook_receiver: &Receiver<Orderbook>,
         inventory_receiver: &Receiver<Inventory>,
         open_orders_receiver: &Receiver<OpenOrders>,
     ) -> Result<Self, RecvError> {
         let mut executions = Vec::new();
+        let mut fills = Vec::new();
         let mut orderbook = None;
         let mut inventory = None;
         let mut open_orders = None;
@@ -49,6 +70,9 @@ impl Observation {
                 recv(execution_receiver) -> msg => {
                     executions.push(msg?);
                 },
+                recv(fill_receiver) -> msg => {
+                    fills.push(msg?);
+                },
                 recv(orderbook_receiver) -> msg => {
                     orderbook = Some(msg?);
                 },
@@ -69,28 +93,42 @@ impl Observation {
         let inventory = inventory.expect("must exists");
         let open_orders = open_orders.expect("must exists");
 
-        Ok(Observation::new(
+        let mut observation = Observation::new(
             info,
             executions,
             orderbook,
             inventory,
             open_orders,
             Vec::new(),
-        ))
+        );
+        observation.fills = fills;
+        Ok(observation)
     }
 
     pub fn insert_execution(&mut self, execution: Execution) {
         self.executions.push(execution);
     }
 
+    pub fn insert_fill(&mut self, fill: Fill) {
+        self.fills.push(fill);
+    }
+
     pub fn update_orderbook(&mut self, orderbook: Orderbook) {
         self.orderbook = orderbook;
     }
 
+    pub fn update_ticker(&mut self, ticker: Ticker) {
+        self.ticker = Some(ticker);
+    }
+
     pub fn update_inventory(&mut self, inventory: Inventory) {
         self.inventory = inventory;
     }
 
+    pub fn update_account(&mut self, account: AccountState) {
+        self.account = Some(account);
+    }
+
     pub fn update_open_orders(&mut self, open_orders: OpenOrders) {
         self.open_orders = open_orders;
     }
@@ -98,6 +136,29 @@ impl Observation {
     pub fn update_pending_orders(&mut self, pending_orders: Vec<Order>) {
         self.pending_orders = pending_orders;
     }
+
+    pub fn update_market_connection(&mut self, state: ConnectionState) {
+        self.market_connection = state;
+    }
+
+    pub fn update_status_connection(&mut self, state: ConnectionState) {
+        self.status_connection = state;
+    }
+
+    /// Ages the data at `now`, given the receive times of the latest orderbook and open orders
+    /// data. The ticker is aged by its own timestamp, as it may stall while the orderbook
+    /// does not.
+    pub fn update_data_age(
+        &mut self,
+        now: u64,
+        orderbook_watermark: Option<u64>,
+        open_orders_watermark: Option<u64>,
+    ) {
+        let age = |timestamp: Option<u64>| timestamp.map(|t| now.saturating_sub(t));
+        self.orderbook_age = age(orderbook_watermark);
+        self.ticker_age = age(self.ticker.as_ref().map(|ticker| ticker.timestamp()));
+        self.open_orders_age = age(open_orders_watermark);
+    }
 }
 
 impl ObservationInterface for Observation {
@@ -109,14 +170,26 @@ impl ObservationInterface for Observation {
         &self.executions
     }
 
+    fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
     fn orderbook(&self) -> &Orderbook {
         &self.orderbook
     }
 
+    fn ticker(&self) -> Option<&Ticker> {
+        self.ticker.as_ref()
+    }
+
     fn inventory(&self) -> &Inventory {
         &self.inventory
     }
 
+    fn account(&self) -> Option<&AccountState> {
+        self.account.as_ref()
+    }
+
     fn open_orders(&self) -> &OpenOrders {
         &self.open_orders
     }
@@ -124,4 +197,48 @@ impl ObservationInterface for Observation {
     fn pending_orders(&self) -> &[Order] {
         &self.pending_orders
     }
+
+    fn connection(&self) -> ConnectionState {
+        self.market_connection.min(self.status_connection)
+    }
+
+    fn orderbook_age(&self) -> Option<u64> {
+        self.orderbook_age
+    }
+
+    fn ticker_age(&self) -> Option<u64> {
+        self.ticker_age
+    }
+
+    fn open_orders_age(&self) -> Option<u64> {
+        self.open_orders_age
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_age() {
+        let mut observation = Observation::new(
+            MarketInfo::for_test(),
+            vec![],
+            Orderbook::new(0, vec![], vec![]),
+            Inventory::Position(Default::default()),
+            OpenOrders::new(0, vec![]),
+            vec![],
+        );
+        observation.update_data_age(10_000, Some(9_900), None);
+        assert_eq!(observation.orderbook_age(), Some(100));
+        assert_eq!(observation.ticker_age(), None);
+        assert_eq!(observation.open_orders_age(), None);
+
+        // a ticker stalled while the orderbook kept coming
+        observation.update_ticker(Ticker::new(4_000));
+        observation.update_data_age(10_000, Some(9_950), Some(9_000));
+        assert_eq!(observation.orderbook_age(), Some(50));
+        assert_eq!(observation.ticker_age(), Some(6_000));
+        assert_eq!(observation.open_orders_age(), Some(1_000));
+    }
 }
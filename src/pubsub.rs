@@ -62,6 +62,11 @@ where
         guard.unsubscribe(id)
     }
 
+    pub fn unsubscribe_all(&self) {
+        let mut guard = self.0.lock().unwrap();
+        guard.unsubscribe_all()
+    }
+
     pub fn publish(&self, message: T) {
         let mut guard = self.0.lock().unwrap();
         guard.publish(message)
@@ -106,6 +111,13 @@ where
         self.subscribers.remove(id);
     }
 
+    fn unsubscribe_all(&mut self) {
+        let ids: Vec<_> = self.subscribers.keys().copied().collect();
+        for id in &ids {
+            self.unsubscribe(id);
+        }
+    }
+
     fn publish(&mut self, message: T) {
         let mut disconnected = Vec::new();
         match self.subscribers.len() {
@@ -234,5 +246,14 @@ mod tests {
         pubsub.publish(7);
 
         assert!(pubsub.0.lock().unwrap().subscribers.is_empty());
+
+        let sub3 = pubsub.subscribe();
+        let sub4 = pubsub.subscribe();
+
+        pubsub.unsubscribe_all();
+
+        assert!(sub3.disconnected());
+        assert!(sub4.disconnected());
+        assert!(pubsub.0.lock().unwrap().subscribers.is_empty());
     }
 }
@@ -30,6 +30,24 @@ impl DepthBasedOffering {
 
 impl Policy for DepthBasedOffering {
     fn evaluate(&self, observation: impl Observation) -> Vec<Order> {
+        // the book cannot be trusted, so pull our quotes until it resyncs
+        if observation.connection().is_stale() {
+            let canceling: Vec<&OrderId> = observation
+                .pending_orders()
+                .iter()
+                .filter_map(|order| match order {
+                    Order::Cancel(cancel_order) => Some(cancel_order.id()),
+                    _ => None,
+                })
+                .collect();
+            return observation
+                .open_orders()
+                .orders()
+                .filter(|order| !canceling.contains(&order.id()))
+                .map(|order| order.to_cancel_order().into())
+                .collect();
+        }
+
         if !observation.pending_orders().is_empty() {
             return Vec::new();
         }
@@ -61,44 +79,66 @@ impl Policy for DepthBasedOffering {
         let new_ask_size = self.max_exposure() + position;
         let new_bid_size = self.max_exposure() - position;
 
-        let mut ask_remaining: Amount = new_ask_size;
-        for order in observation.open_orders().asks() {
-            if order.price() == new_ask_price && order.amount() <= ask_remaining {
-                ask_remaining -= order.amount();
-            } else {
-                orders.push(order.to_cancel_order().into());
-            }
-        }
+        orders.extend(requote(
+            observation.open_orders().asks(),
+            Side::Ask,
+            new_ask_price,
+            new_ask_size,
+            info.min_order_size(),
+        ));
+        orders.extend(requote(
+            observation.open_orders().bids(),
+            Side::Bid,
+            new_bid_price,
+            new_bid_size,
+            info.min_order_size(),
+        ));
 
-        if ask_remaining >= info.min_order_size() {
-            orders.push(Order::create(
-                OrderType::Limit,
-                Side::Ask,
-                new_ask_price,
-                ask_remaining,
-            ));
-        }
+        orders
+    }
+}
 
-        let mut bid_remaining: Amount = new_bid_size;
-        for order in observation.open_orders().bids() {
-            if order.price() == new_bid_price && order.amount() <= bid_remaining {
-                bid_remaining -= order.amount();
-            } else {
-                orders.push(order.to_cancel_order().into());
-            }
+/// Keeps our orders at `price` which fit into `size` and moves one of the others there with
+/// an amend, preferring one already at `price`, so only the rest has to be canceled.
+fn requote<'a>(
+    open_orders: impl Iterator<Item = &'a OrderState>,
+    side: Side,
+    price: Price,
+    size: Amount,
+    min_order_size: Amount,
+) -> Vec<Order> {
+    let mut remaining = size;
+    let mut stale = Vec::new();
+    for order in open_orders {
+        if order.price() == price && order.amount() <= remaining {
+            remaining -= order.amount();
+        } else {
+            stale.push(order);
         }
+    }
 
-        if bid_remaining >= info.min_order_size() {
-            orders.push(Order::create(
-                OrderType::Limit,
-                Side::Bid,
-                new_bid_price,
-                bid_remaining,
-            ));
-        }
+    let amended = if remaining >= min_order_size && !stale.is_empty() {
+        Some(stale.iter().position(|o| o.price() == price).unwrap_or(0))
+    } else {
+        None
+    };
+
+    let mut orders: Vec<Order> = stale
+        .iter()
+        .enumerate()
+        .map(|(i, order)| match amended {
+            Some(amended) if amended == i => {
+                let new_order = NewOrder::new(OrderType::Limit, side, price, remaining);
+                order.to_update_order(new_order).into()
+            }
+            _ => order.to_cancel_order().into(),
+        })
+        .collect();
 
-        orders
+    if amended.is_none() && remaining >= min_order_size {
+        orders.push(Order::create(OrderType::Limit, side, price, remaining));
     }
+    orders
 }
 
 struct RemainingOrders {
@@ -159,24 +199,13 @@ mod tests {
 
     use crate::observation::Observation;
 
-    fn dummy_info() -> MarketInfo {
-        MarketInfo {
-            max_order_size: dec!(10000000),
-            min_order_size: dec!(100),
-            lot_size: dec!(100),
-            max_order_price: dec!(1000000),
-            min_order_price: dec!(1),
-            tick_size: dec!(0.5),
-        }
-    }
-
     fn dummy_observation() -> Observation {
         dummy_observation_with(dec!(0), vec![])
     }
 
     fn dummy_observation_with(position: Price, orders: Vec<OrderState>) -> Observation {
         Observation::new(
-            dummy_info(),
+            MarketInfo::for_test(),
             vec![],
             Orderbook::new(
                 0,
@@ -344,17 +373,46 @@ mod tests {
         assert_eq!(
             policy.evaluate(&observation),
             vec![
-                Order::cancel(OrderId::new(159995)),
-                Order::create(OrderType::Limit, Side::Ask, dec!(15999.5), dec!(500)),
-                Order::cancel(OrderId::new(140005)),
-                Order::create(OrderType::Limit, Side::Bid, dec!(14000.5), dec!(500)),
+                Order::update(
+                    OrderId::new(159995),
+                    NewOrder::new(OrderType::Limit, Side::Ask, dec!(15999.5), dec!(500))
+                ),
+                Order::update(
+                    OrderId::new(140005),
+                    NewOrder::new(OrderType::Limit, Side::Bid, dec!(14000.5), dec!(500))
+                ),
+            ],
+        );
+
+        // moved, keeping the order at the new price
+        let observation = dummy_observation_with(
+            dec!(0),
+            vec![
+                OrderState::new(OrderId::new(165000), Side::Ask, dec!(16500), dec!(200)),
+                OrderState::new(OrderId::new(159995), Side::Ask, dec!(15999.5), dec!(200)),
+                OrderState::new(OrderId::new(135000), Side::Bid, dec!(13500), dec!(200)),
+                OrderState::new(OrderId::new(136000), Side::Bid, dec!(13600), dec!(200)),
+            ],
+        );
+        assert_eq!(
+            policy.evaluate(&observation),
+            vec![
+                Order::update(
+                    OrderId::new(165000),
+                    NewOrder::new(OrderType::Limit, Side::Ask, dec!(15999.5), dec!(300))
+                ),
+                Order::update(
+                    OrderId::new(135000),
+                    NewOrder::new(OrderType::Limit, Side::Bid, dec!(14000.5), dec!(500))
+                ),
+                Order::cancel(OrderId::new(136000)),
             ],
         );
 
         // already placed (ignoring our open orders)
         let policy = DepthBasedOffering::new(dec!(1000), dec!(1000));
         let observation = Observation::new(
-            dummy_info(),
+            MarketInfo::for_test(),
             vec![],
             Orderbook::new(
                 0,
@@ -381,4 +439,37 @@ mod tests {
         );
         assert_eq!(policy.evaluate(&observation), vec![]);
     }
+
+    #[test]
+    fn test_dbo_stale() {
+        let policy = DepthBasedOffering::new(dec!(500), dec!(1000));
+
+        let mut observation = dummy_observation_with(
+            dec!(0),
+            vec![
+                OrderState::new(OrderId::new(159995), Side::Ask, dec!(15999.5), dec!(500)),
+                OrderState::new(OrderId::new(140005), Side::Bid, dec!(14000.5), dec!(500)),
+            ],
+        );
+        observation.update_market_connection(ConnectionState::Resyncing);
+        assert_eq!(
+            policy.evaluate(&observation),
+            vec![
+                Order::cancel(OrderId::new(159995)),
+                Order::cancel(OrderId::new(140005)),
+            ],
+        );
+
+        // no duplicated cancels
+        observation.update_pending_orders(vec![Order::cancel(OrderId::new(159995))]);
+        assert_eq!(
+            policy.evaluate(&observation),
+            vec![Order::cancel(OrderId::new(140005))],
+        );
+
+        // quotes again once synced
+        observation.update_market_connection(ConnectionState::Synced);
+        observation.update_pending_orders(vec![]);
+        assert_eq!(policy.evaluate(&observation), vec![]);
+    }
 }
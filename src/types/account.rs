@@ -0,0 +1,71 @@
+use rust_decimal::prelude::*;
+
+use super::values::{Amount, Price};
+
+/// Margin and position details of a derivatives account. Balances and PnL are in the
+/// settlement currency as the exchange reports them, e.g. satoshis for XBt, and are `None`
+/// while unknown.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    pub timestamp: u64,
+    pub currency: String,
+    pub position: Amount,
+    pub wallet_balance: Option<Amount>,
+    pub margin_balance: Option<Amount>,
+    pub available_margin: Option<Amount>,
+    pub leverage: Option<Decimal>, // zero on cross margin
+    pub entry_price: Option<Price>,
+    pub liquidation_price: Option<Price>,
+    pub unrealised_pnl: Option<Amount>,
+}
+
+impl AccountState {
+    pub fn new(timestamp: u64, currency: impl ToString, position: Amount) -> Self {
+        Self {
+            timestamp,
+            currency: currency.to_string(),
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn position(&self) -> Amount {
+        self.position
+    }
+
+    pub fn wallet_balance(&self) -> Option<Amount> {
+        self.wallet_balance
+    }
+
+    pub fn margin_balance(&self) -> Option<Amount> {
+        self.margin_balance
+    }
+
+    pub fn available_margin(&self) -> Option<Amount> {
+        self.available_margin
+    }
+
+    pub fn leverage(&self) -> Option<Decimal> {
+        self.leverage
+    }
+
+    pub fn entry_price(&self) -> Option<Price> {
+        self.entry_price
+    }
+
+    pub fn liquidation_price(&self) -> Option<Price> {
+        self.liquidation_price
+    }
+
+    pub fn unrealised_pnl(&self) -> Option<Amount> {
+        self.unrealised_pnl
+    }
+}
@@ -0,0 +1,15 @@
+/// Health of a data stream, ordered from least to most trustworthy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionState {
+    /// Not connected, waiting to reconnect.
+    Disconnected,
+    /// Connected, but the data is stale until a fresh snapshot arrives.
+    Resyncing,
+    Synced,
+}
+
+impl ConnectionState {
+    pub fn is_stale(&self) -> bool {
+        *self != Self::Synced
+    }
+}
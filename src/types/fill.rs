@@ -0,0 +1,93 @@
+use super::order::{ClientOrderId, OrderId, Side};
+use super::values::{Amount, Price};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+impl Liquidity {
+    pub fn is_maker(&self) -> bool {
+        matches!(self, Self::Maker)
+    }
+
+    pub fn is_taker(&self) -> bool {
+        matches!(self, Self::Taker)
+    }
+}
+
+/// One execution of our own order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fill {
+    timestamp: u64,
+    order_id: OrderId,
+    client_id: Option<ClientOrderId>,
+    side: Side,
+    price: Price,
+    amount: Amount,
+    fee: Amount, // negative on rebates
+    liquidity: Liquidity,
+}
+
+impl Fill {
+    pub fn new(
+        timestamp: u64,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        amount: Amount,
+        fee: Amount,
+        liquidity: Liquidity,
+    ) -> Self {
+        Self {
+            timestamp,
+            order_id,
+            client_id: None,
+            side,
+            price,
+            amount,
+            fee,
+            liquidity,
+        }
+    }
+
+    pub fn with_client_id(self, client_id: impl Into<Option<ClientOrderId>>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            ..self
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn order_id(&self) -> &OrderId {
+        &self.order_id
+    }
+
+    pub fn client_id(&self) -> Option<&ClientOrderId> {
+        self.client_id.as_ref()
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    pub fn price(&self) -> Price {
+        self.price
+    }
+
+    pub fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    pub fn fee(&self) -> Amount {
+        self.fee
+    }
+
+    pub fn liquidity(&self) -> Liquidity {
+        self.liquidity
+    }
+}
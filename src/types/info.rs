@@ -1,5 +1,6 @@
 use rust_decimal::prelude::*;
 
+use super::order::{NewOrder, OrderType, RejectReason};
 use super::values::{Amount, Price};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -10,6 +11,8 @@ pub struct MarketInfo {
     pub max_order_price: Price,
     pub min_order_price: Price,
     pub tick_size: Decimal,
+    pub multiplier: Decimal, // negative for inverse contracts
+    pub settlement_currency: String,
 }
 
 impl MarketInfo {
@@ -36,4 +39,74 @@ impl MarketInfo {
     pub fn tick_size(&self) -> Decimal {
         self.tick_size
     }
+
+    pub fn multiplier(&self) -> Decimal {
+        self.multiplier
+    }
+
+    pub fn settlement_currency(&self) -> &str {
+        &self.settlement_currency
+    }
+
+    /// Value of one long contract at the price, in the settlement currency.
+    ///
+    /// Linear contracts are worth `multiplier * price`, inverse ones `multiplier / price`, which
+    /// is negative but still grows with the price, so the PnL of a contract is the difference.
+    pub fn contract_value(&self, price: Price) -> Decimal {
+        if self.multiplier.is_sign_negative() {
+            self.multiplier / price
+        } else {
+            self.multiplier * price
+        }
+    }
+
+    /// Notional of the amount at the price in the settlement currency, which fees are rated on.
+    pub fn notional(&self, price: Price, amount: Amount) -> Decimal {
+        self.contract_value(price).abs() * amount
+    }
+
+    /// XBTUSD, as the tests trade it.
+    #[cfg(test)]
+    pub(crate) fn for_test() -> Self {
+        use rust_decimal_macros::dec;
+
+        Self {
+            max_order_size: dec!(10000000),
+            min_order_size: dec!(100),
+            lot_size: dec!(100),
+            max_order_price: dec!(1000000),
+            min_order_price: dec!(1),
+            tick_size: dec!(0.5),
+            multiplier: dec!(-100000000),
+            settlement_currency: "XBt".to_string(),
+        }
+    }
+
+    /// Whether the order size and, for limit orders, the price are within bounds and on grid.
+    pub fn validate(&self, order: &NewOrder) -> bool {
+        self.check(order).is_ok()
+    }
+
+    /// Like `validate`, telling which of the size and price is invalid.
+    pub fn check(&self, order: &NewOrder) -> Result<(), RejectReason> {
+        let amount = order.amount();
+        if amount < self.min_order_size
+            || amount > self.max_order_size
+            || !(amount % self.lot_size).is_zero()
+        {
+            return Err(RejectReason::InvalidSize);
+        }
+
+        if order.order_type() == OrderType::Limit {
+            let price = order.price();
+            if price < self.min_order_price
+                || price > self.max_order_price
+                || !(price % self.tick_size).is_zero()
+            {
+                return Err(RejectReason::InvalidPrice);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -1,13 +1,21 @@
+pub mod account;
+pub mod connection;
 pub mod execution;
+pub mod fill;
 pub mod info;
 pub mod inventory;
 pub mod order;
 pub mod orderbook;
+pub mod ticker;
 pub mod values;
 
+pub use account::*;
+pub use connection::*;
 pub use execution::*;
+pub use fill::*;
 pub use info::*;
 pub use inventory::*;
 pub use order::*;
 pub use orderbook::*;
+pub use ticker::*;
 pub use values::*;
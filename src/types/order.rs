@@ -1,5 +1,6 @@
 use chrono::{TimeZone, Utc};
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use super::values::{Amount, Price};
@@ -19,7 +20,23 @@ impl fmt::Display for OrderId {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Our own id of an order, sent along with it so the exchange's updates can be matched.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClientOrderId(String);
+
+impl ClientOrderId {
+    pub fn new(id: impl ToString) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl fmt::Display for ClientOrderId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Side {
     Ask,
     Bid,
@@ -51,6 +68,7 @@ pub enum OrderType {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Order {
     New(NewOrder),
+    Update(UpdateOrder),
     Cancel(CancelOrder),
 }
 
@@ -59,6 +77,11 @@ impl Order {
         NewOrder::new(order_type, order_side, price, amount).into()
     }
 
+    /// Amends the price and remaining amount of a resting order.
+    pub fn update(id: OrderId, new_order: NewOrder) -> Self {
+        UpdateOrder::new(id, new_order).into()
+    }
+
     pub fn cancel(id: OrderId) -> Self {
         CancelOrder::new(id).into()
     }
@@ -70,6 +93,12 @@ impl From<NewOrder> for Order {
     }
 }
 
+impl From<UpdateOrder> for Order {
+    fn from(order: UpdateOrder) -> Self {
+        Self::Update(order)
+    }
+}
+
 impl From<CancelOrder> for Order {
     fn from(order: CancelOrder) -> Self {
         Self::Cancel(order)
@@ -82,6 +111,7 @@ pub struct NewOrder {
     order_side: Side,
     price: Price,
     amount: Amount,
+    client_id: Option<ClientOrderId>,
 }
 
 impl NewOrder {
@@ -91,6 +121,14 @@ impl NewOrder {
             order_side,
             price,
             amount,
+            client_id: None,
+        }
+    }
+
+    pub fn with_client_id(self, client_id: impl Into<Option<ClientOrderId>>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            ..self
         }
     }
 
@@ -109,6 +147,10 @@ impl NewOrder {
     pub fn amount(&self) -> Amount {
         self.amount
     }
+
+    pub fn client_id(&self) -> Option<&ClientOrderId> {
+        self.client_id.as_ref()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -125,6 +167,26 @@ impl UpdateOrder {
     pub fn id(&self) -> &OrderId {
         &self.id
     }
+
+    pub fn new_order(&self) -> &NewOrder {
+        &self.new_order
+    }
+
+    /// Only the price and amount of a resting limit order can be amended.
+    pub fn check(&self, current: &OrderState) -> Result<(), RejectReason> {
+        if self.new_order.order_type() != OrderType::Limit
+            || self.new_order.order_side() != current.side()
+        {
+            let message = "only the price and amount of a limit order can be amended";
+            return Err(RejectReason::Other(message.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Queue priority survives an amend only when the amount is reduced at the same price.
+    pub fn keeps_priority(&self, current: &OrderState) -> bool {
+        self.new_order.price() == current.price() && self.new_order.amount() <= current.amount()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -190,6 +252,7 @@ pub struct OrderState {
     pub(crate) side: Side,
     pub(crate) price: Price,
     pub(crate) amount: Amount,
+    pub(crate) client_id: Option<ClientOrderId>,
 }
 
 impl OrderState {
@@ -199,6 +262,14 @@ impl OrderState {
             side,
             price,
             amount,
+            client_id: None,
+        }
+    }
+
+    pub fn with_client_id(self, client_id: impl Into<Option<ClientOrderId>>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            ..self
         }
     }
 
@@ -218,6 +289,10 @@ impl OrderState {
         self.amount
     }
 
+    pub fn client_id(&self) -> Option<&ClientOrderId> {
+        self.client_id.as_ref()
+    }
+
     pub fn to_update_order(&self, new_order: NewOrder) -> UpdateOrder {
         UpdateOrder::new(self.id.clone(), new_order)
     }
@@ -229,8 +304,54 @@ impl OrderState {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum OrderResponse {
-    Accept(OrderId),
-    Reject,
+    /// The exchange's id of the order, along with ours when it was given one
+    Accept(OrderId, Option<ClientOrderId>),
+    Reject(RejectReason),
+}
+
+/// Why an order was not accepted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    InsufficientMargin,
+    /// A post-only order would have taken liquidity
+    WouldCross,
+    RateLimited,
+    Overloaded,
+    InvalidPrice,
+    InvalidSize,
+    /// The order to amend or cancel is not open
+    OrderNotFound,
+    /// The request failed in transit, so the order may or may not have reached the exchange
+    Network(String),
+    Other(String),
+}
+
+impl RejectReason {
+    /// Whether the exchange asks us to slow down.
+    pub fn is_throttled(&self) -> bool {
+        matches!(self, Self::RateLimited | Self::Overloaded)
+    }
+
+    /// Whether the order may still have been placed.
+    pub fn is_outcome_unknown(&self) -> bool {
+        matches!(self, Self::Network(_))
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InsufficientMargin => write!(f, "insufficient margin"),
+            Self::WouldCross => write!(f, "post-only order would cross"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Overloaded => write!(f, "exchange overloaded"),
+            Self::InvalidPrice => write!(f, "invalid price"),
+            Self::InvalidSize => write!(f, "invalid size"),
+            Self::OrderNotFound => write!(f, "order not found"),
+            Self::Network(message) => write!(f, "network error, outcome unknown: {message}"),
+            Self::Other(message) => write!(f, "{message}"),
+        }
+    }
 }
 
 impl fmt::Display for OpenOrders {
@@ -1,16 +1,23 @@
 use chrono::{TimeZone, Utc};
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use super::order::OrderId;
 use super::values::{Amount, Price};
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct OfferId(String);
 
 impl OfferId {
     pub fn new(id: impl ToString) -> Self {
         Self(id.to_string())
     }
+
+    /// Our orders rest in a simulated book under ids no feed offer can have.
+    pub(crate) fn own(id: &OrderId) -> Self {
+        Self(format!("own-{id}"))
+    }
 }
 
 impl fmt::Display for OfferId {
@@ -19,7 +26,7 @@ impl fmt::Display for OfferId {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Offer {
     pub(crate) id: OfferId,
     pub(crate) price: Price,
@@ -49,7 +56,7 @@ impl Offer {
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Orderbook {
     pub(crate) timestamp: u64, // ms
     pub(crate) asks: Vec<Offer>,
@@ -57,6 +64,22 @@ pub struct Orderbook {
 }
 
 impl Orderbook {
+    /// Two levels a side around 15000, as the simulation tests trade it.
+    #[cfg(test)]
+    pub(crate) fn for_test() -> Self {
+        Self::new(
+            0,
+            vec![
+                Offer::new(OfferId::new(160000), dec!(16000.0), dec!(1000)),
+                Offer::new(OfferId::new(170000), dec!(17000.0), dec!(1000)),
+            ],
+            vec![
+                Offer::new(OfferId::new(140000), dec!(14000.0), dec!(1000)),
+                Offer::new(OfferId::new(130000), dec!(13000.0), dec!(1000)),
+            ],
+        )
+    }
+
     pub fn new<A, B>(timestamp: u64, asks: A, bids: B) -> Self
     where
         A: IntoIterator<Item = Offer>,
@@ -0,0 +1,58 @@
+use rust_decimal::prelude::*;
+
+use super::values::{Amount, Price};
+
+/// Reference prices and funding of a derivative, each `None` while unknown or where the
+/// venue has no such thing. Funding rates are per funding interval.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ticker {
+    pub timestamp: u64,
+    pub mark_price: Option<Price>,
+    pub index_price: Option<Price>,
+    pub funding_rate: Option<Decimal>, // of the next funding
+    pub next_funding_time: Option<u64>,
+    pub last_funding_rate: Option<Decimal>,
+    pub open_interest: Option<Amount>,
+}
+
+impl Ticker {
+    pub fn new(timestamp: u64) -> Self {
+        Self {
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn mark_price(&self) -> Option<Price> {
+        self.mark_price
+    }
+
+    pub fn index_price(&self) -> Option<Price> {
+        self.index_price
+    }
+
+    /// Mark price over the index price, e.g. the premium a perpetual trades at.
+    pub fn basis(&self) -> Option<Price> {
+        Some(self.mark_price? - self.index_price?)
+    }
+
+    pub fn funding_rate(&self) -> Option<Decimal> {
+        self.funding_rate
+    }
+
+    pub fn next_funding_time(&self) -> Option<u64> {
+        self.next_funding_time
+    }
+
+    pub fn last_funding_rate(&self) -> Option<Decimal> {
+        self.last_funding_rate
+    }
+
+    pub fn open_interest(&self) -> Option<Amount> {
+        self.open_interest
+    }
+}